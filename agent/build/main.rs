@@ -9,10 +9,12 @@ fn main() {
     let protocol_dir = workspace_root.join("proto");
     cargo_emit::rerun_if_changed!(protocol_dir.display());
 
-    // Only build the server
+    // Only build the server, the descriptor set is served by the reflection service
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
     tonic_build::configure()
         .build_client(false)
         .build_server(true)
+        .file_descriptor_set_path(out_dir.join("dragon_claw_descriptor.bin"))
         .compile(&[protocol_dir.join("service.proto")], &[protocol_dir])
         .unwrap();
 
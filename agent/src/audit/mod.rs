@@ -0,0 +1,203 @@
+use crate::config::AuditConfig;
+use crate::pal::power::PowerAction;
+use crate::storage::StorageError;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+const AUDIT_LOG_FILE_NAME: &str = "audit-log";
+const AUDIT_LOG_FILE_EXTENSION: &str = "jsonl";
+
+/// A call to perform, confirm or schedule a power action, or a scheduled action becoming due,
+/// as recorded in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Time of the call, in seconds since the unix epoch.
+    pub timestamp: u64,
+
+    /// Address of the client which made the call.
+    pub peer_address: Option<SocketAddr>,
+
+    /// Identifier of the paired device, not set if the client was not authenticated.
+    pub device_id: Option<String>,
+
+    /// Human readable name of the paired device.
+    pub device_name: Option<String>,
+
+    /// The requested power action, not set if the client sent an invalid action.
+    pub action: Option<PowerAction>,
+
+    /// Identifier of the scheduled action the record belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled_id: Option<String>,
+
+    /// What became of the call.
+    #[serde(flatten)]
+    pub result: AuditResult,
+}
+
+/// Outcome of an audited call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum AuditResult {
+    /// The action has been accepted for execution
+    Accepted { action_id: String },
+
+    /// A challenge has been issued, the action only runs once it is confirmed
+    ConfirmationRequired { expires_at: u64 },
+
+    /// The action has been scheduled for the given time
+    Scheduled { at: u64 },
+
+    /// The scheduled action is due and performed by the platform itself
+    Delegated,
+
+    /// The call has been rejected with the given gRPC status
+    Rejected { code: i32, message: String },
+}
+
+/// Selects records of the audit log.
+#[derive(Debug, Clone)]
+pub struct AuditFilter {
+    /// Only records at or after this time, in seconds since the unix epoch.
+    pub since: Option<u64>,
+
+    /// Only records before this time, in seconds since the unix epoch.
+    pub until: Option<u64>,
+
+    /// Only records of these actions, all records if empty.
+    pub actions: Vec<PowerAction>,
+
+    /// Maximum number of records, the most recent ones are kept.
+    pub limit: usize,
+}
+
+impl AuditFilter {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp < until)
+            && (self.actions.is_empty() || record.action.is_some_and(|a| self.actions.contains(&a)))
+    }
+}
+
+/// Append-only log of power action calls, stored as JSON lines in the data directory.
+///
+/// Once the log exceeds its configured size it is rotated, keeping a limited number of old
+/// files around.
+#[derive(Debug)]
+pub struct AuditLog {
+    directory: PathBuf,
+    max_file_size: u64,
+    max_rotated_files: u32,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(data_directory: &Path, config: &AuditConfig) -> Self {
+        Self {
+            directory: data_directory.to_path_buf(),
+            max_file_size: config.max_file_size,
+            max_rotated_files: config.max_rotated_files,
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Appends a record to the log.
+    pub async fn record(&self, record: &AuditRecord) -> Result<(), StorageError> {
+        let mut line =
+            serde_json::to_vec(record).map_err(|err| StorageError::Json(self.file_path(0), err))?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        tokio::fs::create_dir_all(&self.directory).await?;
+
+        let path = self.file_path(0);
+        let size = match tokio::fs::metadata(&path).await {
+            Ok(v) => v.len(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+
+        if size > 0 && size + line.len() as u64 > self.max_file_size {
+            self.rotate().await?;
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+
+    /// Reads the records matching the given filter, oldest first.
+    pub async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditRecord>, StorageError> {
+        // Don't let a rotation move the files away while reading them
+        let _guard = self.lock.lock().await;
+
+        let mut records = Vec::new();
+        for index in (0..=self.max_rotated_files).rev() {
+            let path = self.file_path(index);
+            let data = match tokio::fs::read_to_string(&path).await {
+                Ok(v) => v,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            for line in data.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<AuditRecord>(line) {
+                    Ok(record) if filter.matches(&record) => records.push(record),
+                    Ok(_) => {}
+                    // Such as a line cut short by a crash, the rest of the log is still useful
+                    Err(err) => tracing::warn!(
+                        "Skipping invalid audit record in {}: {}",
+                        path.display(),
+                        err
+                    ),
+                }
+            }
+        }
+
+        let excess = records.len().saturating_sub(filter.limit);
+        records.drain(..excess);
+
+        Ok(records)
+    }
+
+    /// Shifts every file to the next index, dropping the oldest one.
+    async fn rotate(&self) -> Result<(), StorageError> {
+        for index in (0..=self.max_rotated_files).rev() {
+            let from = self.file_path(index);
+            let res = if index == self.max_rotated_files {
+                tokio::fs::remove_file(&from).await
+            } else {
+                tokio::fs::rename(&from, self.file_path(index + 1)).await
+            };
+
+            match res {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+
+        tracing::debug!("Rotated audit log");
+        Ok(())
+    }
+
+    /// Path of the current log for index 0, rotated logs have increasing indices with age.
+    fn file_path(&self, index: u32) -> PathBuf {
+        let name = match index {
+            0 => format!("{}.{}", AUDIT_LOG_FILE_NAME, AUDIT_LOG_FILE_EXTENSION),
+            _ => format!(
+                "{}.{}.{}",
+                AUDIT_LOG_FILE_NAME, index, AUDIT_LOG_FILE_EXTENSION
+            ),
+        };
+
+        self.directory.join(name)
+    }
+}
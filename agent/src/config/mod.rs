@@ -0,0 +1,625 @@
+use crate::pal::power::PowerAction;
+use crate::policy::Subnet;
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Environment variable which can be used to point the agent to a configuration file.
+pub const CONFIG_ENV_VAR: &str = "DRAGON_CLAW_AGENT_CONFIG";
+
+/// Command line flag which can be used to point the agent to a configuration file.
+pub const CONFIG_FLAG: &str = "--config";
+
+/// The default port the RPC server listens on.
+const DEFAULT_PORT: u16 = 37121;
+
+/// The default DNS-SD service type.
+const DEFAULT_SERVICE_TYPE: &str = "_dragon-claw._tcp";
+
+/// The default lifetime of SSDP announcements.
+const DEFAULT_SSDP_MAX_AGE: u32 = 1800;
+
+/// The default number of seconds after which custom actions are killed.
+const DEFAULT_CUSTOM_ACTION_TIMEOUT: u64 = 60;
+
+/// The default number of seconds power actions can be confirmed within.
+const DEFAULT_CONFIRMATION_WINDOW: u64 = 30;
+
+/// The default size in bytes after which the audit log is rotated.
+const DEFAULT_AUDIT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// The default number of rotated audit logs which are kept.
+const DEFAULT_AUDIT_MAX_ROTATED_FILES: u32 = 4;
+
+/// Agent configuration, every value falls back to its default if not set.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listener: ListenerConfig,
+    pub discovery: DiscoveryConfig,
+    pub power: PowerConfig,
+    pub power_policy: PowerPolicyConfig,
+    pub pairing: PairingConfig,
+    pub custom_actions: Vec<CustomActionConfig>,
+    pub metrics: MetricsConfig,
+    pub audit: AuditConfig,
+    pub mock: MockConfig,
+}
+
+/// Settings of the RPC listener.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// The address to bind to, `::` listens on both IPv4 and IPv6.
+    pub address: IpAddr,
+
+    /// The port to bind to, 0 picks a random port.
+    pub port: u16,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            port: DEFAULT_PORT,
+        }
+    }
+}
+
+/// Mechanisms the service can be advertised with.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscoveryBackend {
+    /// DNS-SD over mDNS, using Avahi on Linux and the DNS API on Windows
+    Mdns,
+
+    /// SSDP multicast announcements
+    Ssdp,
+}
+
+/// Settings of the service advertisement.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoveryConfig {
+    /// The enabled discovery backends.
+    pub backends: Vec<DiscoveryBackend>,
+
+    /// The DNS-SD service type, such as `_dragon-claw._tcp`.
+    pub service_type: String,
+
+    /// Number of seconds SSDP announcements stay valid, they are repeated at random intervals
+    /// below half of it.
+    pub ssdp_max_age: u32,
+
+    /// Port the UPnP device description SSDP announcements point to is served on, a random port
+    /// is chosen if 0.
+    pub ssdp_description_port: u16,
+}
+
+#[cfg_attr(feature = "mock-platform", allow(dead_code))] // The mock platform announces nothing
+impl DiscoveryConfig {
+    /// Tests whether the given backend is enabled.
+    pub fn is_enabled(&self, backend: DiscoveryBackend) -> bool {
+        self.backends.contains(&backend)
+    }
+
+    /// Retrieves the lifetime of SSDP announcements.
+    pub fn ssdp_max_age(&self) -> Duration {
+        Duration::from_secs(self.ssdp_max_age as u64)
+    }
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            backends: vec![DiscoveryBackend::Mdns, DiscoveryBackend::Ssdp],
+            service_type: DEFAULT_SERVICE_TYPE.to_string(),
+            ssdp_max_age: DEFAULT_SSDP_MAX_AGE,
+            ssdp_description_port: 0,
+        }
+    }
+}
+
+/// Settings of power actions.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    /// Number of seconds to wait before executing an accepted power action.
+    pub delay_seconds: u64,
+
+    /// The enabled power actions, all supported actions if not set.
+    pub actions: Option<Vec<PowerAction>>,
+
+    /// The sessions affected by session actions such as lock and log out.
+    pub sessions: SessionTarget,
+
+    /// Power actions which only run once the client has confirmed them in a second call.
+    pub confirm_actions: Vec<PowerAction>,
+
+    /// Number of seconds a power action can be confirmed within.
+    pub confirmation_window_seconds: u64,
+}
+
+/// Selects the graphical sessions affected by session actions, all sessions if nothing is set.
+///
+/// Only used on Linux.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionTarget {
+    /// Only sessions on this seat, such as `seat0`.
+    pub seat: Option<String>,
+
+    /// Only sessions of this user name.
+    pub user: Option<String>,
+}
+
+impl PowerConfig {
+    /// Retrieves the delay before executing an accepted power action.
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs(self.delay_seconds)
+    }
+
+    /// Retrieves the time a power action can be confirmed within.
+    pub fn confirmation_window(&self) -> Duration {
+        Duration::from_secs(self.confirmation_window_seconds)
+    }
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            delay_seconds: 1,
+            actions: None,
+            sessions: SessionTarget::default(),
+            confirm_actions: Vec::new(),
+            confirmation_window_seconds: DEFAULT_CONFIRMATION_WINDOW,
+        }
+    }
+}
+
+/// Restrictions of the power actions clients may perform.
+///
+/// The first rule matching a client decides, clients no rule matches fall back to the default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerPolicyConfig {
+    /// Power actions allowed for clients no rule matches, all actions if not set.
+    pub default: Option<Vec<PowerAction>>,
+
+    #[serde(rename = "rule")]
+    pub rules: Vec<PolicyRuleConfig>,
+}
+
+/// A rule granting a set of power actions to matching clients.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRuleConfig {
+    /// Identifiers of paired devices this rule applies to.
+    #[serde(default)]
+    pub devices: Vec<String>,
+
+    /// Subnets of client addresses this rule applies to.
+    #[serde(default)]
+    pub subnets: Vec<Subnet>,
+
+    /// The power actions matching clients are allowed to perform.
+    pub actions: Vec<PowerAction>,
+}
+
+/// Settings of device pairing.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PairingConfig {
+    /// Identifiers of paired devices which may also list and revoke other devices, cancel the
+    /// actions they scheduled and release their keep awake leases.
+    pub privileged_devices: Vec<String>,
+}
+
+/// Settings of the Prometheus metrics listener.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// The address to serve metrics on over plain HTTP, metrics are not served if not set.
+    pub address: Option<SocketAddr>,
+}
+
+/// Settings of the power action audit log.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    /// Size in bytes after which the audit log is rotated.
+    pub max_file_size: u64,
+
+    /// Number of rotated audit logs which are kept, older ones are deleted.
+    pub max_rotated_files: u32,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: DEFAULT_AUDIT_MAX_FILE_SIZE,
+            max_rotated_files: DEFAULT_AUDIT_MAX_ROTATED_FILES,
+        }
+    }
+}
+
+/// Settings of the simulated platform.
+///
+/// Only used by agents built with the `mock-platform` feature.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockConfig {
+    /// Power actions the simulated platform supports, all actions if not set.
+    pub supported_actions: Option<Vec<PowerAction>>,
+
+    /// File performed power actions are appended to as JSON lines, they are only logged if not
+    /// set.
+    pub action_log: Option<PathBuf>,
+
+    /// Directory for persistent agent data, a directory in the temporary directory if not set.
+    pub data_directory: Option<PathBuf>,
+}
+
+/// A command clients may run on the agent machine.
+///
+/// Custom actions can only be defined here, clients merely refer to them by name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomActionConfig {
+    /// Unique name clients refer to the action by.
+    pub name: String,
+
+    /// Human readable description of the action.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Absolute path of the executable to run.
+    pub command: PathBuf,
+
+    /// Arguments passed to the executable.
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// User to run the command as, the user of the agent if not set.
+    ///
+    /// Only supported on Unix.
+    #[serde(default)]
+    pub user: Option<String>,
+
+    /// Working directory of the command, the working directory of the agent if not set.
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+
+    /// Number of seconds after which the command is killed.
+    #[serde(default = "CustomActionConfig::default_timeout_seconds")]
+    pub timeout_seconds: u64,
+
+    /// Identifiers of the paired devices allowed to run the action, all if not set.
+    #[serde(default)]
+    pub devices: Option<Vec<String>>,
+}
+
+impl CustomActionConfig {
+    fn default_timeout_seconds() -> u64 {
+        DEFAULT_CUSTOM_ACTION_TIMEOUT
+    }
+
+    /// Retrieves the duration after which the command is killed.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_seconds)
+    }
+
+    /// Tests whether the given paired device may run the action.
+    pub fn is_allowed(&self, device_id: &str) -> bool {
+        match &self.devices {
+            None => true,
+            Some(devices) => devices.iter().any(|d| d == device_id),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the file given on the command line or in the environment.
+    ///
+    /// If neither specifies a file, the default configuration is used.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = match Self::path_from_args(std::env::args().skip(1))? {
+            Some(v) => Some(v),
+            None => std::env::var_os(CONFIG_ENV_VAR).map(PathBuf::from),
+        };
+
+        match path {
+            None => {
+                tracing::debug!("No configuration file given, using defaults");
+                Ok(Self::default())
+            }
+            Some(path) => Self::load_from(&path),
+        }
+    }
+
+    /// Loads the configuration from the given file.
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+
+        let config: Self = toml::from_str(&data)
+            .map_err(|err| ConfigError::Toml(path.to_path_buf(), Box::new(err)))?;
+        config
+            .validate()
+            .map_err(|err| ConfigError::Invalid(path.to_path_buf(), err))?;
+
+        tracing::info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+
+    fn path_from_args(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Option<PathBuf>, ConfigError> {
+        let mut path = None;
+
+        while let Some(arg) = args.next() {
+            let value = if arg == CONFIG_FLAG {
+                args.next().ok_or_else(|| {
+                    ConfigError::Arguments(format!("{} requires a path", CONFIG_FLAG))
+                })?
+            } else if let Some(value) = arg
+                .strip_prefix(CONFIG_FLAG)
+                .and_then(|v| v.strip_prefix('='))
+            {
+                value.to_string()
+            } else {
+                return Err(ConfigError::Arguments(format!("unknown argument {}", arg)));
+            };
+
+            path = Some(PathBuf::from(value));
+        }
+
+        Ok(path)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // RFC 6763: _<service name>._tcp or _<service name>._udp, with service names as per
+        // RFC 6335
+        let service_name = self
+            .discovery
+            .service_type
+            .strip_suffix("._tcp")
+            .or_else(|| self.discovery.service_type.strip_suffix("._udp"))
+            .and_then(|v| v.strip_prefix('_'))
+            .filter(|v| {
+                (1..=15).contains(&v.len())
+                    && v.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                    && !v.starts_with('-')
+                    && !v.ends_with('-')
+            });
+        if service_name.is_none() {
+            return Err(format!(
+                "discovery.service_type \"{}\" is not a valid DNS-SD service type, expected something like \"{}\"",
+                self.discovery.service_type, DEFAULT_SERVICE_TYPE
+            ));
+        }
+
+        if self.discovery.ssdp_max_age == 0 {
+            return Err("discovery.ssdp_max_age must be at least 1".to_string());
+        }
+
+        // Only the actions which are hard to take back are meant to be confirmed
+        if let Some(action) = self.power.confirm_actions.iter().find(|v| {
+            !matches!(
+                v,
+                PowerAction::PowerOff | PowerAction::Reboot | PowerAction::RebootToFirmware
+            )
+        }) {
+            return Err(format!(
+                "power.confirm_actions may only contain power_off, reboot and reboot_to_firmware, not {:?}",
+                action
+            ));
+        }
+
+        if self.power.confirmation_window_seconds == 0 {
+            return Err("power.confirmation_window_seconds must be at least 1".to_string());
+        }
+
+        if let Some(i) = self
+            .power_policy
+            .rules
+            .iter()
+            .position(|r| r.devices.is_empty() && r.subnets.is_empty())
+        {
+            return Err(format!(
+                "power_policy.rule[{}] matches no client, it needs devices or subnets",
+                i
+            ));
+        }
+
+        if self.audit.max_file_size == 0 {
+            return Err("audit.max_file_size must be at least 1".to_string());
+        }
+
+        for (i, action) in self.custom_actions.iter().enumerate() {
+            if action.name.trim().is_empty() {
+                return Err(format!("custom_actions[{}].name must not be empty", i));
+            }
+
+            if self.custom_actions[..i]
+                .iter()
+                .any(|other| other.name == action.name)
+            {
+                return Err(format!(
+                    "custom action \"{}\" is defined more than once",
+                    action.name
+                ));
+            }
+
+            if !action.command.is_absolute() {
+                return Err(format!(
+                    "command of custom action \"{}\" must be an absolute path",
+                    action.name
+                ));
+            }
+
+            if action.timeout_seconds == 0 {
+                return Err(format!(
+                    "timeout_seconds of custom action \"{}\" must be at least 1",
+                    action.name
+                ));
+            }
+
+            if cfg!(not(unix)) && action.user.is_some() {
+                return Err(format!(
+                    "custom action \"{}\" sets a user, which is only supported on Unix",
+                    action.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("invalid command line: {0}")]
+    Arguments(String),
+
+    #[error("failed to read configuration {}: {1}", .0.display())]
+    Io(PathBuf, std::io::Error),
+
+    #[error("invalid configuration {}: {1}", .0.display())]
+    Toml(PathBuf, Box<toml::de::Error>),
+
+    #[error("invalid configuration {}: {1}", .0.display())]
+    Invalid(PathBuf, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(toml).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn empty_configuration_uses_defaults() {
+        let config = parse("").unwrap();
+
+        assert_eq!(config.listener.port, DEFAULT_PORT);
+        assert_eq!(config.discovery.service_type, DEFAULT_SERVICE_TYPE);
+        assert!(config.discovery.is_enabled(DiscoveryBackend::Mdns));
+        assert!(config.discovery.is_enabled(DiscoveryBackend::Ssdp));
+        assert!(config.power.actions.is_none());
+        assert!(config.power_policy.rules.is_empty());
+        assert!(config.custom_actions.is_empty());
+    }
+
+    #[test]
+    fn default_configuration_is_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(parse("[listener]\nadress = \"::\"").is_err());
+        assert!(parse("[unknown]").is_err());
+    }
+
+    #[test]
+    fn service_type_is_validated() {
+        assert!(parse("[discovery]\nservice_type = \"_other._udp\"").is_ok());
+
+        for service_type in [
+            "dragon-claw._tcp",
+            "_dragon-claw._sctp",
+            "_._tcp",
+            "_-dragon-claw._tcp",
+            "_dragon-claw-._tcp",
+            "_dragon_claw._tcp",
+            "_a-very-long-service-name._tcp",
+        ] {
+            let toml = format!("[discovery]\nservice_type = \"{}\"", service_type);
+            assert!(parse(&toml).is_err(), "{} was accepted", service_type);
+        }
+    }
+
+    #[test]
+    fn zero_durations_are_rejected() {
+        assert!(parse("[discovery]\nssdp_max_age = 0").is_err());
+        assert!(parse("[power]\nconfirmation_window_seconds = 0").is_err());
+        assert!(parse("[audit]\nmax_file_size = 0").is_err());
+    }
+
+    #[test]
+    fn only_irreversible_actions_can_require_confirmation() {
+        assert!(parse("[power]\nconfirm_actions = [\"power_off\", \"reboot\"]").is_ok());
+        assert!(parse("[power]\nconfirm_actions = [\"suspend\"]").is_err());
+    }
+
+    #[test]
+    fn policy_rules_have_to_match_clients() {
+        let config = parse(
+            "[power_policy]\n\
+             default = [\"lock\"]\n\
+             [[power_policy.rule]]\n\
+             subnets = [\"192.168.1.0/24\"]\n\
+             actions = [\"power_off\"]",
+        )
+        .unwrap();
+        assert_eq!(config.power_policy.rules.len(), 1);
+
+        assert!(parse("[[power_policy.rule]]\nactions = [\"power_off\"]").is_err());
+        assert!(parse(
+            "[[power_policy.rule]]\nsubnets = [\"192.168.1.0/33\"]\nactions = [\"power_off\"]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn custom_actions_are_validated() {
+        let action = |name: &str, command: &str| {
+            format!(
+                "[[custom_actions]]\nname = \"{}\"\ncommand = \"{}\"\n",
+                name, command
+            )
+        };
+
+        assert!(parse(&action("backup", "/usr/bin/backup")).is_ok());
+        assert!(parse(&action(" ", "/usr/bin/backup")).is_err());
+        assert!(parse(&action("backup", "backup")).is_err());
+        assert!(parse(&format!(
+            "{}{}",
+            action("backup", "/usr/bin/backup"),
+            action("backup", "/usr/bin/other")
+        ))
+        .is_err());
+        assert!(parse(&format!(
+            "{}timeout_seconds = 0",
+            action("backup", "/usr/bin/backup")
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn path_is_taken_from_arguments() {
+        let args = |args: &[&str]| {
+            Config::path_from_args(args.iter().map(|v| v.to_string()))
+                .map_err(|err| err.to_string())
+        };
+
+        assert_eq!(args(&[]).unwrap(), None);
+        assert_eq!(
+            args(&["--config", "/etc/agent.toml"]).unwrap(),
+            Some(PathBuf::from("/etc/agent.toml"))
+        );
+        assert_eq!(
+            args(&["--config=/etc/agent.toml"]).unwrap(),
+            Some(PathBuf::from("/etc/agent.toml"))
+        );
+        assert!(args(&["--config"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+    }
+}
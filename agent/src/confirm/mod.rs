@@ -0,0 +1,211 @@
+use crate::pal::power::PowerAction;
+use crate::util::{random_hex, unix_time_now};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+
+/// A power action waiting to be confirmed by the device which requested it.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    /// Token the device has to confirm the action with.
+    pub token: String,
+
+    /// The power action to perform once confirmed.
+    pub action: PowerAction,
+
+    /// Time the token expires at, in seconds since the unix epoch.
+    pub expires_at: u64,
+}
+
+#[derive(Debug)]
+struct PendingChallenge {
+    challenge: Challenge,
+    device_id: String,
+    used: bool,
+}
+
+/// Keeps track of power actions which only run after a second, confirming call.
+///
+/// Tokens are remembered for another window after they expired, so that late or repeated
+/// confirmations can be told apart from unknown tokens.
+#[derive(Debug)]
+pub struct ConfirmationManager {
+    actions: Vec<PowerAction>,
+    window: Duration,
+    challenges: Mutex<HashMap<String, PendingChallenge>>,
+}
+
+impl ConfirmationManager {
+    /// Creates a manager requiring confirmation of the given actions within the given window.
+    pub fn new(actions: Vec<PowerAction>, window: Duration) -> Self {
+        Self {
+            actions,
+            window,
+            challenges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Tests whether the given action has to be confirmed.
+    pub fn is_required(&self, action: PowerAction) -> bool {
+        self.actions.contains(&action)
+    }
+
+    /// Issues a challenge the given device has to confirm before the action is performed.
+    pub fn issue(&self, action: PowerAction, device_id: &str) -> Challenge {
+        let challenge = Challenge {
+            token: random_hex(16),
+            action,
+            expires_at: unix_time_now() + self.window.as_secs(),
+        };
+
+        let mut challenges = self.challenges.lock().expect("Poisoned challenges lock");
+        self.forget_stale(&mut challenges);
+        challenges.insert(
+            challenge.token.clone(),
+            PendingChallenge {
+                challenge: challenge.clone(),
+                device_id: device_id.to_string(),
+                used: false,
+            },
+        );
+
+        challenge
+    }
+
+    /// Redeems a challenge token, returning the action to perform.
+    ///
+    /// Every token can only be redeemed once, and only by the device it has been issued to.
+    pub fn confirm(&self, token: &str, device_id: &str) -> Result<PowerAction, ConfirmationError> {
+        let mut challenges = self.challenges.lock().expect("Poisoned challenges lock");
+        self.forget_stale(&mut challenges);
+
+        let pending = challenges
+            .get_mut(token)
+            .filter(|p| p.device_id == device_id)
+            .ok_or(ConfirmationError::UnknownToken)?;
+
+        if pending.used {
+            return Err(ConfirmationError::AlreadyUsed);
+        } else if unix_time_now() >= pending.challenge.expires_at {
+            return Err(ConfirmationError::Expired);
+        }
+
+        pending.used = true;
+        Ok(pending.challenge.action)
+    }
+
+    fn forget_stale(&self, challenges: &mut HashMap<String, PendingChallenge>) {
+        let now = unix_time_now();
+        challenges.retain(|_, p| now < p.challenge.expires_at + self.window.as_secs());
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfirmationError {
+    #[error("unknown confirmation token")]
+    UnknownToken,
+
+    #[error("confirmation token has expired")]
+    Expired,
+
+    #[error("confirmation token has already been used")]
+    AlreadyUsed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(30);
+
+    fn manager() -> ConfirmationManager {
+        ConfirmationManager::new(vec![PowerAction::PowerOff, PowerAction::Reboot], WINDOW)
+    }
+
+    /// Moves the expiry of a challenge, as if time had passed since it was issued.
+    fn set_expires_at(manager: &ConfirmationManager, token: &str, expires_at: u64) {
+        manager
+            .challenges
+            .lock()
+            .unwrap()
+            .get_mut(token)
+            .unwrap()
+            .challenge
+            .expires_at = expires_at;
+    }
+
+    #[test]
+    fn only_configured_actions_require_confirmation() {
+        let manager = manager();
+
+        assert!(manager.is_required(PowerAction::PowerOff));
+        assert!(manager.is_required(PowerAction::Reboot));
+        assert!(!manager.is_required(PowerAction::Suspend));
+    }
+
+    #[test]
+    fn token_can_be_confirmed_once() {
+        let manager = manager();
+        let issued_at = unix_time_now();
+        let challenge = manager.issue(PowerAction::Reboot, "device");
+
+        assert_eq!(challenge.action, PowerAction::Reboot);
+        assert!(challenge.expires_at >= issued_at + WINDOW.as_secs());
+        assert!(challenge.expires_at <= unix_time_now() + WINDOW.as_secs());
+        assert!(matches!(
+            manager.confirm(&challenge.token, "device"),
+            Ok(PowerAction::Reboot)
+        ));
+        assert!(matches!(
+            manager.confirm(&challenge.token, "device"),
+            Err(ConfirmationError::AlreadyUsed)
+        ));
+    }
+
+    #[test]
+    fn token_is_bound_to_device() {
+        let manager = manager();
+        let challenge = manager.issue(PowerAction::PowerOff, "device");
+
+        assert!(matches!(
+            manager.confirm(&challenge.token, "other-device"),
+            Err(ConfirmationError::UnknownToken)
+        ));
+        assert!(matches!(
+            manager.confirm("unknown", "device"),
+            Err(ConfirmationError::UnknownToken)
+        ));
+
+        // Failed attempts of others don't use up the token
+        assert!(manager.confirm(&challenge.token, "device").is_ok());
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let manager = manager();
+        let challenge = manager.issue(PowerAction::PowerOff, "device");
+        set_expires_at(&manager, &challenge.token, unix_time_now());
+
+        assert!(matches!(
+            manager.confirm(&challenge.token, "device"),
+            Err(ConfirmationError::Expired)
+        ));
+    }
+
+    #[test]
+    fn expired_token_is_forgotten_after_another_window() {
+        let manager = manager();
+        let challenge = manager.issue(PowerAction::PowerOff, "device");
+        set_expires_at(
+            &manager,
+            &challenge.token,
+            unix_time_now() - WINDOW.as_secs(),
+        );
+
+        assert!(matches!(
+            manager.confirm(&challenge.token, "device"),
+            Err(ConfirmationError::UnknownToken)
+        ));
+    }
+}
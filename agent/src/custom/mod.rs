@@ -0,0 +1,266 @@
+use crate::config::CustomActionConfig;
+use std::process::Stdio;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+
+/// How many output chunks are buffered before the command has to wait for the client.
+const OUTPUT_BUFFER_SIZE: usize = 64;
+
+/// Size of a single output chunk.
+const OUTPUT_CHUNK_SIZE: usize = 4096;
+
+/// Output of a running custom action.
+#[derive(Debug, Clone)]
+pub enum CustomActionOutput {
+    /// Data the command wrote to its standard output
+    Stdout(Vec<u8>),
+
+    /// Data the command wrote to its standard error
+    Stderr(Vec<u8>),
+
+    /// The command has finished, this is always the last output
+    Exited(CustomActionExit),
+}
+
+/// How a custom action has finished.
+#[derive(Debug, Copy, Clone)]
+pub struct CustomActionExit {
+    /// The exit code of the command, not set if it has been killed by a signal.
+    pub code: Option<i32>,
+
+    /// Whether the command has been killed because it exceeded its timeout.
+    pub timed_out: bool,
+}
+
+/// Runs the custom actions defined in the configuration.
+#[derive(Debug)]
+pub struct CustomActionRunner {
+    actions: Vec<CustomActionConfig>,
+}
+
+impl CustomActionRunner {
+    pub fn new(actions: Vec<CustomActionConfig>) -> Self {
+        Self { actions }
+    }
+
+    /// Retrieves the actions the given paired device may run.
+    pub fn actions_for(&self, device_id: &str) -> Vec<CustomActionConfig> {
+        self.actions
+            .iter()
+            .filter(|a| a.is_allowed(device_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Starts a custom action on behalf of the given paired device.
+    ///
+    /// The command keeps running if the returned receiver is dropped, until it exits or its
+    /// timeout is exceeded.
+    pub fn run(
+        &self,
+        name: &str,
+        device_id: &str,
+    ) -> Result<mpsc::Receiver<CustomActionOutput>, CustomActionError> {
+        let action = self
+            .actions
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or(CustomActionError::UnknownAction)?;
+
+        if !action.is_allowed(device_id) {
+            return Err(CustomActionError::NotAllowed);
+        }
+
+        let mut command = Command::new(&action.command);
+        command
+            .args(&action.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        if let Some(working_directory) = &action.working_directory {
+            command.current_dir(working_directory);
+        }
+
+        #[cfg(unix)]
+        if let Some(user) = &action.user {
+            let user = unix_user::lookup(user)?;
+            command
+                .uid(user.uid)
+                .gid(user.gid)
+                .env("HOME", &user.home)
+                .env("USER", &user.name)
+                .env("LOGNAME", &user.name);
+        }
+
+        let child = command.spawn().map_err(CustomActionError::Spawn)?;
+        tracing::info!(
+            "Running custom action \"{}\" for device {} (pid {:?})",
+            action.name,
+            device_id,
+            child.id()
+        );
+
+        let (sender, receiver) = mpsc::channel(OUTPUT_BUFFER_SIZE);
+        tokio::spawn(Self::supervise(action.clone(), child, sender));
+
+        Ok(receiver)
+    }
+
+    async fn supervise(
+        action: CustomActionConfig,
+        mut child: Child,
+        sender: mpsc::Sender<CustomActionOutput>,
+    ) {
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let res = tokio::time::timeout(action.timeout(), async {
+            tokio::join!(
+                Self::forward(stdout, &sender, CustomActionOutput::Stdout),
+                Self::forward(stderr, &sender, CustomActionOutput::Stderr),
+            );
+
+            child.wait().await
+        })
+        .await;
+
+        let exit = match res {
+            Ok(Ok(status)) => CustomActionExit {
+                code: status.code(),
+                timed_out: false,
+            },
+            Ok(Err(err)) => {
+                tracing::error!(
+                    "Failed to wait for custom action \"{}\": {}",
+                    action.name,
+                    err
+                );
+                CustomActionExit {
+                    code: None,
+                    timed_out: false,
+                }
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "Custom action \"{}\" exceeded its timeout of {} seconds, killing it",
+                    action.name,
+                    action.timeout_seconds
+                );
+
+                if let Err(err) = child.kill().await {
+                    tracing::error!("Failed to kill custom action \"{}\": {}", action.name, err);
+                }
+
+                CustomActionExit {
+                    code: None,
+                    timed_out: true,
+                }
+            }
+        };
+
+        tracing::info!(
+            "Custom action \"{}\" finished with exit code {:?}",
+            action.name,
+            exit.code
+        );
+
+        // The client may be gone already
+        let _ = sender.send(CustomActionOutput::Exited(exit)).await;
+    }
+
+    /// Forwards everything read from the given pipe, until it is closed.
+    async fn forward(
+        mut pipe: impl AsyncRead + Unpin,
+        sender: &mpsc::Sender<CustomActionOutput>,
+        wrap: fn(Vec<u8>) -> CustomActionOutput,
+    ) {
+        let mut buffer = vec![0; OUTPUT_CHUNK_SIZE];
+        loop {
+            match pipe.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => {
+                    // Keep draining the pipe even if the client is gone, so the command does not
+                    // block on a full pipe
+                    let _ = sender.send(wrap(buffer[..n].to_vec())).await;
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to read output of custom action: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix_user {
+    use super::CustomActionError;
+    use std::ffi::{CStr, CString};
+    use std::path::PathBuf;
+
+    /// A user the agent can run commands as.
+    pub struct UnixUser {
+        pub name: String,
+        pub uid: u32,
+        pub gid: u32,
+        pub home: PathBuf,
+    }
+
+    /// Looks up a user in the password database.
+    pub fn lookup(name: &str) -> Result<UnixUser, CustomActionError> {
+        let c_name = CString::new(name).map_err(|_| CustomActionError::UnknownUser)?;
+
+        let mut passwd = std::mem::MaybeUninit::<libc::passwd>::uninit();
+        let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+        let mut result = std::ptr::null_mut();
+
+        let err = unsafe {
+            libc::getpwnam_r(
+                c_name.as_ptr(),
+                passwd.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+
+        if err != 0 {
+            return Err(CustomActionError::Spawn(std::io::Error::from_raw_os_error(
+                err,
+            )));
+        } else if result.is_null() {
+            return Err(CustomActionError::UnknownUser);
+        }
+
+        let passwd = unsafe { passwd.assume_init() };
+        let home = unsafe { CStr::from_ptr(passwd.pw_dir) }
+            .to_string_lossy()
+            .into_owned();
+
+        Ok(UnixUser {
+            name: name.to_string(),
+            uid: passwd.pw_uid,
+            gid: passwd.pw_gid,
+            home: PathBuf::from(home),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CustomActionError {
+    #[error("unknown custom action")]
+    UnknownAction,
+
+    #[error("custom action is not allowed for this device")]
+    NotAllowed,
+
+    #[error("the user of the custom action does not exist")]
+    UnknownUser,
+
+    #[error("failed to start custom action: {0}")]
+    Spawn(std::io::Error),
+}
@@ -1,3 +1,9 @@
+use crate::pairing::PairingError;
+use crate::policy::PolicyError;
+use crate::schedule::ScheduleError;
+use crate::storage::StorageError;
+use crate::tls::AgentIdentityError;
+use crate::wol::WakeOnLanError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -11,6 +17,30 @@ pub enum DragonClawAgentError {
     #[error("rpc transport failed: {0}")]
     TonicTransport(#[from] tonic::transport::Error),
 
+    #[error("failed to set up server reflection: {0}")]
+    Reflection(#[from] tonic_reflection::server::Error),
+
+    #[error("failed to start metrics listener: {0}")]
+    Metrics(#[from] hyper::Error),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("failed to set up TLS identity: {0}")]
+    Identity(#[from] AgentIdentityError),
+
+    #[error("failed to load UPnP device identity: {0}")]
+    DeviceIdentity(#[from] StorageError),
+
+    #[error("failed to load paired devices: {0}")]
+    Pairing(#[from] PairingError),
+
+    #[error("invalid power policy: {0}")]
+    Policy(#[from] PolicyError),
+
+    #[error("failed to start scheduler: {0}")]
+    Schedule(#[from] ScheduleError),
+
+    #[error("failed to load known peers: {0}")]
+    WakeOnLan(#[from] WakeOnLanError),
 }
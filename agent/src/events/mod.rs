@@ -0,0 +1,133 @@
+use crate::pal::events::{EventManager, SystemEvent};
+use crate::pal::power::PowerAction;
+use crate::pal::status::ApplicationStatus;
+use crate::pal::{PlatformAbstraction, PlatformAbstractionError};
+use crate::util::unix_time_now;
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// How many events are buffered for slow subscribers before they start missing events.
+const EVENT_BUFFER_SIZE: usize = 64;
+
+/// Simplified application status, as reported to clients.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AgentStatus {
+    Starting,
+    Running,
+    Stopping,
+    Stopped,
+    Failed,
+}
+
+impl From<&ApplicationStatus> for AgentStatus {
+    fn from(value: &ApplicationStatus) -> Self {
+        match value {
+            ApplicationStatus::Starting => Self::Starting,
+            ApplicationStatus::Running => Self::Running,
+            ApplicationStatus::Stopping => Self::Stopping,
+            ApplicationStatus::Stopped => Self::Stopped,
+            ApplicationStatus::PlatformError(_) | ApplicationStatus::ApplicationError(_) => {
+                Self::Failed
+            }
+        }
+    }
+}
+
+/// The different kinds of events.
+#[derive(Debug, Clone)]
+pub enum AgentEventKind {
+    /// The status of the agent has changed
+    StatusChanged(AgentStatus),
+
+    /// The operating system reported an event
+    System(SystemEvent),
+
+    /// A client requested a power action
+    PowerActionRequested {
+        action_id: String,
+        action: PowerAction,
+        device_id: String,
+        device_name: String,
+    },
+}
+
+/// An event which happened on the agent.
+#[derive(Debug, Clone)]
+pub struct AgentEvent {
+    /// Time the event happened at, in seconds since the unix epoch.
+    pub timestamp: u64,
+
+    /// What happened.
+    pub kind: AgentEventKind,
+}
+
+impl AgentEvent {
+    /// Tests whether the event announces that the agent is stopping.
+    pub fn is_stopping(&self) -> bool {
+        matches!(
+            self.kind,
+            AgentEventKind::StatusChanged(AgentStatus::Stopping)
+        )
+    }
+}
+
+/// Distributes agent events to all subscribers.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<AgentEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    /// Publishes an event to all current subscribers.
+    pub fn publish(&self, kind: AgentEventKind) {
+        tracing::trace!("Publishing event {:?}", kind);
+
+        // Failing only means that nobody is listening
+        let _ = self.sender.send(AgentEvent {
+            timestamp: unix_time_now(),
+            kind,
+        });
+    }
+
+    /// Subscribes to all events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<AgentEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Starts publishing the events reported by the platform.
+    pub fn forward_system_events(
+        self: &Arc<Self>,
+        pal: Arc<PlatformAbstraction>,
+    ) -> JoinHandle<()> {
+        let this = self.clone();
+
+        tokio::spawn(async move {
+            let mut events = match pal.event_manager().subscribe().await {
+                Ok(v) => v,
+                Err(PlatformAbstractionError::Unsupported) => {
+                    tracing::debug!("System events are not supported on this platform");
+                    return;
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to subscribe to system events: {}", err);
+                    return;
+                }
+            };
+
+            while let Some(event) = events.next().await {
+                this.publish(AgentEventKind::System(event));
+            }
+
+            tracing::debug!("System event stream ended");
+        })
+    }
+}
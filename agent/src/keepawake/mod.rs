@@ -0,0 +1,197 @@
+use crate::pal::power::{PowerManager, SleepInhibitor};
+use crate::pal::{PlatformAbstraction, PlatformAbstractionError};
+use crate::util::{random_hex, unix_time_now};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+/// Upper bound for the duration of a single lease.
+pub const MAX_LEASE_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A lease keeping the system from going to sleep.
+#[derive(Debug, Clone)]
+pub struct KeepAwakeLease {
+    /// Identifier of the lease.
+    pub id: String,
+
+    /// Why the system is kept awake, as given by the client.
+    pub reason: String,
+
+    /// Identifier of the device which acquired the lease.
+    pub requested_by: String,
+
+    /// Time the lease has been acquired at, in seconds since the unix epoch.
+    pub acquired_at: u64,
+
+    /// Time the lease expires at, in seconds since the unix epoch.
+    pub expires_at: u64,
+}
+
+#[derive(Debug)]
+struct ActiveLease {
+    lease: KeepAwakeLease,
+    expiry_task: JoinHandle<()>,
+
+    // Only held so the lock is released once the lease is dropped
+    _inhibitor: SleepInhibitor,
+}
+
+impl Drop for ActiveLease {
+    fn drop(&mut self) {
+        self.expiry_task.abort();
+    }
+}
+
+/// Keeps the system awake for a limited time on behalf of clients.
+///
+/// Every lease holds its own inhibitor lock, the system may sleep again once all leases have
+/// been released or expired.
+#[derive(Debug)]
+pub struct KeepAwakeManager {
+    pal: Arc<PlatformAbstraction>,
+    leases: Mutex<HashMap<String, ActiveLease>>,
+}
+
+impl KeepAwakeManager {
+    pub fn new(pal: Arc<PlatformAbstraction>) -> Self {
+        Self {
+            pal,
+            leases: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Acquires a lease keeping the system awake for the given duration.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        duration: Duration,
+        reason: String,
+        requested_by: String,
+    ) -> Result<KeepAwakeLease, KeepAwakeError> {
+        if duration.is_zero() || duration > MAX_LEASE_DURATION {
+            return Err(KeepAwakeError::InvalidDuration);
+        }
+
+        let power = self
+            .pal
+            .power_manager()
+            .ok_or(PlatformAbstractionError::Unsupported)?;
+        let inhibitor = power.inhibit_sleep(&reason).await?;
+
+        let now = unix_time_now();
+        let lease = KeepAwakeLease {
+            id: random_hex(8),
+            reason,
+            requested_by,
+            acquired_at: now,
+            expires_at: now + duration.as_secs(),
+        };
+
+        let expiry_task = tokio::spawn(Self::expire(
+            Arc::downgrade(self),
+            lease.id.clone(),
+            duration,
+        ));
+
+        self.leases
+            .lock()
+            .expect("Poisoned keep awake leases lock")
+            .insert(
+                lease.id.clone(),
+                ActiveLease {
+                    lease: lease.clone(),
+                    expiry_task,
+                    _inhibitor: inhibitor,
+                },
+            );
+
+        tracing::info!(
+            "Keeping system awake until {} for \"{}\" ({})",
+            lease.expires_at,
+            lease.reason,
+            lease.id
+        );
+
+        Ok(lease)
+    }
+
+    /// Retrieves the active lease with the given identifier.
+    pub fn get(&self, id: &str) -> Option<KeepAwakeLease> {
+        self.leases
+            .lock()
+            .expect("Poisoned keep awake leases lock")
+            .get(id)
+            .map(|v| v.lease.clone())
+    }
+
+    /// Releases a lease before it expires.
+    pub fn release(&self, id: &str) -> Result<(), KeepAwakeError> {
+        let removed = self
+            .leases
+            .lock()
+            .expect("Poisoned keep awake leases lock")
+            .remove(id);
+
+        match removed {
+            None => Err(KeepAwakeError::UnknownLease),
+            Some(_) => {
+                tracing::info!("Released keep awake lease {}", id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Releases all leases, such as when the agent is shutting down.
+    pub fn release_all(&self) {
+        let mut leases = self.leases.lock().expect("Poisoned keep awake leases lock");
+        if !leases.is_empty() {
+            tracing::info!("Releasing {} keep awake leases", leases.len());
+            leases.clear();
+        }
+    }
+
+    /// Retrieves all active leases, ordered by the time they expire.
+    pub fn leases(&self) -> Vec<KeepAwakeLease> {
+        let mut leases = self
+            .leases
+            .lock()
+            .expect("Poisoned keep awake leases lock")
+            .values()
+            .map(|v| v.lease.clone())
+            .collect::<Vec<_>>();
+        leases.sort_by_key(|l| l.expires_at);
+        leases
+    }
+
+    async fn expire(manager: Weak<Self>, id: String, duration: Duration) {
+        tokio::time::sleep(duration).await;
+
+        let Some(manager) = manager.upgrade() else {
+            return;
+        };
+
+        // Dropping the lease aborts this task, which only takes effect at the next await point
+        let expired = manager
+            .leases
+            .lock()
+            .expect("Poisoned keep awake leases lock")
+            .remove(&id);
+
+        if expired.is_some() {
+            tracing::info!("Keep awake lease {} expired", id);
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeepAwakeError {
+    #[error(transparent)]
+    Platform(#[from] PlatformAbstractionError),
+
+    #[error("lease duration must be between 1 second and {} hours", MAX_LEASE_DURATION.as_secs() / 3600)]
+    InvalidDuration,
+
+    #[error("unknown keep awake lease")]
+    UnknownLease,
+}
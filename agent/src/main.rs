@@ -1,21 +1,63 @@
+use crate::audit::AuditLog;
+use crate::config::Config;
+use crate::confirm::ConfirmationManager;
+use crate::custom::CustomActionRunner;
 use crate::error::DragonClawAgentError;
-use crate::pal::discovery::DiscoveryManager;
+use crate::pal::discovery::{DiscoveryManager, MacAddress, ServiceInfo};
 use crate::pal::status::{ApplicationStatus, StatusManager};
 use crate::pal::{PlatformInitData, ShutdownRequestFut};
-use std::net::{Ipv4Addr, SocketAddrV4};
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tonic::server::NamedService;
 use tonic::transport::server::TcpIncoming;
 use tonic::transport::Server;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::proto::{DragonClawAgentImpl, DragonClawAgentServer};
+use crate::events::{AgentEventKind, AgentStatus, EventBus};
+use crate::keepawake::KeepAwakeManager;
+use crate::metrics::{RpcMetricsLayer, METRICS};
+use crate::pairing::PairingManager;
+use crate::policy::PowerPolicy;
+use crate::proto::{
+    AuthInterceptor, DragonClawAgentImpl, DragonClawAgentServer, FILE_DESCRIPTOR_SET,
+};
+use crate::schedule::Scheduler;
+use crate::tls::AgentIdentity;
+use crate::tracker::ActionTracker;
+use crate::wol::PeerRegistry;
 
+mod audit;
+mod config;
+mod confirm;
+mod custom;
 mod error;
+mod events;
+mod keepawake;
+// Windows registers services with its own DNS-SD responder, the mock platform announces nothing
+#[cfg(all(target_os = "linux", not(feature = "mock-platform")))]
+mod mdns;
+mod metrics;
+mod net;
+mod pairing;
 mod pal;
+mod policy;
 mod proto;
+mod schedule;
+// The mock platform only uses the device identity, it announces nothing
+#[cfg_attr(feature = "mock-platform", allow(dead_code))]
 mod ssdp;
+mod storage;
+mod tls;
+mod tracker;
+mod util;
+mod wol;
+
+/// How long open RPC calls are given to finish when shutting down.
+const SERVER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 fn main() {
     // Set up logging using tracing
@@ -24,6 +66,9 @@ fn main() {
         .with(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
+    // Uptime is measured from here
+    LazyLock::force(&METRICS);
+
     tracing::info!(
         "{} version {}",
         env!("CARGO_PKG_NAME"),
@@ -31,7 +76,17 @@ fn main() {
     );
     tracing::info!("Starting agent...");
 
-    let res = pal::PlatformAbstraction::dispatch_main(service_main);
+    let config = match Config::load() {
+        Ok(v) => v,
+        Err(err) => {
+            tracing::error!("Failed to load configuration: {}", err);
+            std::process::exit(1)
+        }
+    };
+
+    let res = pal::PlatformAbstraction::dispatch_main(move |data, shutdown_fut| {
+        service_main(data, shutdown_fut, config)
+    });
 
     // Set exit code depending on run result
     match res {
@@ -51,9 +106,13 @@ fn main() {
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn service_main(data: PlatformInitData, shutdown_fut: ShutdownRequestFut) -> Result<(), ()> {
+async fn service_main(
+    data: PlatformInitData,
+    shutdown_fut: ShutdownRequestFut,
+    config: Config,
+) -> Result<(), ()> {
     tracing::debug!("Creating platform abstraction layer...");
-    let pal = match pal::PlatformAbstraction::new(data).await {
+    let pal = match pal::PlatformAbstraction::new(data, &config).await {
         Ok(v) => v,
         Err(err) => {
             tracing::error!("Failed to create platform abstraction layer: {}", err);
@@ -66,7 +125,7 @@ async fn service_main(data: PlatformInitData, shutdown_fut: ShutdownRequestFut)
     pal.status_manager()
         .set_status(ApplicationStatus::Starting)
         .await;
-    match runner(pal.clone(), shutdown_fut).await {
+    match runner(pal.clone(), &config, shutdown_fut).await {
         Ok(()) => {
             tracing::info!("Service finished successfully!");
             pal.status_manager()
@@ -88,54 +147,176 @@ async fn service_main(data: PlatformInitData, shutdown_fut: ShutdownRequestFut)
 
 async fn runner(
     pal: Arc<pal::PlatformAbstraction>,
+    config: &Config,
     shutdown_fut: ShutdownRequestFut,
 ) -> Result<(), DragonClawAgentError> {
+    tracing::debug!("Loading TLS identity...");
+    let identity = AgentIdentity::load_or_generate(pal.data_directory()).await?;
+
+    tracing::debug!("Loading UPnP device identity...");
+    let device_uuid = ssdp::load_or_generate_device_uuid(pal.data_directory()).await?;
+
+    tracing::debug!("Loading paired devices...");
+    let pairing = Arc::new(
+        PairingManager::load(
+            pal.data_directory(),
+            config.pairing.privileged_devices.clone(),
+        )
+        .await?,
+    );
+
+    tracing::debug!("Loading known peers...");
+    let peers = Arc::new(PeerRegistry::load(pal.data_directory()).await?);
+
+    PowerPolicy::check_legacy_file(pal.data_directory()).await?;
+    let policy = Arc::new(PowerPolicy::new(
+        config.power_policy.clone(),
+        config.power.actions.clone(),
+    ));
+
+    let tracker = Arc::new(ActionTracker::new(pal.clone(), config.power.delay()));
+    let keep_awake = Arc::new(KeepAwakeManager::new(pal.clone()));
+    let custom_actions = Arc::new(CustomActionRunner::new(config.custom_actions.clone()));
+    let audit = Arc::new(AuditLog::new(pal.data_directory(), &config.audit));
+    let confirmations = Arc::new(ConfirmationManager::new(
+        config.power.confirm_actions.clone(),
+        config.power.confirmation_window(),
+    ));
+
+    let events = Arc::new(EventBus::default());
+    let system_events_task = events.forward_system_events(pal.clone());
+
+    tracing::debug!("Starting scheduler...");
+    let scheduler = Arc::new(
+        Scheduler::start(
+            pal.clone(),
+            tracker.clone(),
+            audit.clone(),
+            pairing.clone(),
+            policy.clone(),
+        )
+        .await?,
+    );
+
     tracing::debug!("Binding TCP listener...");
-    let any_host = Ipv4Addr::new(0, 0, 0, 0);
-    let socket_addr = SocketAddrV4::new(any_host, 37121);
+    let socket_addr = SocketAddr::new(config.listener.address, config.listener.port);
 
-    let listener = TcpListener::bind(socket_addr).await?;
+    let listener = net::bind_listener(socket_addr)?;
     let local_addr = listener.local_addr()?;
 
     tracing::debug!("Listening on {}", local_addr);
     let discovery_manager = pal.discovery_manager();
 
-    let service_advertised = if let Err(err) = discovery_manager.advertise_service(local_addr).await
-    {
-        tracing::warn!(
-            "Failed to advertise service, discovery not available: {}",
-            err
-        );
+    let service_info = ServiceInfo {
+        socket_addr: local_addr,
+        certificate_fingerprint: identity.fingerprint().to_string(),
+        mac_addresses: wake_on_lan_addresses(discovery_manager),
+        device_uuid,
+    };
+
+    let service_advertised =
+        if let Err(err) = discovery_manager.advertise_service(&service_info).await {
+            tracing::warn!(
+                "Failed to advertise service, discovery not available: {}",
+                err
+            );
+
+            false
+        } else {
+            true
+        };
 
-        false
-    } else {
-        true
+    let (metrics_shutdown_tx, metrics_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let metrics_task = match config.metrics.address {
+        None => None,
+        Some(address) => {
+            tracing::debug!("Binding metrics listener...");
+            let server = metrics::serve(address, async {
+                let _ = metrics_shutdown_rx.await;
+            })?;
+
+            Some(tokio::spawn(async move {
+                if let Err(err) = server.await {
+                    tracing::error!("Metrics listener failed: {}", err);
+                }
+            }))
+        }
     };
 
     let incoming =
         TcpIncoming::from_listener(listener, true, None).map_err(DragonClawAgentError::Tonic)?;
 
+    // Not serving until the agent is running
+    let (mut health, health_service) = tonic_health::server::health_reporter();
+    set_health(&mut health, &ApplicationStatus::Starting).await;
+
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build()?;
+
     tracing::info!("Starting RPC...");
+    let (server_shutdown_tx, server_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let server_future = Server::builder()
-        .add_service(DragonClawAgentServer::new(DragonClawAgentImpl::new(
-            pal.clone(),
-        )))
-        .serve_with_incoming(incoming);
+        .tls_config(identity.server_tls_config())?
+        .layer(RpcMetricsLayer)
+        .add_service(health_service)
+        .add_service(reflection_service)
+        .add_service(DragonClawAgentServer::with_interceptor(
+            DragonClawAgentImpl::new(
+                pal.clone(),
+                pairing.clone(),
+                policy,
+                scheduler.clone(),
+                tracker,
+                events.clone(),
+                peers,
+                keep_awake.clone(),
+                custom_actions,
+                audit,
+                confirmations,
+            ),
+            AuthInterceptor::new(pairing),
+        ))
+        .serve_with_incoming_shutdown(incoming, async {
+            let _ = server_shutdown_rx.await;
+        });
+    tokio::pin!(server_future);
 
-    pal.status_manager()
-        .set_status(ApplicationStatus::Running)
-        .await;
+    set_status(&pal, &events, &mut health, ApplicationStatus::Running).await;
 
-    tokio::select! {
-        res = server_future => res?,
+    let server_stopped = tokio::select! {
+        res = &mut server_future => {
+            res?;
+            true
+        },
         _ = shutdown_fut => {
             tracing::info!("Received shutdown request, shutting down...");
+            false
+        }
+    };
+
+    // This also ends the event streams of connected clients
+    set_status(&pal, &events, &mut health, ApplicationStatus::Stopping).await;
+
+    if !server_stopped {
+        let _ = server_shutdown_tx.send(());
+
+        match tokio::time::timeout(SERVER_SHUTDOWN_TIMEOUT, server_future).await {
+            Ok(res) => res?,
+            Err(_) => tracing::warn!("Timed out waiting for RPC calls to finish"),
         }
     }
 
-    pal.status_manager()
-        .set_status(ApplicationStatus::Stopping)
-        .await;
+    system_events_task.abort();
+
+    if let Some(metrics_task) = metrics_task {
+        let _ = metrics_shutdown_tx.send(());
+        let _ = metrics_task.await;
+    }
+
+    scheduler.stop();
+    keep_awake.release_all();
 
     if service_advertised {
         if let Err(err) = discovery_manager.stop_advertising_service().await {
@@ -145,3 +326,57 @@ async fn runner(
 
     Ok(())
 }
+
+/// Reports the status to the platform, to clients watching events and to health checks.
+async fn set_status(
+    pal: &pal::PlatformAbstraction,
+    events: &EventBus,
+    health: &mut HealthReporter,
+    status: ApplicationStatus,
+) {
+    events.publish(AgentEventKind::StatusChanged(AgentStatus::from(&status)));
+    set_health(health, &status).await;
+    pal.status_manager().set_status(status).await;
+}
+
+/// Reports the status to `grpc.health.v1.Health`, both for the server and the agent service.
+async fn set_health(health: &mut HealthReporter, status: &ApplicationStatus) {
+    let serving_status = match status {
+        ApplicationStatus::Running => ServingStatus::Serving,
+        _ => ServingStatus::NotServing,
+    };
+
+    health.set_service_status("", serving_status).await;
+    health
+        .set_service_status(
+            <DragonClawAgentServer<DragonClawAgentImpl> as NamedService>::NAME,
+            serving_status,
+        )
+        .await;
+}
+
+/// Collects the hardware addresses clients can use to wake the machine.
+fn wake_on_lan_addresses(discovery_manager: &impl DiscoveryManager) -> Vec<MacAddress> {
+    let interfaces = match discovery_manager.network_interfaces() {
+        Ok(v) => v,
+        Err(err) => {
+            tracing::warn!("Failed to retrieve network interfaces: {}", err);
+            return Vec::new();
+        }
+    };
+
+    let mut addresses = Vec::new();
+    for interface in interfaces {
+        // Interfaces without addresses can't be reached anyway
+        if interface.addresses.is_empty() {
+            continue;
+        }
+
+        match interface.mac_address {
+            Some(mac) if !mac.is_zero() && !addresses.contains(&mac) => addresses.push(mac),
+            _ => {}
+        }
+    }
+
+    addresses
+}
@@ -0,0 +1,961 @@
+use crate::pal::discovery::ServiceInfo;
+use futures::stream::{FuturesUnordered, StreamExt};
+use packet::{Message, Name, Question, Record, RecordData, CLASS_IN, TYPE_ANY};
+use rand::Rng;
+use socket2::{Domain, InterfaceIndexOrAddress, Protocol, Socket};
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+mod packet;
+
+const MDNS_PORT: u16 = 5353;
+const MDNS_MULTICAST_IPV4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_MULTICAST_IPV6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0x00fb);
+
+/// Domain all multicast DNS names live in.
+const MDNS_DOMAIN: &str = "local";
+
+/// Name browsers query to enumerate the service types on the network.
+const MDNS_SERVICES_NAME: &str = "_services._dns-sd._udp.local";
+
+/// Lifetime of records naming a host, which become stale when it goes away.
+const MDNS_HOST_RECORD_TTL: u32 = 120;
+
+/// Lifetime of all other records.
+const MDNS_OTHER_RECORD_TTL: u32 = 4500;
+
+/// Lifetime of records in responses to legacy unicast queries.
+const MDNS_LEGACY_UNICAST_TTL: u32 = 10;
+
+/// Multicast DNS messages may be up to this large.
+const MDNS_MAX_MESSAGE_SIZE: usize = 9000;
+
+/// Number of probes sent before the names are considered to be ours.
+const MDNS_PROBES: u32 = 3;
+
+/// Time between the probes, and after the last one.
+const MDNS_PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Probing starts after a random delay up to this long, so hosts don't probe in lockstep.
+const MDNS_MAX_PROBE_DELAY: Duration = Duration::from_millis(250);
+
+/// Time probing is delayed after losing a tiebreak against a simultaneous probe.
+const MDNS_TIEBREAK_DELAY: Duration = Duration::from_secs(1);
+
+/// After this many conflicts within [`MDNS_CONFLICT_WINDOW`], probing is slowed down.
+const MDNS_MAX_QUICK_CONFLICTS: usize = 15;
+const MDNS_CONFLICT_WINDOW: Duration = Duration::from_secs(10);
+const MDNS_CONFLICT_PROBE_DELAY: Duration = Duration::from_secs(5);
+
+/// Number of unsolicited responses announcing the records, and the time between the first two.
+const MDNS_ANNOUNCEMENTS: u32 = 2;
+const MDNS_ANNOUNCEMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Multicast responses containing shared records are delayed randomly within this range, so
+/// the responses of several hosts don't collide.
+const MDNS_MIN_SHARED_RESPONSE_DELAY: Duration = Duration::from_millis(20);
+const MDNS_MAX_SHARED_RESPONSE_DELAY: Duration = Duration::from_millis(120);
+
+/// Number of received messages which may wait for the responder.
+const MDNS_RECEIVE_QUEUE: usize = 64;
+
+/// Number of interface changes which may wait for the responder.
+const MDNS_UPDATE_QUEUE: usize = 4;
+
+/// Addresses of a network interface the service is announced on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddresses {
+    /// Index of the interface.
+    pub index: u32,
+
+    /// The addresses of the interface.
+    pub addresses: Vec<IpAddr>,
+}
+
+/// A multicast DNS responder announcing the service via DNS-SD, for when there is no system
+/// responder to register it with.
+///
+/// The host and service instance names are claimed by probing first, a number is appended in
+/// case somebody else uses them already.
+#[derive(Debug)]
+pub struct MdnsResponder {
+    service_addr: SocketAddr,
+    interfaces: Vec<InterfaceAddresses>,
+    updates: mpsc::Sender<Vec<InterfaceSocket>>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl MdnsResponder {
+    /// Starts announcing the service as `instance` of `service_type` (such as
+    /// `_dragon-claw._tcp`), running on `host`, on the given interfaces.
+    pub fn start(
+        instance: &str,
+        host: &str,
+        service_type: &str,
+        service: &ServiceInfo,
+        interfaces: Vec<InterfaceAddresses>,
+    ) -> Result<Self, std::io::Error> {
+        let service_addr = service.socket_addr;
+        let interfaces = Self::announced_addresses(service_addr, interfaces);
+        let sockets = Self::bind_sockets(&interfaces);
+
+        if sockets.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrNotAvailable,
+                "No local addresses to bind to",
+            ));
+        }
+
+        // Host names are single labels, the rest of a fully qualified name is dropped
+        let host = host.split('.').next().unwrap_or(host);
+
+        let (packet_sender, packets) = mpsc::channel(MDNS_RECEIVE_QUEUE);
+        let mut responder = Responder {
+            service_name: Name::parse(&format!("{}.{}", service_type, MDNS_DOMAIN)),
+            base_instance: instance.to_string(),
+            base_host: host.to_string(),
+            instance: instance.to_string(),
+            host: host.to_string(),
+            instance_renames: 0,
+            host_renames: 0,
+            port: service_addr.port(),
+            txt: service
+                .properties()
+                .into_iter()
+                .map(|(key, value)| format!("{}={}", key, value).into_bytes())
+                .collect(),
+            sockets,
+            receive_tasks: Vec::new(),
+            packet_sender,
+            phase: Phase::Probing(0),
+            recent_conflicts: Vec::new(),
+        };
+        responder.spawn_receivers();
+
+        let (updates, update_receiver) = mpsc::channel(MDNS_UPDATE_QUEUE);
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        let task = tokio::spawn(responder.run(packets, update_receiver, shutdown_receiver));
+
+        Ok(Self {
+            service_addr,
+            interfaces,
+            updates,
+            shutdown,
+            task,
+        })
+    }
+
+    /// Follows changed interfaces or addresses.
+    ///
+    /// Records which are no longer valid are withdrawn, then the names are probed for and
+    /// announced again on the new interfaces. Nothing happens if the announced addresses did not
+    /// change.
+    pub async fn refresh_interfaces(&mut self, interfaces: Vec<InterfaceAddresses>) {
+        let interfaces = Self::announced_addresses(self.service_addr, interfaces);
+        if interfaces == self.interfaces {
+            return;
+        }
+
+        // Without any sockets, the responder waits for interfaces to come back
+        let sockets = Self::bind_sockets(&interfaces);
+        if sockets.is_empty() {
+            tracing::warn!("No local addresses left to announce the service on via mDNS");
+        }
+        self.interfaces = interfaces;
+
+        // Only fails if the responder is gone already
+        let _ = self.updates.send(sockets).await;
+    }
+
+    /// Stops the responder, after telling others the records are gone.
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+
+    /// Keeps the addresses the service is reachable on.
+    ///
+    /// An unspecified IPv6 address means the service is reachable via both address families.
+    fn announced_addresses(
+        service_addr: SocketAddr,
+        interfaces: Vec<InterfaceAddresses>,
+    ) -> Vec<InterfaceAddresses> {
+        interfaces
+            .into_iter()
+            .map(|mut interface| {
+                interface.addresses.retain(|v| {
+                    !v.is_loopback()
+                        && match service_addr.ip() {
+                            ip if ip.is_unspecified() => service_addr.is_ipv6() || v.is_ipv4(),
+                            ip => ip == *v,
+                        }
+                });
+                interface
+            })
+            .filter(|interface| !interface.addresses.is_empty())
+            .collect()
+    }
+
+    /// Binds a socket per interface and address family.
+    fn bind_sockets(interfaces: &[InterfaceAddresses]) -> Vec<InterfaceSocket> {
+        interfaces
+            .iter()
+            .flat_map(|interface| {
+                let ipv4 = interface.addresses.iter().find_map(|v| match v {
+                    IpAddr::V4(v) => Some(*v),
+                    IpAddr::V6(_) => None,
+                });
+                let has_ipv6 = interface.addresses.iter().any(IpAddr::is_ipv6);
+                let addresses = Arc::new(interface.addresses.clone());
+
+                let ipv4_socket = ipv4.and_then(|v| {
+                    InterfaceSocket::bind_ipv4(interface.index, v, addresses.clone())
+                });
+                let ipv6_socket = has_ipv6
+                    .then(|| InterfaceSocket::bind_ipv6(interface.index, addresses.clone()))
+                    .flatten();
+
+                ipv4_socket.into_iter().chain(ipv6_socket)
+            })
+            .collect()
+    }
+}
+
+/// Socket receiving and sending messages on a single interface in one address family.
+#[derive(Debug)]
+struct InterfaceSocket {
+    index: u32,
+    is_ipv4: bool,
+    addresses: Arc<Vec<IpAddr>>,
+    socket: Arc<UdpSocket>,
+}
+
+impl InterfaceSocket {
+    fn bind_ipv4(index: u32, address: Ipv4Addr, addresses: Arc<Vec<IpAddr>>) -> Option<Self> {
+        let do_bind = move || -> Result<Socket, std::io::Error> {
+            let socket = Socket::new(Domain::IPV4, socket2::Type::DGRAM, Some(Protocol::UDP))?;
+
+            // Every socket only receives the messages from its own interface
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+            socket.set_multicast_all_v4(false)?;
+            socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+            socket.join_multicast_v4_n(
+                &MDNS_MULTICAST_IPV4,
+                &InterfaceIndexOrAddress::Index(index),
+            )?;
+
+            // Receivers ignore messages which may have been forwarded by a router
+            socket.set_multicast_if_v4(&address)?;
+            socket.set_multicast_loop_v4(false)?;
+            socket.set_multicast_ttl_v4(255)?;
+            socket.set_ttl(255)?;
+
+            Ok(socket)
+        };
+
+        Self::finish_bind(do_bind(), index, true, addresses)
+    }
+
+    fn bind_ipv6(index: u32, addresses: Arc<Vec<IpAddr>>) -> Option<Self> {
+        let do_bind = move || -> Result<Socket, std::io::Error> {
+            let socket = Socket::new(Domain::IPV6, socket2::Type::DGRAM, Some(Protocol::UDP))?;
+
+            // Every socket only receives the messages from its own interface
+            socket.set_only_v6(true)?;
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+            socket.set_multicast_all_v6(false)?;
+            socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+            socket.join_multicast_v6(&MDNS_MULTICAST_IPV6, index)?;
+
+            // Receivers ignore messages which may have been forwarded by a router
+            socket.set_multicast_if_v6(index)?;
+            socket.set_multicast_loop_v6(false)?;
+            socket.set_multicast_hops_v6(255)?;
+            socket.set_unicast_hops_v6(255)?;
+
+            Ok(socket)
+        };
+
+        Self::finish_bind(do_bind(), index, false, addresses)
+    }
+
+    fn finish_bind(
+        socket: Result<Socket, std::io::Error>,
+        index: u32,
+        is_ipv4: bool,
+        addresses: Arc<Vec<IpAddr>>,
+    ) -> Option<Self> {
+        let do_convert = move || -> Result<UdpSocket, std::io::Error> {
+            let socket = socket?;
+            socket.set_nonblocking(true)?;
+            UdpSocket::from_std(std::net::UdpSocket::from(socket))
+        };
+
+        match do_convert() {
+            Ok(socket) => Some(Self {
+                index,
+                is_ipv4,
+                addresses,
+                socket: Arc::new(socket),
+            }),
+            Err(err) => {
+                tracing::warn!("Failed to bind mDNS socket on interface {}: {}", index, err);
+                None
+            }
+        }
+    }
+
+    /// Address multicast messages are sent to on this interface.
+    fn multicast_addr(&self) -> SocketAddr {
+        match self.is_ipv4 {
+            true => SocketAddrV4::new(MDNS_MULTICAST_IPV4, MDNS_PORT).into(),
+            false => SocketAddrV6::new(MDNS_MULTICAST_IPV6, MDNS_PORT, 0, self.index).into(),
+        }
+    }
+}
+
+/// A message received on one of the interface sockets.
+#[derive(Debug)]
+struct Packet {
+    socket: usize,
+    source: SocketAddr,
+    message: Message,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Probing whether the names are in use, with the number of probes sent so far.
+    Probing(u32),
+    /// Announcing the records, with the number of announcements sent so far.
+    Announcing(u32),
+    /// The records have been announced, only queries are answered.
+    Announced,
+}
+
+#[derive(Debug)]
+struct Responder {
+    service_name: Name,
+    base_instance: String,
+    base_host: String,
+    instance: String,
+    host: String,
+    instance_renames: u32,
+    host_renames: u32,
+    port: u16,
+    txt: Vec<Vec<u8>>,
+    sockets: Vec<InterfaceSocket>,
+    receive_tasks: Vec<JoinHandle<()>>,
+    packet_sender: mpsc::Sender<Packet>,
+    phase: Phase,
+    recent_conflicts: Vec<Instant>,
+}
+
+impl Responder {
+    fn instance_name(&self) -> Name {
+        self.service_name.prepend(&self.instance)
+    }
+
+    fn host_name(&self) -> Name {
+        Name::new([self.host.as_str(), MDNS_DOMAIN])
+    }
+
+    /// The records of the service as announced on an interface with the given addresses.
+    ///
+    /// Only the addresses of the interface a record is sent on are announced, as addresses of
+    /// other interfaces may not be reachable from there.
+    fn records(&self, addresses: &[IpAddr]) -> Vec<Record> {
+        let instance_name = self.instance_name();
+        let host_name = self.host_name();
+
+        let mut records = vec![
+            Record {
+                name: self.service_name.clone(),
+                cache_flush: false,
+                ttl: MDNS_OTHER_RECORD_TTL,
+                data: RecordData::Ptr(instance_name.clone()),
+            },
+            Record {
+                name: Name::parse(MDNS_SERVICES_NAME),
+                cache_flush: false,
+                ttl: MDNS_OTHER_RECORD_TTL,
+                data: RecordData::Ptr(self.service_name.clone()),
+            },
+            Record {
+                name: instance_name.clone(),
+                cache_flush: true,
+                ttl: MDNS_HOST_RECORD_TTL,
+                data: RecordData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: self.port,
+                    target: host_name.clone(),
+                },
+            },
+            Record {
+                name: instance_name,
+                cache_flush: true,
+                ttl: MDNS_OTHER_RECORD_TTL,
+                data: RecordData::Txt(self.txt.clone()),
+            },
+        ];
+
+        records.extend(addresses.iter().map(|address| Record {
+            name: host_name.clone(),
+            cache_flush: true,
+            ttl: MDNS_HOST_RECORD_TTL,
+            data: match address {
+                IpAddr::V4(v) => RecordData::A(*v),
+                IpAddr::V6(v) => RecordData::Aaaa(*v),
+            },
+        }));
+
+        records
+    }
+
+    /// Tests whether the record is one of ours, as announced on any interface.
+    fn is_own_record(&self, record: &Record) -> bool {
+        self.sockets.iter().any(|socket| {
+            self.records(&socket.addresses)
+                .iter()
+                .any(|v| v.same_as(record))
+        })
+    }
+
+    async fn run(
+        mut self,
+        mut packets: mpsc::Receiver<Packet>,
+        mut updates: mpsc::Receiver<Vec<InterfaceSocket>>,
+        mut shutdown: oneshot::Receiver<()>,
+    ) {
+        let mut deadline = Some(Instant::now() + Self::probe_delay());
+        let mut pending_responses = FuturesUnordered::new();
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                Some(sockets) = updates.recv() => {
+                    self.replace_sockets(sockets, &mut packets).await;
+
+                    // The names are claimed again on the new interfaces
+                    self.phase = Phase::Probing(0);
+                    deadline = (!self.sockets.is_empty())
+                        .then(|| Instant::now() + Self::probe_delay());
+                }
+                Some(packet) = packets.recv() => {
+                    if let Some(conflict) = self.find_conflict(&packet) {
+                        // Probing starts over, possibly with new names
+                        let delay = self.handle_conflict(conflict);
+                        self.phase = Phase::Probing(0);
+                        deadline = Some(Instant::now() + delay);
+                    } else if let Some((response, destination, delay)) = self.answer(&packet) {
+                        let socket = &self.sockets[packet.socket];
+                        pending_responses.push(Self::send_delayed(
+                            socket.socket.clone(),
+                            socket.index,
+                            response,
+                            destination,
+                            delay,
+                        ));
+                    }
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() => {
+                    deadline = self.step().await.map(|v| Instant::now() + v);
+                }
+                // Drive the responses waiting for their delay
+                Some(()) = pending_responses.next(), if !pending_responses.is_empty() => {}
+            }
+        }
+
+        for task in &self.receive_tasks {
+            task.abort();
+        }
+
+        // Records that have never been announced need no goodbye
+        if self.has_announced() {
+            self.send_goodbyes().await;
+        }
+    }
+
+    fn has_announced(&self) -> bool {
+        matches!(self.phase, Phase::Announcing(1..) | Phase::Announced)
+    }
+
+    /// Starts receiving messages on every socket.
+    fn spawn_receivers(&mut self) {
+        self.receive_tasks = self
+            .sockets
+            .iter()
+            .enumerate()
+            .map(|(index, socket)| {
+                tokio::spawn(Self::receive_loop(
+                    index,
+                    socket.socket.clone(),
+                    self.packet_sender.clone(),
+                ))
+            })
+            .collect();
+    }
+
+    /// Moves over to the sockets of changed interfaces, withdrawing the records which are gone.
+    async fn replace_sockets(
+        &mut self,
+        sockets: Vec<InterfaceSocket>,
+        packets: &mut mpsc::Receiver<Packet>,
+    ) {
+        for task in self.receive_tasks.drain(..) {
+            task.abort();
+            let _ = task.await;
+        }
+
+        // Received messages refer to the old sockets by their index
+        while packets.try_recv().is_ok() {}
+
+        if self.has_announced() {
+            self.send_withdrawals(&sockets).await;
+        }
+
+        self.sockets = sockets;
+        self.spawn_receivers();
+    }
+
+    /// Random delay before probing starts.
+    fn probe_delay() -> Duration {
+        rand::thread_rng().gen_range(Duration::ZERO..=MDNS_MAX_PROBE_DELAY)
+    }
+
+    /// Advances probing and announcing, returns the time until the next step, if any.
+    async fn step(&mut self) -> Option<Duration> {
+        match self.phase {
+            Phase::Probing(sent) if sent < MDNS_PROBES => {
+                self.send_probes().await;
+                self.phase = Phase::Probing(sent + 1);
+                Some(MDNS_PROBE_INTERVAL)
+            }
+            Phase::Probing(_) => {
+                tracing::info!(
+                    "Claimed mDNS names {} and {}",
+                    self.instance_name(),
+                    self.host_name()
+                );
+                self.phase = Phase::Announcing(0);
+                Some(Duration::ZERO)
+            }
+            Phase::Announcing(sent) if sent < MDNS_ANNOUNCEMENTS => {
+                self.send_announcements(false).await;
+                self.phase = Phase::Announcing(sent + 1);
+
+                // The interval doubles with every announcement
+                Some(MDNS_ANNOUNCEMENT_INTERVAL * 2u32.pow(sent))
+            }
+            Phase::Announcing(_) | Phase::Announced => {
+                self.phase = Phase::Announced;
+                None
+            }
+        }
+    }
+
+    /// Checks a received message for somebody else using our names.
+    fn find_conflict(&self, packet: &Packet) -> Option<Conflict> {
+        match packet.message.is_response {
+            true => self.find_conflicting_record(&packet.message),
+            false => self.lost_tiebreak(packet),
+        }
+    }
+
+    /// Checks a response for records conflicting with ours.
+    fn find_conflicting_record(&self, message: &Message) -> Option<Conflict> {
+        let instance_name = self.instance_name();
+        let host_name = self.host_name();
+
+        for record in message.answers.iter().chain(&message.additionals) {
+            let conflict = if record.name == instance_name {
+                Conflict::Instance
+            } else if record.name == host_name {
+                Conflict::Host
+            } else {
+                continue;
+            };
+
+            // We may receive our own records on another interface connected to the same link
+            if self.is_own_record(record) {
+                continue;
+            }
+
+            // While probing, any answer means the name is taken, later only records of the
+            // types we claimed to be the only ones of conflict
+            let is_conflict = match self.phase {
+                Phase::Probing(_) => true,
+                _ => self.sockets.iter().any(|socket| {
+                    self.records(&socket.addresses).iter().any(|v| {
+                        v.cache_flush
+                            && v.name == record.name
+                            && v.data.record_type() == record.data.record_type()
+                    })
+                }),
+            };
+
+            if is_conflict {
+                tracing::debug!("Received conflicting mDNS record {:?}", record);
+                return Some(conflict);
+            }
+        }
+
+        None
+    }
+
+    /// Checks whether a simultaneous probe for one of our names wins the tiebreak.
+    fn lost_tiebreak(&self, packet: &Packet) -> Option<Conflict> {
+        if !matches!(self.phase, Phase::Probing(1..)) {
+            return None;
+        }
+
+        let ours = self.records(&self.sockets[packet.socket].addresses);
+        for name in [self.instance_name(), self.host_name()] {
+            if !packet.message.questions.iter().any(|v| v.name == name) {
+                continue;
+            }
+
+            let theirs = packet
+                .message
+                .authorities
+                .iter()
+                .filter(|v| v.name == name)
+                .collect::<Vec<_>>();
+
+            // Our own probes may arrive on another interface connected to the same link
+            if theirs.is_empty() || theirs.iter().all(|v| self.is_own_record(v)) {
+                continue;
+            }
+
+            // Both probe for the same name, the one with the lexicographically later data wins
+            let ours = ours.iter().filter(|v| v.name == name).collect::<Vec<_>>();
+            if Self::compare_probes(&ours, &theirs) == Ordering::Less {
+                tracing::debug!("Lost mDNS probe tiebreak for {} to {}", name, packet.source);
+                return Some(Conflict::Tiebreak);
+            }
+        }
+
+        None
+    }
+
+    /// Compares the records of two simultaneous probes for the same name.
+    fn compare_probes(ours: &[&Record], theirs: &[&Record]) -> Ordering {
+        let sorted = |records: &[&Record]| {
+            let mut records = records
+                .iter()
+                .map(|v| {
+                    let mut data = Vec::new();
+                    v.data.encode(&mut data);
+                    (v.data.record_type(), data)
+                })
+                .collect::<Vec<_>>();
+            records.sort();
+            records
+        };
+
+        // The probe with more records wins if all others are equal
+        sorted(ours).cmp(&sorted(theirs))
+    }
+
+    /// Renames what the conflict is about, returns the delay before probing again.
+    fn handle_conflict(&mut self, conflict: Conflict) -> Duration {
+        let now = Instant::now();
+        self.recent_conflicts
+            .retain(|v| now.duration_since(*v) < MDNS_CONFLICT_WINDOW);
+        self.recent_conflicts.push(now);
+
+        match conflict {
+            // Only the loser of a tiebreak keeps probing with its names
+            Conflict::Tiebreak => return MDNS_TIEBREAK_DELAY,
+            // Names are only changed when somebody else uses them while probing, otherwise we
+            // probe again to find out whether the other one is still around
+            _ if !matches!(self.phase, Phase::Probing(_)) => {
+                tracing::info!("Received conflicting mDNS records, probing again");
+            }
+            Conflict::Instance => {
+                self.instance_renames += 1;
+                let instance = format!("{} ({})", self.base_instance, self.instance_renames + 1);
+                tracing::info!(
+                    "mDNS service name {} is taken, trying {}",
+                    self.instance,
+                    instance
+                );
+                self.instance = instance;
+            }
+            Conflict::Host => {
+                self.host_renames += 1;
+                let host = format!("{}-{}", self.base_host, self.host_renames + 1);
+                tracing::info!("mDNS host name {} is taken, trying {}", self.host, host);
+                self.host = host;
+            }
+        }
+
+        match self.recent_conflicts.len() > MDNS_MAX_QUICK_CONFLICTS {
+            true => MDNS_CONFLICT_PROBE_DELAY,
+            false => Self::probe_delay(),
+        }
+    }
+
+    /// Builds the response to a query along with where and when to send it, if there is
+    /// anything to answer.
+    fn answer(&self, packet: &Packet) -> Option<(Message, SocketAddr, Duration)> {
+        // Names are only used once they have been claimed
+        if packet.message.is_response || matches!(self.phase, Phase::Probing(_)) {
+            return None;
+        }
+
+        let socket = &self.sockets[packet.socket];
+        let records = self.records(&socket.addresses);
+        let query = &packet.message;
+
+        let mut answers: Vec<Record> = Vec::new();
+        let mut unicast = true;
+        for question in &query.questions {
+            let matching = records
+                .iter()
+                .filter(|v| question.matches(v))
+                .collect::<Vec<_>>();
+            if matching.is_empty() {
+                continue;
+            }
+
+            unicast &= question.unicast_response;
+            for record in matching {
+                // Records the querier knows already with at least half of their lifetime left
+                // are not sent again
+                let is_known = query
+                    .answers
+                    .iter()
+                    .any(|v| v.same_as(record) && v.ttl >= record.ttl / 2);
+                if !is_known && !answers.iter().any(|v| v.same_as(record)) {
+                    answers.push(record.clone());
+                }
+            }
+        }
+
+        if answers.is_empty() {
+            return None;
+        }
+
+        // Save the querier from asking for what it is going to need next
+        let mut additionals: Vec<Record> = Vec::new();
+        for answer in &answers {
+            let related = match &answer.data {
+                RecordData::Ptr(target) if answer.name == self.service_name => {
+                    let host_name = self.host_name();
+                    records
+                        .iter()
+                        .filter(|v| v.name == *target || v.name == host_name)
+                        .collect::<Vec<_>>()
+                }
+                RecordData::Srv { target, .. } => {
+                    records.iter().filter(|v| v.name == *target).collect()
+                }
+                _ => continue,
+            };
+
+            for record in related {
+                if !answers
+                    .iter()
+                    .chain(&additionals)
+                    .any(|v| v.same_as(record))
+                {
+                    additionals.push(record.clone());
+                }
+            }
+        }
+
+        // Legacy resolvers sending a one-shot query from another port expect a plain DNS response
+        if packet.source.port() != MDNS_PORT {
+            let mut response = Message::response(query.id, answers, additionals);
+            response.questions = query.questions.clone();
+            for record in response.answers.iter_mut().chain(&mut response.additionals) {
+                record.cache_flush = false;
+                record.ttl = record.ttl.min(MDNS_LEGACY_UNICAST_TTL);
+            }
+
+            return Some((response, packet.source, Duration::ZERO));
+        }
+
+        // Shared records may be answered by several hosts, which should not do so all at once
+        let destination = match unicast {
+            true => packet.source,
+            false => socket.multicast_addr(),
+        };
+        let delay = match !unicast && answers.iter().any(|v| !v.cache_flush) {
+            true => rand::thread_rng()
+                .gen_range(MDNS_MIN_SHARED_RESPONSE_DELAY..=MDNS_MAX_SHARED_RESPONSE_DELAY),
+            false => Duration::ZERO,
+        };
+
+        Some((
+            Message::response(0, answers, additionals),
+            destination,
+            delay,
+        ))
+    }
+
+    /// Asks whether anybody uses our names, proposing our records.
+    async fn send_probes(&self) {
+        for socket in &self.sockets {
+            let questions = [self.instance_name(), self.host_name()]
+                .into_iter()
+                .map(|name| Question {
+                    name,
+                    record_type: TYPE_ANY,
+                    class: CLASS_IN,
+                    unicast_response: true,
+                })
+                .collect();
+
+            // Only the records we claim to be the only ones of are proposed
+            let authorities = self
+                .records(&socket.addresses)
+                .into_iter()
+                .filter(|v| v.cache_flush)
+                .collect();
+
+            let probe = Message::query(questions, authorities);
+            Self::send(
+                &socket.socket,
+                socket.index,
+                &probe,
+                socket.multicast_addr(),
+            )
+            .await;
+        }
+    }
+
+    /// Sends all records to everybody, with a TTL of 0 to say goodbye.
+    async fn send_announcements(&self, goodbye: bool) {
+        for socket in &self.sockets {
+            let mut records = self.records(&socket.addresses);
+            if goodbye {
+                for record in &mut records {
+                    record.ttl = 0;
+                }
+            }
+
+            let announcement = Message::response(0, records, Vec::new());
+            Self::send(
+                &socket.socket,
+                socket.index,
+                &announcement,
+                socket.multicast_addr(),
+            )
+            .await;
+        }
+    }
+
+    /// Says goodbye to the records of the current sockets which the new ones don't announce.
+    async fn send_withdrawals(&self, new_sockets: &[InterfaceSocket]) {
+        for socket in &self.sockets {
+            let remaining = new_sockets
+                .iter()
+                .find(|v| v.index == socket.index && v.is_ipv4 == socket.is_ipv4)
+                .map(|v| self.records(&v.addresses))
+                .unwrap_or_default();
+
+            let gone = self
+                .records(&socket.addresses)
+                .into_iter()
+                .filter(|record| !remaining.iter().any(|v| v.same_as(record)))
+                .map(|record| Record { ttl: 0, ..record })
+                .collect::<Vec<_>>();
+            if gone.is_empty() {
+                continue;
+            }
+
+            let goodbye = Message::response(0, gone, Vec::new());
+            Self::send(
+                &socket.socket,
+                socket.index,
+                &goodbye,
+                socket.multicast_addr(),
+            )
+            .await;
+        }
+    }
+
+    async fn send_goodbyes(&self) {
+        self.send_announcements(true).await;
+        tracing::debug!(
+            "Sent mDNS goodbyes for {} and {}",
+            self.instance_name(),
+            self.host_name()
+        );
+    }
+
+    async fn send(socket: &UdpSocket, index: u32, message: &Message, destination: SocketAddr) {
+        let data = message.encode();
+        if let Err(err) = socket.send_to(&data, destination).await {
+            tracing::warn!(
+                "Failed to send mDNS message on interface {} to {}: {}",
+                index,
+                destination,
+                err
+            );
+        }
+    }
+
+    async fn send_delayed(
+        socket: Arc<UdpSocket>,
+        index: u32,
+        message: Message,
+        destination: SocketAddr,
+        delay: Duration,
+    ) {
+        tokio::time::sleep(delay).await;
+        Self::send(&socket, index, &message, destination).await;
+    }
+
+    async fn receive_loop(index: usize, socket: Arc<UdpSocket>, packets: mpsc::Sender<Packet>) {
+        let mut buffer = vec![0u8; MDNS_MAX_MESSAGE_SIZE];
+
+        loop {
+            let (len, source) = match socket.recv_from(&mut buffer).await {
+                Ok(v) => v,
+                Err(err) => {
+                    tracing::warn!("Failed to receive mDNS message: {}", err);
+                    continue;
+                }
+            };
+
+            // Malformed messages are silently ignored
+            let Some(message) = Message::decode(&buffer[..len]) else {
+                continue;
+            };
+
+            let packet = Packet {
+                socket: index,
+                source,
+                message,
+            };
+            if packets.send(packet).await.is_err() {
+                // The responder is gone
+                return;
+            }
+        }
+    }
+}
+
+/// What a conflict is about.
+#[derive(Debug, Clone, Copy)]
+enum Conflict {
+    /// Somebody else uses the service instance name.
+    Instance,
+    /// Somebody else uses the host name.
+    Host,
+    /// Somebody else probes for one of the names at the same time and wins.
+    Tiebreak,
+}
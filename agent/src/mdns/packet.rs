@@ -0,0 +1,653 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_ANY: u16 = 255;
+
+/// Top bit of the class of questions, asking for a unicast response.
+const UNICAST_RESPONSE_BIT: u16 = 0x8000;
+
+/// Top bit of the class of records, telling caches to drop other records of the same set.
+const CACHE_FLUSH_BIT: u16 = 0x8000;
+
+/// Flags of an authoritative response.
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// Mask of the query/response bit.
+const FLAG_QR: u16 = 0x8000;
+
+/// Mask of the opcode and response code, which have to be zero for mDNS.
+const FLAGS_OPCODE_RCODE: u16 = 0x780f;
+
+/// Compression pointers are only followed this often, which rules out loops.
+const MAX_COMPRESSION_POINTERS: usize = 16;
+
+/// A domain name, compared case-insensitively.
+#[derive(Debug, Clone)]
+pub struct Name {
+    labels: Vec<String>,
+}
+
+impl Name {
+    /// Creates a name from its labels.
+    pub fn new<I, S>(labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            labels: labels.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Creates a name from dotted notation, labels can't contain dots this way.
+    pub fn parse(name: &str) -> Self {
+        Self::new(name.split('.').filter(|v| !v.is_empty()))
+    }
+
+    /// Prepends a label to the name.
+    pub fn prepend(&self, label: &str) -> Self {
+        let mut labels = Vec::with_capacity(self.labels.len() + 1);
+        labels.push(label.to_string());
+        labels.extend(self.labels.iter().cloned());
+
+        Self { labels }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        for label in &self.labels {
+            // Labels are limited to 63 bytes, longer ones are cut
+            let label = &label.as_bytes()[..label.len().min(63)];
+            out.push(label.len() as u8);
+            out.extend_from_slice(label);
+        }
+        out.push(0);
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(&other.labels)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for label in &self.labels {
+            write!(f, "{}.", label)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Question {
+    pub name: Name,
+    pub record_type: u16,
+    pub class: u16,
+    pub unicast_response: bool,
+}
+
+impl Question {
+    /// Tests whether the question asks for the given record.
+    pub fn matches(&self, record: &Record) -> bool {
+        (self.record_type == TYPE_ANY || self.record_type == record.data.record_type())
+            && (self.class == CLASS_IN || self.class == CLASS_ANY)
+            && self.name == record.name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ptr(Name),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+    },
+    Txt(Vec<Vec<u8>>),
+    Other {
+        record_type: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl RecordData {
+    pub fn record_type(&self) -> u16 {
+        match self {
+            RecordData::A(_) => TYPE_A,
+            RecordData::Aaaa(_) => TYPE_AAAA,
+            RecordData::Ptr(_) => TYPE_PTR,
+            RecordData::Srv { .. } => TYPE_SRV,
+            RecordData::Txt(_) => TYPE_TXT,
+            RecordData::Other { record_type, .. } => *record_type,
+        }
+    }
+
+    /// Encodes the data without name compression, which is also the form records are compared
+    /// in when resolving conflicts.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            RecordData::A(addr) => out.extend_from_slice(&addr.octets()),
+            RecordData::Aaaa(addr) => out.extend_from_slice(&addr.octets()),
+            RecordData::Ptr(name) => name.encode(out),
+            RecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                out.extend_from_slice(&priority.to_be_bytes());
+                out.extend_from_slice(&weight.to_be_bytes());
+                out.extend_from_slice(&port.to_be_bytes());
+                target.encode(out);
+            }
+            RecordData::Txt(entries) if entries.is_empty() => {
+                // An empty TXT record still has to contain a single empty string
+                out.push(0);
+            }
+            RecordData::Txt(entries) => {
+                for entry in entries {
+                    let entry = &entry[..entry.len().min(255)];
+                    out.push(entry.len() as u8);
+                    out.extend_from_slice(entry);
+                }
+            }
+            RecordData::Other { data, .. } => out.extend_from_slice(data),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: Name,
+    pub cache_flush: bool,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+impl Record {
+    /// Tests whether both records have the same name, type and data, regardless of the TTL.
+    pub fn same_as(&self, other: &Record) -> bool {
+        self.name == other.name && self.data == other.data
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.name.encode(out);
+        out.extend_from_slice(&self.data.record_type().to_be_bytes());
+
+        let class = match self.cache_flush {
+            true => CLASS_IN | CACHE_FLUSH_BIT,
+            false => CLASS_IN,
+        };
+        out.extend_from_slice(&class.to_be_bytes());
+        out.extend_from_slice(&self.ttl.to_be_bytes());
+
+        // Reserve the length and fill it in after the data
+        let length_index = out.len();
+        out.extend_from_slice(&[0, 0]);
+        self.data.encode(out);
+        let length = (out.len() - length_index - 2) as u16;
+        out[length_index..length_index + 2].copy_from_slice(&length.to_be_bytes());
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    pub id: u16,
+    pub is_response: bool,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    /// Creates a query, such as a probe.
+    pub fn query(questions: Vec<Question>, authorities: Vec<Record>) -> Self {
+        Self {
+            questions,
+            authorities,
+            ..Default::default()
+        }
+    }
+
+    /// Creates an authoritative response.
+    pub fn response(id: u16, answers: Vec<Record>, additionals: Vec<Record>) -> Self {
+        Self {
+            id,
+            is_response: true,
+            answers,
+            additionals,
+            ..Default::default()
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(512);
+
+        let flags = match self.is_response {
+            true => FLAGS_RESPONSE,
+            false => 0,
+        };
+
+        out.extend_from_slice(&self.id.to_be_bytes());
+        out.extend_from_slice(&flags.to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            out.extend_from_slice(&(count as u16).to_be_bytes());
+        }
+
+        for question in &self.questions {
+            question.name.encode(&mut out);
+            out.extend_from_slice(&question.record_type.to_be_bytes());
+
+            let class = match question.unicast_response {
+                true => question.class | UNICAST_RESPONSE_BIT,
+                false => question.class,
+            };
+            out.extend_from_slice(&class.to_be_bytes());
+        }
+
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            record.encode(&mut out);
+        }
+
+        out
+    }
+
+    /// Decodes a message, `None` if it is malformed or no mDNS message.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data, position: 0 };
+
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        if flags & FLAGS_OPCODE_RCODE != 0 {
+            // Messages with a non-zero opcode or response code have to be ignored
+            return None;
+        }
+
+        let question_count = reader.u16()?;
+        let answer_count = reader.u16()?;
+        let authority_count = reader.u16()?;
+        let additional_count = reader.u16()?;
+
+        let questions = (0..question_count)
+            .map(|_| reader.question())
+            .collect::<Option<Vec<_>>>()?;
+        let answers = (0..answer_count)
+            .map(|_| reader.record())
+            .collect::<Option<Vec<_>>>()?;
+        let authorities = (0..authority_count)
+            .map(|_| reader.record())
+            .collect::<Option<Vec<_>>>()?;
+
+        // Additional records are only hints, so a malformed one does not spoil the message
+        let additionals = (0..additional_count)
+            .map_while(|_| reader.record())
+            .collect::<Vec<_>>();
+
+        Some(Self {
+            id,
+            is_response: flags & FLAG_QR != 0,
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|v| v[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|v| u16::from_be_bytes([v[0], v[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+    }
+
+    fn name(&mut self) -> Option<Name> {
+        let mut labels = Vec::new();
+        let mut position = self.position;
+        let mut pointers = 0;
+
+        loop {
+            let len = *self.data.get(position)? as usize;
+            match len {
+                0 => {
+                    position += 1;
+                    break;
+                }
+                // Compression pointer to an earlier name
+                len if len & 0xc0 == 0xc0 => {
+                    let target = (len & 0x3f) << 8 | *self.data.get(position + 1)? as usize;
+                    if pointers == 0 {
+                        // The name ends after the first pointer for the reader
+                        self.position = position + 2;
+                    }
+
+                    pointers += 1;
+                    if pointers > MAX_COMPRESSION_POINTERS {
+                        return None;
+                    }
+
+                    position = target;
+                    continue;
+                }
+                len if len & 0xc0 != 0 => return None,
+                len => {
+                    let label = self.data.get(position + 1..position + 1 + len)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    position += 1 + len;
+                }
+            }
+        }
+
+        if pointers == 0 {
+            self.position = position;
+        }
+
+        Some(Name { labels })
+    }
+
+    fn question(&mut self) -> Option<Question> {
+        let name = self.name()?;
+        let record_type = self.u16()?;
+        let class = self.u16()?;
+
+        Some(Question {
+            name,
+            record_type,
+            class: class & !UNICAST_RESPONSE_BIT,
+            unicast_response: class & UNICAST_RESPONSE_BIT != 0,
+        })
+    }
+
+    fn record(&mut self) -> Option<Record> {
+        let name = self.name()?;
+        let record_type = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = self.u16()? as usize;
+
+        let end = self.position.checked_add(len)?;
+        if end > self.data.len() {
+            return None;
+        }
+
+        let data = match record_type {
+            TYPE_A if len == 4 => {
+                let bytes: [u8; 4] = self.bytes(4)?.try_into().ok()?;
+                RecordData::A(Ipv4Addr::from(bytes))
+            }
+            TYPE_AAAA if len == 16 => {
+                let bytes: [u8; 16] = self.bytes(16)?.try_into().ok()?;
+                RecordData::Aaaa(Ipv6Addr::from(bytes))
+            }
+            TYPE_PTR => RecordData::Ptr(self.name()?),
+            TYPE_SRV => RecordData::Srv {
+                priority: self.u16()?,
+                weight: self.u16()?,
+                port: self.u16()?,
+                target: self.name()?,
+            },
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+                while self.position < end {
+                    let len = self.u8()? as usize;
+                    entries.push(self.bytes(len)?.to_vec());
+                }
+
+                RecordData::Txt(entries)
+            }
+            record_type => RecordData::Other {
+                record_type,
+                data: self.bytes(len)?.to_vec(),
+            },
+        };
+
+        // Whatever the data contained, the next record starts after it
+        self.position = end;
+
+        Some(Record {
+            name,
+            cache_flush: class & CACHE_FLUSH_BIT != 0,
+            ttl,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, data: RecordData) -> Record {
+        Record {
+            name: Name::parse(name),
+            cache_flush: true,
+            ttl: 120,
+            data,
+        }
+    }
+
+    #[test]
+    fn names_compare_case_insensitively() {
+        let name = Name::parse("Agent._dragon-claw._tcp.local.");
+        assert_eq!(name, Name::parse("agent._DRAGON-CLAW._tcp.local"));
+        assert_ne!(name, Name::parse("_dragon-claw._tcp.local"));
+        assert_eq!(
+            Name::parse("_tcp.local").prepend("_dragon-claw"),
+            Name::parse("_dragon-claw._tcp.local")
+        );
+        assert_eq!(name.to_string(), "Agent._dragon-claw._tcp.local.");
+    }
+
+    #[test]
+    fn response_survives_encoding() {
+        let answers = vec![
+            record(
+                "_dragon-claw._tcp.local",
+                RecordData::Ptr(Name::parse("agent._dragon-claw._tcp.local")),
+            ),
+            record(
+                "agent._dragon-claw._tcp.local",
+                RecordData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: 37121,
+                    target: Name::parse("host.local"),
+                },
+            ),
+            record(
+                "agent._dragon-claw._tcp.local",
+                RecordData::Txt(vec![b"fingerprint=abc".to_vec(), b"mac=".to_vec()]),
+            ),
+        ];
+        let additionals = vec![
+            record("host.local", RecordData::A(Ipv4Addr::new(192, 168, 1, 2))),
+            record("host.local", RecordData::Aaaa("fd00::2".parse().unwrap())),
+            record(
+                "host.local",
+                RecordData::Other {
+                    record_type: 47,
+                    data: vec![1, 2, 3],
+                },
+            ),
+        ];
+
+        let message = Message::response(0, answers.clone(), additionals.clone());
+        let decoded = Message::decode(&message.encode()).unwrap();
+
+        assert!(decoded.is_response);
+        assert!(decoded.questions.is_empty());
+        assert!(decoded.authorities.is_empty());
+        assert_eq!(decoded.answers.len(), answers.len());
+        assert_eq!(decoded.additionals.len(), additionals.len());
+        for (decoded, original) in decoded
+            .answers
+            .iter()
+            .chain(&decoded.additionals)
+            .zip(answers.iter().chain(&additionals))
+        {
+            assert!(decoded.same_as(original), "{:?} != {:?}", decoded, original);
+            assert!(decoded.cache_flush);
+            assert_eq!(decoded.ttl, 120);
+        }
+    }
+
+    #[test]
+    fn probe_survives_encoding() {
+        let question = Question {
+            name: Name::parse("host.local"),
+            record_type: TYPE_ANY,
+            class: CLASS_IN,
+            unicast_response: true,
+        };
+        let authority = Record {
+            cache_flush: false,
+            ..record("host.local", RecordData::A(Ipv4Addr::new(192, 168, 1, 2)))
+        };
+
+        let message = Message::query(vec![question], vec![authority.clone()]);
+        let decoded = Message::decode(&message.encode()).unwrap();
+
+        assert!(!decoded.is_response);
+        assert_eq!(decoded.questions.len(), 1);
+        assert_eq!(decoded.questions[0].name, Name::parse("host.local"));
+        assert_eq!(decoded.questions[0].record_type, TYPE_ANY);
+        assert_eq!(decoded.questions[0].class, CLASS_IN);
+        assert!(decoded.questions[0].unicast_response);
+        assert!(decoded.questions[0].matches(&authority));
+        assert_eq!(decoded.authorities.len(), 1);
+        assert!(decoded.authorities[0].same_as(&authority));
+        assert!(!decoded.authorities[0].cache_flush);
+    }
+
+    #[test]
+    fn empty_txt_record_contains_empty_string() {
+        let mut out = Vec::new();
+        RecordData::Txt(Vec::new()).encode(&mut out);
+        assert_eq!(out, [0]);
+    }
+
+    #[test]
+    fn long_labels_are_cut() {
+        let mut out = Vec::new();
+        RecordData::Ptr(Name::new(["a".repeat(70)])).encode(&mut out);
+        assert_eq!(out.len(), 1 + 63 + 1);
+        assert_eq!(out[0], 63);
+    }
+
+    /// Header of a query with the given number of questions.
+    fn header(questions: u16) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0];
+        data.extend_from_slice(&questions.to_be_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data
+    }
+
+    #[test]
+    fn compressed_names_are_decoded() {
+        let mut data = header(2);
+        // host.local, type A, class IN
+        data.extend_from_slice(b"\x04host\x05local\x00\x00\x01\x00\x01");
+        // other + pointer to "local" at offset 17, type AAAA, class IN
+        data.extend_from_slice(b"\x05other\xc0\x11\x00\x1c\x00\x01");
+
+        let decoded = Message::decode(&data).unwrap();
+        assert_eq!(decoded.questions.len(), 2);
+        assert_eq!(decoded.questions[0].name, Name::parse("host.local"));
+        assert_eq!(decoded.questions[1].name, Name::parse("other.local"));
+        assert_eq!(decoded.questions[1].record_type, TYPE_AAAA);
+    }
+
+    #[test]
+    fn compression_loops_are_rejected() {
+        let mut data = header(1);
+        // A pointer to itself
+        data.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01");
+
+        assert!(Message::decode(&data).is_none());
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        // Non-zero opcode
+        let mut data = header(0);
+        data[2] = 0x08;
+        assert!(Message::decode(&data).is_none());
+
+        // Truncated header
+        assert!(Message::decode(&[0, 0, 0]).is_none());
+
+        // Missing question
+        assert!(Message::decode(&header(1)).is_none());
+
+        // Record data longer than the message
+        let mut message = Message::response(
+            0,
+            vec![record("host.local", RecordData::A(Ipv4Addr::LOCALHOST))],
+            Vec::new(),
+        )
+        .encode();
+        message.truncate(message.len() - 1);
+        assert!(Message::decode(&message).is_none());
+    }
+
+    #[test]
+    fn malformed_additionals_are_ignored() {
+        let answer = record("host.local", RecordData::A(Ipv4Addr::LOCALHOST));
+        let mut data = Message::response(0, vec![answer.clone()], vec![answer]).encode();
+        data.truncate(data.len() - 1);
+
+        let decoded = Message::decode(&data).unwrap();
+        assert_eq!(decoded.answers.len(), 1);
+        assert!(decoded.additionals.is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+mod rpc;
+
+pub use rpc::RpcMetricsLayer;
+
+/// The path metrics are served at.
+const METRICS_PATH: &str = "/metrics";
+
+/// Content type of the Prometheus text exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the RPC latency histogram buckets, in seconds.
+const RPC_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// States of an Avahi entry group, indexed by the value Avahi reports.
+pub const AVAHI_ENTRY_GROUP_STATES: &[&str] = &[
+    "uncommitted",
+    "registering",
+    "established",
+    "collision",
+    "failure",
+];
+
+/// The metrics of the agent, collected whether or not they are exposed.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// A value which only ever goes up.
+#[derive(Debug, Default)]
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Counters partitioned by label values.
+#[derive(Debug)]
+pub struct CounterVec {
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    fn new(labels: &'static [&'static str]) -> Self {
+        Self {
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Increments the counter with the given label values, in the order of the label names.
+    pub fn inc(&self, values: &[&str]) {
+        debug_assert_eq!(values.len(), self.labels.len());
+
+        let mut counters = self.values.lock().expect("Poisoned metrics lock");
+        *counters
+            .entry(values.iter().map(|v| v.to_string()).collect())
+            .or_default() += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bucket_counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Histograms partitioned by label values.
+#[derive(Debug)]
+pub struct HistogramVec {
+    labels: &'static [&'static str],
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, Histogram>>,
+}
+
+impl HistogramVec {
+    fn new(labels: &'static [&'static str], buckets: &'static [f64]) -> Self {
+        Self {
+            labels,
+            buckets,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records an observation with the given label values, in the order of the label names.
+    pub fn observe(&self, values: &[&str], value: f64) {
+        debug_assert_eq!(values.len(), self.labels.len());
+
+        let mut histograms = self.values.lock().expect("Poisoned metrics lock");
+        let histogram = histograms
+            .entry(values.iter().map(|v| v.to_string()).collect())
+            .or_insert_with(|| Histogram {
+                bucket_counts: vec![0; self.buckets.len()],
+                sum: 0.0,
+                count: 0,
+            });
+
+        // Buckets are cumulative, so every bucket the value fits in is incremented
+        for (count, bound) in histogram.bucket_counts.iter_mut().zip(self.buckets) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+}
+
+/// A set of mutually exclusive states, at most one of them is active at a time.
+#[derive(Debug)]
+pub struct StateSet {
+    states: &'static [&'static str],
+    current: Mutex<Option<&'static str>>,
+}
+
+impl StateSet {
+    fn new(states: &'static [&'static str]) -> Self {
+        Self {
+            states,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Activates the given state, or clears the active state.
+    #[cfg_attr(
+        any(not(target_os = "linux"), feature = "mock-platform"),
+        allow(dead_code)
+    )] // Only Avahi reports a state
+    pub fn set(&self, state: Option<&'static str>) {
+        debug_assert!(state.is_none_or(|s| self.states.contains(&s)));
+        *self.current.lock().expect("Poisoned metrics lock") = state;
+    }
+}
+
+/// Everything the agent reports to Prometheus.
+#[derive(Debug)]
+pub struct Metrics {
+    started_at: Instant,
+
+    /// Finished RPC calls by method and gRPC status code.
+    pub rpc_calls: CounterVec,
+
+    /// Duration of RPC calls by method and gRPC status code.
+    pub rpc_duration: HistogramVec,
+
+    /// Power actions accepted for execution, including scheduled ones, by action.
+    pub power_actions_requested: CounterVec,
+
+    /// Power actions handed to the platform, by action and result.
+    pub power_actions_executed: CounterVec,
+
+    /// SSDP packets sent.
+    pub ssdp_packets_sent: Counter,
+
+    /// SSDP packets received.
+    pub ssdp_packets_received: Counter,
+
+    /// M-SEARCH requests the agent answered.
+    pub ssdp_search_hits: Counter,
+
+    /// State of the Avahi entry group the service is registered with.
+    pub avahi_entry_group_state: StateSet,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            rpc_calls: CounterVec::new(&["method", "code"]),
+            rpc_duration: HistogramVec::new(&["method", "code"], RPC_DURATION_BUCKETS),
+            power_actions_requested: CounterVec::new(&["action"]),
+            power_actions_executed: CounterVec::new(&["action", "result"]),
+            ssdp_packets_sent: Counter::default(),
+            ssdp_packets_received: Counter::default(),
+            ssdp_search_hits: Counter::default(),
+            avahi_entry_group_state: StateSet::new(AVAHI_ENTRY_GROUP_STATES),
+        }
+    }
+
+    /// Time since the agent has been started.
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_gauge(
+            &mut out,
+            "dragon_claw_agent_uptime_seconds",
+            "Time since the agent has been started.",
+            self.uptime().as_secs_f64(),
+        );
+        write_counter_vec(
+            &mut out,
+            "dragon_claw_rpc_calls_total",
+            "Finished RPC calls by method and gRPC status code.",
+            &self.rpc_calls,
+        );
+        write_histogram_vec(
+            &mut out,
+            "dragon_claw_rpc_duration_seconds",
+            "Duration of RPC calls by method and gRPC status code, streams count until they end.",
+            &self.rpc_duration,
+        );
+        write_counter_vec(
+            &mut out,
+            "dragon_claw_power_actions_requested_total",
+            "Power actions accepted for execution, including scheduled ones.",
+            &self.power_actions_requested,
+        );
+        write_counter_vec(
+            &mut out,
+            "dragon_claw_power_actions_executed_total",
+            "Power actions handed to the platform, by result.",
+            &self.power_actions_executed,
+        );
+        write_counter(
+            &mut out,
+            "dragon_claw_ssdp_packets_sent_total",
+            "SSDP packets sent.",
+            &self.ssdp_packets_sent,
+        );
+        write_counter(
+            &mut out,
+            "dragon_claw_ssdp_packets_received_total",
+            "SSDP packets received.",
+            &self.ssdp_packets_received,
+        );
+        write_counter(
+            &mut out,
+            "dragon_claw_ssdp_search_hits_total",
+            "SSDP M-SEARCH requests the agent answered.",
+            &self.ssdp_search_hits,
+        );
+        write_state_set(
+            &mut out,
+            "dragon_claw_avahi_entry_group_state",
+            "State of the Avahi entry group the service is registered with.",
+            &self.avahi_entry_group_state,
+        );
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, ty: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, ty);
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    write_header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    write_header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, counter.get());
+}
+
+fn write_counter_vec(out: &mut String, name: &str, help: &str, counters: &CounterVec) {
+    write_header(out, name, help, "counter");
+
+    let values = counters.values.lock().expect("Poisoned metrics lock");
+    for (label_values, value) in values.iter() {
+        let _ = writeln!(
+            out,
+            "{}{{{}}} {}",
+            name,
+            format_labels(counters.labels, label_values, None),
+            value
+        );
+    }
+}
+
+fn write_histogram_vec(out: &mut String, name: &str, help: &str, histograms: &HistogramVec) {
+    write_header(out, name, help, "histogram");
+
+    let values = histograms.values.lock().expect("Poisoned metrics lock");
+    for (label_values, histogram) in values.iter() {
+        let buckets = histogram
+            .bucket_counts
+            .iter()
+            .zip(histograms.buckets)
+            .map(|(count, bound)| (bound.to_string(), *count))
+            .chain(std::iter::once(("+Inf".to_string(), histogram.count)));
+
+        for (bound, count) in buckets {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}}} {}",
+                name,
+                format_labels(histograms.labels, label_values, Some(("le", &bound))),
+                count
+            );
+        }
+
+        let labels = format_labels(histograms.labels, label_values, None);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+    }
+}
+
+fn write_state_set(out: &mut String, name: &str, help: &str, state_set: &StateSet) {
+    write_header(out, name, help, "gauge");
+
+    let current = *state_set.current.lock().expect("Poisoned metrics lock");
+    for state in state_set.states {
+        let _ = writeln!(
+            out,
+            "{}{{state=\"{}\"}} {}",
+            name,
+            state,
+            u8::from(current == Some(*state))
+        );
+    }
+}
+
+fn format_labels(names: &[&str], values: &[String], extra: Option<(&str, &str)>) -> String {
+    names
+        .iter()
+        .copied()
+        .zip(values.iter().map(String::as_str))
+        .chain(extra)
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Binds the metrics listener, the returned future serves the metrics until `shutdown`
+/// completes.
+pub fn serve(
+    address: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> Result<impl Future<Output = Result<(), hyper::Error>>, hyper::Error> {
+    let server = Server::try_bind(&address)?;
+    tracing::info!("Serving metrics on http://{}{}", address, METRICS_PATH);
+
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+    Ok(server.serve(make_service).with_graceful_shutdown(shutdown))
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, METRICS_PATH) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, METRICS_CONTENT_TYPE)
+            .body(Body::from(METRICS.render())),
+        (_, METRICS_PATH) => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(hyper::header::ALLOW, "GET")
+            .body(Body::empty()),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+
+    Ok(response.expect("Metrics response is valid"))
+}
@@ -0,0 +1,149 @@
+use crate::metrics::METRICS;
+use futures::future::BoxFuture;
+use http::{HeaderMap, Request, Response};
+use http_body::Body;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::Code;
+use tower::{Layer, Service};
+
+/// Records the count and duration of RPC calls.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RpcMetricsLayer;
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcMetricsService { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcMetricsService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RpcMetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<MeteredBody<ResBody>>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let method = request.uri().path().to_string();
+        let started_at = Instant::now();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+
+            // Errors returned by the handlers are sent as trailers-only response, which carries
+            // the status in the headers
+            let call = RpcCall {
+                method,
+                started_at,
+                code: grpc_status_of(response.headers()),
+            };
+
+            Ok(response.map(|inner| MeteredBody {
+                inner,
+                call: Some(call),
+            }))
+        })
+    }
+}
+
+#[derive(Debug)]
+struct RpcCall {
+    method: String,
+    started_at: Instant,
+    code: Option<Code>,
+}
+
+impl RpcCall {
+    fn finish(self) {
+        // Without a status the client went away before the call finished
+        let code = self.code.unwrap_or(Code::Cancelled);
+
+        // Don't let clients create a series for every path they can think of
+        let method = match code {
+            Code::Unimplemented => "unknown",
+            _ => self.method.as_str(),
+        };
+        let code = format!("{:?}", code);
+
+        METRICS.rpc_calls.inc(&[method, &code]);
+        METRICS
+            .rpc_duration
+            .observe(&[method, &code], self.started_at.elapsed().as_secs_f64());
+    }
+}
+
+/// Response body which records the call once the status has been sent.
+#[derive(Debug)]
+pub struct MeteredBody<B> {
+    inner: B,
+    call: Option<RpcCall>,
+}
+
+impl<B> Body for MeteredBody<B>
+where
+    B: Body + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let res = Pin::new(&mut self.inner).poll_trailers(cx);
+
+        if let Poll::Ready(Ok(Some(trailers))) = &res {
+            if let Some(mut call) = self.call.take() {
+                call.code = grpc_status_of(trailers).or(call.code);
+                call.finish();
+            }
+        }
+
+        res
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for MeteredBody<B> {
+    fn drop(&mut self) {
+        if let Some(call) = self.call.take() {
+            call.finish();
+        }
+    }
+}
+
+fn grpc_status_of(headers: &HeaderMap) -> Option<Code> {
+    headers
+        .get("grpc-status")
+        .map(|v| Code::from_bytes(v.as_bytes()))
+}
@@ -0,0 +1,44 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::net::TcpListener;
+
+/// Binds a TCP listener, such as the one of the RPC server.
+///
+/// The unspecified IPv6 address is bound as dual-stack socket accepting both IPv4 and IPv6
+/// connections, falling back to IPv4 only if IPv6 is not available.
+pub fn bind_listener(addr: SocketAddr) -> Result<TcpListener, std::io::Error> {
+    let dual_stack = addr.is_ipv6() && addr.ip().is_unspecified();
+
+    match bind_tcp_socket(addr, dual_stack) {
+        Err(err) if dual_stack => {
+            tracing::warn!(
+                "Failed to bind dual-stack listener, falling back to IPv4: {}",
+                err
+            );
+            bind_tcp_socket(
+                SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), addr.port()),
+                false,
+            )
+        }
+        res => res,
+    }
+}
+
+fn bind_tcp_socket(addr: SocketAddr, dual_stack: bool) -> Result<TcpListener, std::io::Error> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    // Windows defaults to IPv6 only, so always set this explicitly
+    if dual_stack {
+        socket.set_only_v6(false)?;
+    }
+
+    // Same as tokio does, allows restarting while old connections are still lingering
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+
+    TcpListener::from_std(socket.into())
+}
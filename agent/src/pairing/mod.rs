@@ -0,0 +1,500 @@
+use crate::storage::{JsonFile, StorageError};
+use crate::util::{random_hex, unix_time_now};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const PAIRED_DEVICES_FILE_NAME: &str = "paired-devices.json";
+
+/// How long a pairing PIN stays valid.
+pub const PAIRING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How many wrong PINs are accepted before a pending pairing is discarded.
+const MAX_PIN_ATTEMPTS: u32 = 3;
+
+/// How many pairings may be pending at the same time.
+const MAX_PENDING_PAIRINGS: usize = 8;
+
+/// How many wrong PINs are accepted across all pairings before pairing is locked.
+///
+/// Pairing requests are not authenticated, so without this limit the PIN could be guessed by
+/// requesting one pairing after another.
+const MAX_FAILED_PIN_ATTEMPTS: u32 = 10;
+
+/// How long pairing stays locked after too many wrong PINs.
+const PAIRING_LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A device which has been paired with the agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairedDevice {
+    /// Identifier of the device.
+    pub id: String,
+
+    /// Human readable name of the device.
+    pub name: String,
+
+    /// Time the device has been paired at, in seconds since the unix epoch.
+    pub paired_at: u64,
+
+    /// Hex encoded SHA-256 hash of the access token issued to the device.
+    token_hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PairedDevicesDocument {
+    devices: Vec<PairedDevice>,
+}
+
+#[derive(Debug)]
+struct PendingPairing {
+    device_name: String,
+    pin: String,
+    expires_at: Instant,
+    failed_attempts: u32,
+}
+
+#[derive(Debug, Default)]
+struct PendingPairings {
+    pairings: HashMap<String, PendingPairing>,
+
+    /// Wrong PINs entered since the last successful pairing or lockout.
+    failed_attempts: u32,
+
+    /// Time until which no pairings are accepted.
+    locked_until: Option<Instant>,
+}
+
+impl PendingPairings {
+    /// Fails if pairing is locked, lifting the lockout once it has passed.
+    fn check_lockout(&mut self, now: Instant) -> Result<(), PairingError> {
+        match self.locked_until {
+            Some(locked_until) if locked_until > now => Err(PairingError::LockedOut),
+            Some(_) => {
+                tracing::info!("Pairing is possible again");
+                self.locked_until = None;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Keeps track of paired devices and pending pairings.
+#[derive(Debug)]
+pub struct PairingManager {
+    storage: JsonFile,
+    privileged_devices: Vec<String>,
+    devices: RwLock<Vec<PairedDevice>>,
+    pending: Mutex<PendingPairings>,
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+impl PairingManager {
+    /// Loads the paired devices from the given data directory.
+    ///
+    /// The given devices are privileged, see [`PairingManager::is_privileged`].
+    pub async fn load(
+        data_directory: &Path,
+        privileged_devices: Vec<String>,
+    ) -> Result<Self, PairingError> {
+        let storage = JsonFile::new(data_directory, PAIRED_DEVICES_FILE_NAME);
+        let document = storage.load::<PairedDevicesDocument>().await?;
+
+        tracing::debug!("Loaded {} paired devices", document.devices.len());
+
+        Ok(Self {
+            storage,
+            privileged_devices,
+            devices: RwLock::new(document.devices),
+            pending: Mutex::new(PendingPairings::default()),
+            persist_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Starts a new pairing and displays the PIN on the agent side.
+    ///
+    /// Returns the identifier of the pending pairing.
+    pub fn request_pairing(&self, device_name: String) -> Result<String, PairingError> {
+        let mut pending = self.pending.lock().expect("Poisoned pending pairing lock");
+
+        let now = Instant::now();
+        pending.check_lockout(now)?;
+
+        // Get rid of pairings which can't be completed anymore
+        pending.pairings.retain(|_, p| p.expires_at > now);
+
+        if pending.pairings.len() >= MAX_PENDING_PAIRINGS {
+            return Err(PairingError::TooManyPendingPairings);
+        }
+
+        let pairing_id = random_hex(16);
+        let pin = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+
+        // This is the only place the PIN is ever displayed
+        tracing::warn!(
+            "Pairing requested by \"{}\", enter PIN {} on the device to complete it",
+            device_name,
+            pin
+        );
+
+        pending.pairings.insert(
+            pairing_id.clone(),
+            PendingPairing {
+                device_name,
+                pin,
+                expires_at: now + PAIRING_TIMEOUT,
+                failed_attempts: 0,
+            },
+        );
+
+        Ok(pairing_id)
+    }
+
+    /// Completes a pending pairing.
+    ///
+    /// Returns the newly paired device and its access token.
+    pub async fn complete_pairing(
+        &self,
+        pairing_id: &str,
+        pin: &str,
+    ) -> Result<(PairedDevice, String), PairingError> {
+        let pairing = {
+            let mut pending = self.pending.lock().expect("Poisoned pending pairing lock");
+
+            let now = Instant::now();
+            pending.check_lockout(now)?;
+
+            let Some(pairing) = pending.pairings.get_mut(pairing_id) else {
+                return Err(PairingError::UnknownPairing);
+            };
+
+            if pairing.expires_at <= now {
+                pending.pairings.remove(pairing_id);
+                return Err(PairingError::UnknownPairing);
+            }
+
+            if !constant_time_eq(pairing.pin.as_bytes(), pin.as_bytes()) {
+                pairing.failed_attempts += 1;
+                if pairing.failed_attempts >= MAX_PIN_ATTEMPTS {
+                    tracing::warn!(
+                        "Too many wrong PINs for pairing with \"{}\", discarding it",
+                        pairing.device_name
+                    );
+                    pending.pairings.remove(pairing_id);
+                }
+
+                pending.failed_attempts += 1;
+                if pending.failed_attempts >= MAX_FAILED_PIN_ATTEMPTS {
+                    tracing::warn!(
+                        "Too many wrong PINs, locking pairing for {} seconds",
+                        PAIRING_LOCKOUT.as_secs()
+                    );
+
+                    // PINs which may have been guessed partially are not accepted anymore
+                    pending.pairings.clear();
+                    pending.failed_attempts = 0;
+                    pending.locked_until = Some(now + PAIRING_LOCKOUT);
+                }
+
+                return Err(PairingError::InvalidPin);
+            }
+
+            pending.failed_attempts = 0;
+            pending.pairings.remove(pairing_id).unwrap()
+        };
+
+        let token = random_hex(32);
+        let device = PairedDevice {
+            id: random_hex(8),
+            name: pairing.device_name,
+            paired_at: unix_time_now(),
+            token_hash: hash_token(&token),
+        };
+
+        self.devices
+            .write()
+            .expect("Poisoned paired devices lock")
+            .push(device.clone());
+        self.persist().await?;
+
+        tracing::info!("Paired device \"{}\" ({})", device.name, device.id);
+
+        Ok((device, token))
+    }
+
+    /// Looks up the device an access token has been issued to.
+    pub fn authenticate(&self, token: &str) -> Option<PairedDevice> {
+        let token_hash = hash_token(token);
+
+        self.devices
+            .read()
+            .expect("Poisoned paired devices lock")
+            .iter()
+            .find(|d| d.token_hash == token_hash)
+            .cloned()
+    }
+
+    /// Tests whether the device with the given identifier is paired.
+    pub fn is_paired(&self, device_id: &str) -> bool {
+        self.devices
+            .read()
+            .expect("Poisoned paired devices lock")
+            .iter()
+            .any(|d| d.id == device_id)
+    }
+
+    /// Tests whether a device may manage what other devices own, such as their pairings.
+    pub fn is_privileged(&self, device_id: &str) -> bool {
+        self.privileged_devices.iter().any(|d| d == device_id)
+    }
+
+    /// Retrieves all paired devices.
+    pub fn paired_devices(&self) -> Vec<PairedDevice> {
+        self.devices
+            .read()
+            .expect("Poisoned paired devices lock")
+            .clone()
+    }
+
+    /// Revokes the pairing of a device, invalidating its access token.
+    pub async fn revoke(&self, device_id: &str) -> Result<(), PairingError> {
+        {
+            let mut devices = self.devices.write().expect("Poisoned paired devices lock");
+            let len_before = devices.len();
+            devices.retain(|d| d.id != device_id);
+
+            if devices.len() == len_before {
+                return Err(PairingError::UnknownDevice);
+            }
+        }
+
+        self.persist().await?;
+        tracing::info!("Revoked pairing of device {}", device_id);
+
+        Ok(())
+    }
+
+    async fn persist(&self) -> Result<(), PairingError> {
+        // Hold the lock while taking the snapshot, so writes can't overtake each other
+        let _guard = self.persist_lock.lock().await;
+        let document = PairedDevicesDocument {
+            devices: self.paired_devices(),
+        };
+
+        self.storage.store(&document).await?;
+        Ok(())
+    }
+}
+
+/// Compares two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum PairingError {
+    #[error("failed to persist paired devices: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("unknown or expired pairing")]
+    UnknownPairing,
+
+    #[error("invalid PIN")]
+    InvalidPin,
+
+    #[error("too many pending pairings")]
+    TooManyPendingPairings,
+
+    #[error("too many wrong PINs, pairing is locked for a while")]
+    LockedOut,
+
+    #[error("unknown device")]
+    UnknownDevice,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TestDirectory;
+
+    /// Retrieves the PIN of a pending pairing, which is otherwise only logged.
+    fn pin_of(manager: &PairingManager, pairing_id: &str) -> String {
+        manager.pending.lock().unwrap().pairings[pairing_id]
+            .pin
+            .clone()
+    }
+
+    /// Builds a PIN which does not match the given one.
+    fn wrong_pin(pin: &str) -> String {
+        if pin == "000000" {
+            "000001".to_string()
+        } else {
+            "000000".to_string()
+        }
+    }
+
+    #[test]
+    fn token_hash_is_hex_encoded_sha256() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(hash_token("abc"), hash_token("abd"));
+    }
+
+    #[test]
+    fn constant_time_eq_compares_contents_and_length() {
+        assert!(constant_time_eq(b"123456", b"123456"));
+        assert!(!constant_time_eq(b"123456", b"123457"));
+        assert!(!constant_time_eq(b"123456", b"12345"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[tokio::test]
+    async fn paired_device_authenticates_with_its_token() {
+        let directory = TestDirectory::new();
+        let manager = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+
+        let pairing_id = manager.request_pairing("Phone".to_string()).unwrap();
+        let pin = pin_of(&manager, &pairing_id);
+        let (device, token) = manager.complete_pairing(&pairing_id, &pin).await.unwrap();
+
+        assert_eq!(device.name, "Phone");
+        assert_eq!(manager.authenticate(&token).unwrap().id, device.id);
+        assert!(manager.authenticate("not-a-token").is_none());
+
+        // Only the hash of the token is stored
+        let stored =
+            std::fs::read_to_string(directory.path().join(PAIRED_DEVICES_FILE_NAME)).unwrap();
+        assert!(!stored.contains(&token));
+        assert!(stored.contains(&hash_token(&token)));
+
+        // The pairing can only be completed once
+        assert!(matches!(
+            manager.complete_pairing(&pairing_id, &pin).await,
+            Err(PairingError::UnknownPairing)
+        ));
+    }
+
+    #[tokio::test]
+    async fn paired_devices_are_persisted_and_revocable() {
+        let directory = TestDirectory::new();
+        let manager = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+
+        let pairing_id = manager.request_pairing("Phone".to_string()).unwrap();
+        let pin = pin_of(&manager, &pairing_id);
+        let (device, token) = manager.complete_pairing(&pairing_id, &pin).await.unwrap();
+
+        let reloaded = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(reloaded.authenticate(&token).unwrap().id, device.id);
+
+        reloaded.revoke(&device.id).await.unwrap();
+        assert!(reloaded.authenticate(&token).is_none());
+        assert!(matches!(
+            reloaded.revoke(&device.id).await,
+            Err(PairingError::UnknownDevice)
+        ));
+
+        let reloaded = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+        assert!(reloaded.paired_devices().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pairing_is_discarded_after_too_many_wrong_pins() {
+        let directory = TestDirectory::new();
+        let manager = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+
+        let pairing_id = manager.request_pairing("Phone".to_string()).unwrap();
+        let pin = pin_of(&manager, &pairing_id);
+
+        for _ in 0..MAX_PIN_ATTEMPTS {
+            assert!(matches!(
+                manager
+                    .complete_pairing(&pairing_id, &wrong_pin(&pin))
+                    .await,
+                Err(PairingError::InvalidPin)
+            ));
+        }
+
+        assert!(matches!(
+            manager.complete_pairing(&pairing_id, &pin).await,
+            Err(PairingError::UnknownPairing)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pairing_locks_after_too_many_wrong_pins_overall() {
+        let directory = TestDirectory::new();
+        let manager = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+
+        let mut failed = 0;
+        while failed < MAX_FAILED_PIN_ATTEMPTS {
+            let pairing_id = manager.request_pairing("Phone".to_string()).unwrap();
+            let pin = pin_of(&manager, &pairing_id);
+
+            for _ in 0..MAX_PIN_ATTEMPTS.min(MAX_FAILED_PIN_ATTEMPTS - failed) {
+                let _ = manager
+                    .complete_pairing(&pairing_id, &wrong_pin(&pin))
+                    .await;
+                failed += 1;
+            }
+        }
+
+        assert!(matches!(
+            manager.request_pairing("Phone".to_string()),
+            Err(PairingError::LockedOut)
+        ));
+    }
+
+    #[tokio::test]
+    async fn pending_pairings_are_limited() {
+        let directory = TestDirectory::new();
+        let manager = PairingManager::load(directory.path(), Vec::new())
+            .await
+            .unwrap();
+
+        for _ in 0..MAX_PENDING_PAIRINGS {
+            manager.request_pairing("Phone".to_string()).unwrap();
+        }
+
+        assert!(matches!(
+            manager.request_pairing("Phone".to_string()),
+            Err(PairingError::TooManyPendingPairings)
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_configured_devices_are_privileged() {
+        let directory = TestDirectory::new();
+        let manager = PairingManager::load(directory.path(), vec!["0123456789abcdef".to_string()])
+            .await
+            .unwrap();
+
+        assert!(manager.is_privileged("0123456789abcdef"));
+        assert!(!manager.is_privileged("fedcba9876543210"));
+    }
+}
@@ -1,14 +1,151 @@
 use crate::pal::PlatformAbstractionError;
-use std::net::SocketAddr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Maximum length of a single DNS-SD TXT record entry.
+const MAX_TXT_ENTRY_LENGTH: usize = 255;
+
+/// A link-layer (Ethernet) hardware address.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    /// Tests whether the address is all zeros, which some virtual interfaces report.
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
+}
+
+impl FromStr for MacAddress {
+    type Err = InvalidMacAddress;
+
+    /// Parses an address such as `aa:bb:cc:dd:ee:ff`, `-` is accepted as separator as well.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mac = [0; 6];
+        let mut parts = s.split([':', '-']);
+
+        for byte in &mut mac {
+            let part = parts.next().ok_or(InvalidMacAddress)?;
+            if part.len() != 2 {
+                return Err(InvalidMacAddress);
+            }
+
+            *byte = u8::from_str_radix(part, 16).map_err(|_| InvalidMacAddress)?;
+        }
+
+        if parts.next().is_some() {
+            return Err(InvalidMacAddress);
+        }
+
+        Ok(Self(mac))
+    }
+}
+
+impl Serialize for MacAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for MacAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let m = &self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            m[0], m[1], m[2], m[3], m[4], m[5]
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid MAC address, expected something like aa:bb:cc:dd:ee:ff")]
+pub struct InvalidMacAddress;
+
+/// A network interface of the local machine.
+#[derive(Debug, Clone)]
+pub struct NetworkInterface {
+    /// The name of the interface, as displayed by the operating system.
+    pub name: String,
+
+    /// The hardware address of the interface, if it has one.
+    pub mac_address: Option<MacAddress>,
+
+    /// The IP addresses assigned to the interface.
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Information about the service which is being advertised.
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    /// The address the RPC server is listening on.
+    pub socket_addr: SocketAddr,
+
+    /// The hex encoded SHA-256 fingerprint of the TLS certificate used by the RPC server.
+    pub certificate_fingerprint: String,
+
+    /// Hardware addresses clients can send Wake-on-LAN packets to.
+    pub mac_addresses: Vec<MacAddress>,
+
+    /// UUID identifying the machine as UPnP device.
+    pub device_uuid: String,
+}
+
+impl ServiceInfo {
+    /// Retrieves the key-value properties which should be published alongside the service,
+    /// for example in a DNS-SD TXT record.
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = vec![(
+            "fingerprint",
+            format!("sha256:{}", self.certificate_fingerprint),
+        )];
+
+        if !self.mac_addresses.is_empty() {
+            // Only as many addresses as fit into a single TXT entry, including "mac="
+            let mut macs = String::new();
+            for mac in &self.mac_addresses {
+                let mac = mac.to_string();
+                let separator = if macs.is_empty() { 0 } else { 1 };
+
+                if 4 + macs.len() + separator + mac.len() > MAX_TXT_ENTRY_LENGTH {
+                    break;
+                }
+
+                if separator != 0 {
+                    macs.push(',');
+                }
+                macs.push_str(&mac);
+            }
+
+            properties.push(("mac", macs));
+        }
+
+        properties
+    }
+}
 
 #[async_trait::async_trait]
 pub trait DiscoveryManager: Send + Sync + 'static {
     /// Starts advertising the service.
     async fn advertise_service(
         &self,
-        socket_addr: SocketAddr,
+        service: &ServiceInfo,
     ) -> Result<(), PlatformAbstractionError>;
 
     /// Stops advertising the service.
     async fn stop_advertising_service(&self) -> Result<(), PlatformAbstractionError>;
+
+    /// Retrieves the network interfaces of the local machine, excluding loopback interfaces.
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, PlatformAbstractionError>;
 }
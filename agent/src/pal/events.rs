@@ -0,0 +1,34 @@
+use crate::pal::PlatformAbstractionError;
+use futures::Stream;
+use std::pin::Pin;
+
+/// Stream of system events.
+pub type SystemEventStream = Pin<Box<dyn Stream<Item = SystemEvent> + Send>>;
+
+/// Events reported by the operating system.
+#[derive(Debug, Clone, Eq, PartialEq)]
+// System events are not reported on Windows yet, and never by the mock platform
+#[cfg_attr(any(windows, feature = "mock-platform"), allow(dead_code))]
+pub enum SystemEvent {
+    /// The system is about to shut down or reboot, or a shutdown has been aborted
+    PrepareForShutdown { active: bool },
+
+    /// The system is about to sleep, or has just resumed
+    PrepareForSleep { active: bool },
+
+    /// A user session has been created
+    SessionAdded { session_id: String },
+
+    /// A user session has been removed
+    SessionRemoved { session_id: String },
+}
+
+#[async_trait::async_trait]
+pub trait EventManager: Send + Sync + 'static {
+    /// Subscribes to system events.
+    ///
+    /// The stream ends when the platform stops delivering events.
+    async fn subscribe(&self) -> Result<SystemEventStream, PlatformAbstractionError> {
+        Err(PlatformAbstractionError::Unsupported)
+    }
+}
@@ -37,5 +37,8 @@ trait AvahiEntryGroup {
 
     async fn commit(&self) -> zbus::Result<()>;
 
+    #[dbus_proxy(signal)]
+    fn state_changed(&self, state: i32, error: &str) -> zbus::Result<()>;
+
     async fn free(self) -> zbus::Result<()>;
 }
@@ -1,3 +1,6 @@
+/// Session id, user id, user name, seat id and object path of a session.
+pub type SessionListEntry = (String, u32, String, String, zbus::zvariant::OwnedObjectPath);
+
 #[zbus::dbus_proxy(
     interface = "org.freedesktop.login1.Manager",
     default_service = "org.freedesktop.login1",
@@ -14,6 +17,8 @@ pub trait Login1Manager {
 
     async fn can_hybrid_sleep(&self) -> zbus::Result<String>;
 
+    async fn can_suspend_then_hibernate(&self) -> zbus::Result<String>;
+
     async fn can_reboot_to_firmware_setup(&self) -> zbus::Result<String>;
 
     async fn power_off(&self, interactive: bool) -> zbus::Result<()>;
@@ -26,5 +31,59 @@ pub trait Login1Manager {
 
     async fn hybrid_sleep(&self, interactive: bool) -> zbus::Result<()>;
 
+    async fn suspend_then_hibernate(&self, interactive: bool) -> zbus::Result<()>;
+
     async fn set_reboot_to_firmware_setup(&self, enable: bool) -> zbus::Result<()>;
+
+    async fn schedule_shutdown(&self, ty: &str, usec: u64) -> zbus::Result<()>;
+
+    async fn cancel_scheduled_shutdown(&self) -> zbus::Result<bool>;
+
+    async fn list_sessions(&self) -> zbus::Result<Vec<SessionListEntry>>;
+
+    async fn lock_sessions(&self) -> zbus::Result<()>;
+
+    async fn lock_session(&self, session_id: &str) -> zbus::Result<()>;
+
+    async fn terminate_session(&self, session_id: &str) -> zbus::Result<()>;
+
+    async fn terminate_user(&self, uid: u32) -> zbus::Result<()>;
+
+    async fn inhibit(
+        &self,
+        what: &str,
+        who: &str,
+        why: &str,
+        mode: &str,
+    ) -> zbus::Result<zbus::zvariant::OwnedFd>;
+
+    #[dbus_proxy(signal)]
+    fn prepare_for_shutdown(&self, start: bool) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn session_new(
+        &self,
+        session_id: &str,
+        object_path: zbus::zvariant::ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn session_removed(
+        &self,
+        session_id: &str,
+        object_path: zbus::zvariant::ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+}
+
+#[zbus::dbus_proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    assume_defaults = false
+)]
+pub trait Login1Session {
+    #[dbus_proxy(property, name = "Type")]
+    fn session_type(&self) -> zbus::Result<String>;
 }
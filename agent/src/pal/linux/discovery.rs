@@ -1,41 +1,72 @@
-use crate::pal::discovery::DiscoveryManager;
+use crate::config::{DiscoveryBackend, DiscoveryConfig};
+use crate::mdns::{InterfaceAddresses, MdnsResponder};
+use crate::metrics::{AVAHI_ENTRY_GROUP_STATES, METRICS};
+use crate::pal::discovery::{DiscoveryManager, MacAddress, NetworkInterface, ServiceInfo};
 use crate::pal::platform::dbus::avahi::{AvahiEntryGroupProxy, AvahiServer2Proxy};
 use crate::pal::platform::dbus::dbus_call;
+use crate::pal::platform::netlink::InterfaceChanges;
 use crate::pal::{PlatformAbstractionError, FALLBACK_NAME};
 use crate::ssdp::{IpAddrWithScopeId, SSDPMulticast};
+use futures::StreamExt;
 use std::borrow::Cow;
-use std::ffi::CString;
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ffi::{CStr, CString};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Time without further interface changes after which the announcements follow them.
+const INTERFACE_SETTLE_DELAY: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub struct LinuxDiscoveryManager {
+    config: DiscoveryConfig,
     avahi: Option<AvahiServer2Proxy<'static>>,
     host_name: Cow<'static, str>,
-    registered_dns_service: Mutex<Option<AvahiEntryGroupProxy<'static>>>,
-    ssdp: Mutex<Option<SSDPMulticast>>,
+    registered_dns_service: Mutex<Option<AvahiRegistration>>,
+    mdns_responder: Arc<Mutex<Option<MdnsResponder>>>,
+    ssdp: Arc<Mutex<Option<SSDPMulticast>>>,
+    interface_watch: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Debug)]
+struct AvahiRegistration {
+    group: AvahiEntryGroupProxy<'static>,
+    state_task: JoinHandle<()>,
 }
 
 impl LinuxDiscoveryManager {
-    /// Attempts to connect to Avahi.
-    pub async fn new(dbus_connection: &zbus::Connection) -> Self {
-        let (avahi, host_name) = match dbus_call!(AvahiServer2Proxy::new(dbus_connection)).await {
-            Ok(avahi) => {
-                let host_name = match dbus_call!(avahi.get_host_name()).await {
-                    Ok(v) => {
-                        tracing::info!("Host name: {}", v);
-                        Some(Cow::Owned(v))
-                    }
-                    Err(err) => {
-                        tracing::warn!("Failed to get host name: {}", err);
-                        None
-                    }
-                };
-                (Some(avahi), host_name)
-            }
+    /// Attempts to connect to Avahi, unless mDNS discovery is disabled. The built-in mDNS
+    /// responder is used if Avahi is not available.
+    pub async fn new(dbus_connection: &zbus::Connection, config: DiscoveryConfig) -> Self {
+        let avahi_res = if config.is_enabled(DiscoveryBackend::Mdns) {
+            dbus_call!(AvahiServer2Proxy::new(dbus_connection))
+                .await
+                .map(Some)
+        } else {
+            Ok(None)
+        };
+
+        let (avahi, host_name) = match avahi_res {
+            Ok(None) => (None, None),
+            // Creating the proxy does not talk to Avahi yet, so only an answer tells it is running
+            Ok(Some(avahi)) => match dbus_call!(avahi.get_host_name()).await {
+                Ok(v) => {
+                    tracing::info!("Host name: {}", v);
+                    (Some(avahi), Some(Cow::Owned(v)))
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to reach Avahi, using the built-in mDNS responder: {}",
+                        err
+                    );
+                    (None, None)
+                }
+            },
             Err(err) => {
                 tracing::warn!(
-                    "Failed to connect to Avahi, mDNS discovery will be unavailable: {}",
+                    "Failed to connect to Avahi, using the built-in mDNS responder: {}",
                     err
                 );
                 (None, None)
@@ -73,14 +104,21 @@ impl LinuxDiscoveryManager {
         });
 
         Self {
+            config,
             avahi,
             host_name,
             registered_dns_service: Mutex::new(None),
-            ssdp: Mutex::new(None),
+            mdns_responder: Arc::new(Mutex::new(None)),
+            ssdp: Arc::new(Mutex::new(None)),
+            interface_watch: Mutex::new(None),
         }
     }
 
-    async fn advertise_with_avahi(&self, addr: SocketAddr) -> Result<(), PlatformAbstractionError> {
+    async fn advertise_with_avahi(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
+        let addr = service.socket_addr;
         let avahi = self
             .avahi
             .as_ref()
@@ -91,43 +129,175 @@ impl LinuxDiscoveryManager {
 
         let group = dbus_call!(avahi.entry_group_new()).await?;
 
+        // Subscribe before committing, so no state change is missed
+        let mut state_changes = dbus_call!(group.receive_state_changed()).await?;
+
+        // Encode the service properties as TXT record entries
+        let txt = service
+            .properties()
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, value).into_bytes())
+            .collect::<Vec<_>>();
+        let txt = txt.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
         dbus_call!(group.add_service(
             -1, // All interfaces
-            match &addr {
-                SocketAddr::V4(_) => 0,
-                SocketAddr::V6(_) => 1,
+            match addr.ip() {
+                // Dual-stack socket, announce on both IPv4 and IPv6
+                IpAddr::V6(ip) if ip.is_unspecified() => -1,
+                IpAddr::V4(_) => 0,
+                IpAddr::V6(_) => 1,
             },
             0,
             &self.host_name,
-            "_dragon-claw._tcp",
+            &self.config.service_type,
             None.into(),
             None.into(),
             addr.port(),
-            &[],
+            &txt,
         ))
         .await?;
 
         dbus_call!(group.commit()).await?;
 
+        let state_task = tokio::spawn(async move {
+            while let Some(signal) = state_changes.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+
+                let state = usize::try_from(args.state)
+                    .ok()
+                    .and_then(|i| AVAHI_ENTRY_GROUP_STATES.get(i))
+                    .copied();
+                match state {
+                    Some("collision" | "failure") => {
+                        tracing::warn!("Avahi registration failed: {:?} {}", state, args.error)
+                    }
+                    _ => tracing::debug!("Avahi entry group state changed to {:?}", state),
+                }
+
+                METRICS.avahi_entry_group_state.set(state);
+            }
+        });
+
         // Store the group in the registered service mutex
         let mut registered_dns_service = self.registered_dns_service.lock().await;
-        registered_dns_service.replace(group);
+        registered_dns_service.replace(AvahiRegistration { group, state_task });
+
+        Ok(())
+    }
+
+    /// Advertises the service using the built-in mDNS responder.
+    async fn advertise_with_mdns_responder(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
+        // The names are claimed again, as the service may have changed
+        let mut mdns_responder = self.mdns_responder.lock().await;
+        if let Some(previous) = mdns_responder.take() {
+            previous.stop().await;
+        }
+
+        let responder = MdnsResponder::start(
+            &self.host_name,
+            &self.host_name,
+            &self.config.service_type,
+            service,
+            Self::get_interface_addresses()?,
+        )?;
+        mdns_responder.replace(responder);
+        drop(mdns_responder);
+
+        self.watch_interfaces().await;
 
         Ok(())
     }
 
     /// Advertises the service using SSDP.
-    async fn advertise_with_ssdp(&self, addr: SocketAddr) -> Result<(), PlatformAbstractionError> {
+    async fn advertise_with_ssdp(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
         self.stop_ssdp().await;
 
-        let multicast_manager =
-            SSDPMulticast::setup(self.host_name.to_string(), addr, Self::get_local_addresses)
-                .await?;
+        let multicast_manager = SSDPMulticast::setup(
+            self.host_name.to_string(),
+            service,
+            self.config.ssdp_max_age(),
+            self.config.ssdp_description_port,
+            Self::get_local_addresses,
+        )
+        .await?;
         self.ssdp.lock().await.replace(multicast_manager);
 
+        self.watch_interfaces().await;
+
         Ok(())
     }
 
+    /// Follows changes of the network interfaces from now on, unless this is done already.
+    async fn watch_interfaces(&self) {
+        let mut interface_watch = self.interface_watch.lock().await;
+        if interface_watch.is_none() {
+            *interface_watch =
+                Self::spawn_interface_watch(self.ssdp.clone(), self.mdns_responder.clone());
+        }
+    }
+
+    /// Starts refreshing the SSDP announcements and the built-in mDNS responder whenever network
+    /// interfaces or their addresses change.
+    fn spawn_interface_watch(
+        ssdp: Arc<Mutex<Option<SSDPMulticast>>>,
+        mdns_responder: Arc<Mutex<Option<MdnsResponder>>>,
+    ) -> Option<JoinHandle<()>> {
+        let mut changes = match InterfaceChanges::subscribe() {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to watch network interfaces, announcements will not follow address \
+                    changes: {}",
+                    err
+                );
+                return None;
+            }
+        };
+
+        let task = async move {
+            loop {
+                changes.next().await?;
+
+                // Changes come in bursts, such as the addresses of a link going away with it
+                while let Ok(res) =
+                    tokio::time::timeout(INTERFACE_SETTLE_DELAY, changes.next()).await
+                {
+                    res?;
+                }
+
+                tracing::debug!("Network interfaces changed, refreshing announcements");
+                if let Some(ssdp) = ssdp.lock().await.as_mut() {
+                    if let Err(err) = ssdp.refresh_addresses(Self::get_local_addresses).await {
+                        tracing::warn!("Failed to refresh SSDP announcements: {}", err);
+                    }
+                }
+
+                if let Some(responder) = mdns_responder.lock().await.as_mut() {
+                    match Self::get_interface_addresses() {
+                        Ok(v) => responder.refresh_interfaces(v).await,
+                        Err(err) => tracing::warn!("Failed to refresh mDNS announcements: {}", err),
+                    }
+                }
+            }
+        };
+
+        Some(tokio::spawn(async move {
+            let res: Result<(), std::io::Error> = task.await;
+            if let Err(err) = res {
+                tracing::warn!("Stopped watching network interfaces: {}", err);
+            }
+        }))
+    }
+
     //noinspection DuplicatedCode <- windows implementation
     async fn stop_ssdp(&self) {
         if let Some(ssdp) = self.ssdp.lock().await.take() {
@@ -137,77 +307,182 @@ impl LinuxDiscoveryManager {
     }
 
     fn get_local_addresses() -> Result<Vec<IpAddrWithScopeId>, std::io::Error> {
+        let mut out = Vec::new();
+        Self::walk_interface_addresses(|current| {
+            if !Self::is_up(current) {
+                return;
+            }
+
+            if let Some(address) = Self::ip_address_of(current) {
+                out.push(address);
+            }
+        })?;
+
+        tracing::trace!("Local addresses: {:?}", out);
+
+        Ok(out)
+    }
+
+    /// Retrieves the addresses of the network interfaces which are up, grouped by interface.
+    fn get_interface_addresses() -> Result<Vec<InterfaceAddresses>, std::io::Error> {
+        let mut out: Vec<InterfaceAddresses> = Vec::new();
+        Self::walk_interface_addresses(|current| {
+            if !Self::is_up(current) {
+                return;
+            }
+
+            let Some(address) = Self::ip_address_of(current) else {
+                return;
+            };
+
+            let index = unsafe { libc::if_nametoindex(current.ifa_name) };
+            match out.iter_mut().find(|v| v.index == index) {
+                Some(interface) => interface.addresses.push(address.ip()),
+                None => out.push(InterfaceAddresses {
+                    index,
+                    addresses: vec![address.ip()],
+                }),
+            }
+        })?;
+
+        tracing::trace!("Interface addresses: {:?}", out);
+
+        Ok(out)
+    }
+
+    /// Tests whether the interface of an address entry is up.
+    ///
+    /// Interfaces which are down can't reach anybody, they are announced once they are up.
+    fn is_up(current: &libc::ifaddrs) -> bool {
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_uint;
+        current.ifa_flags & up == up
+    }
+
+    /// Calls the given function for every entry returned by `getifaddrs`.
+    fn walk_interface_addresses(mut f: impl FnMut(&libc::ifaddrs)) -> Result<(), std::io::Error> {
         let mut addresses = std::ptr::null_mut();
         if unsafe { libc::getifaddrs(&mut addresses) } == -1 {
             // Failed to get the addresses
             return Err(std::io::Error::last_os_error());
         }
 
-        let mut out = Vec::new();
-
         let mut next = addresses;
         while !next.is_null() {
             // Get the current address
             let current = unsafe { &*next };
-            if current.ifa_addr.is_null() {
-                next = current.ifa_next;
-                continue;
+            if !current.ifa_addr.is_null() {
+                f(current);
             }
 
-            let current_address = unsafe { &*current.ifa_addr };
+            next = current.ifa_next;
+        }
 
-            // Get the address family
-            let family = current_address.sa_family as libc::c_int;
+        unsafe { libc::freeifaddrs(addresses) };
 
-            match family {
-                libc::AF_INET => {
-                    let address = unsafe { &*(current.ifa_addr as *const libc::sockaddr_in) };
-                    out.push(IpAddrWithScopeId::V4(Ipv4Addr::from(
-                        address.sin_addr.s_addr.to_le_bytes(),
-                    )));
-                }
+        Ok(())
+    }
 
-                libc::AF_INET6 => {
-                    let address = unsafe { &*(current.ifa_addr as *const libc::sockaddr_in6) };
-                    out.push(IpAddrWithScopeId::V6 {
-                        addr: Ipv6Addr::from(address.sin6_addr.s6_addr),
-                        scope_id: address.sin6_scope_id,
-                    });
-                }
+    /// Extracts the IP address of an interface address entry.
+    fn ip_address_of(current: &libc::ifaddrs) -> Option<IpAddrWithScopeId> {
+        let current_address = unsafe { &*current.ifa_addr };
 
-                _ => {
-                    // Unknown address family
-                }
-            };
+        // Get the address family
+        let family = current_address.sa_family as libc::c_int;
 
-            next = current.ifa_next;
+        match family {
+            libc::AF_INET => {
+                let address = unsafe { &*(current.ifa_addr as *const libc::sockaddr_in) };
+                Some(IpAddrWithScopeId::V4(Ipv4Addr::from(
+                    address.sin_addr.s_addr.to_le_bytes(),
+                )))
+            }
+
+            libc::AF_INET6 => {
+                let address = unsafe { &*(current.ifa_addr as *const libc::sockaddr_in6) };
+
+                // The scope id is only set for link-local addresses, but multicast needs the
+                // interface index for every address
+                let scope_id = match address.sin6_scope_id {
+                    0 => unsafe { libc::if_nametoindex(current.ifa_name) },
+                    v => v,
+                };
+
+                Some(IpAddrWithScopeId::V6 {
+                    addr: Ipv6Addr::from(address.sin6_addr.s6_addr),
+                    scope_id,
+                })
+            }
+
+            _ => {
+                // Unknown address family
+                None
+            }
         }
+    }
 
-        unsafe { libc::freeifaddrs(addresses) };
+    /// Extracts the hardware address of an `AF_PACKET` interface address entry.
+    fn mac_address_of(current: &libc::ifaddrs) -> Option<MacAddress> {
+        let current_address = unsafe { &*current.ifa_addr };
+        if current_address.sa_family as libc::c_int != libc::AF_PACKET {
+            return None;
+        }
 
-        tracing::trace!("Local addresses: {:?}", out);
+        let address = unsafe { &*(current.ifa_addr as *const libc::sockaddr_ll) };
+        if address.sll_halen != 6 {
+            // Not an Ethernet style address, such as on tunnel interfaces
+            return None;
+        }
 
-        Ok(out)
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&address.sll_addr[..6]);
+
+        Some(MacAddress(mac))
     }
 }
 
 #[async_trait::async_trait]
 impl DiscoveryManager for LinuxDiscoveryManager {
-    async fn advertise_service(&self, addr: SocketAddr) -> Result<(), PlatformAbstractionError> {
+    async fn advertise_service(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
         let (avahi_res, ssdp_res) = tokio::join!(
-            self.advertise_with_avahi(addr),
-            self.advertise_with_ssdp(addr)
-        );
+            async {
+                if !self.config.is_enabled(DiscoveryBackend::Mdns) {
+                    return None;
+                }
 
-        if let Err(err) = &avahi_res {
-            tracing::warn!("Failed to advertise with Avahi: {}", err);
-        }
+                // Without Avahi, the service is announced by our own responder
+                let res = match self.avahi {
+                    Some(_) => self.advertise_with_avahi(service).await,
+                    None => self.advertise_with_mdns_responder(service).await,
+                };
+                if let Err(err) = &res {
+                    tracing::warn!("Failed to advertise with mDNS: {}", err);
+                }
 
-        if let Err(err) = &ssdp_res {
-            tracing::warn!("Failed to advertise with SSDP: {}", err);
-        }
+                Some(res)
+            },
+            async {
+                if !self.config.is_enabled(DiscoveryBackend::Ssdp) {
+                    return None;
+                }
+
+                let res = self.advertise_with_ssdp(service).await;
+                if let Err(err) = &res {
+                    tracing::warn!("Failed to advertise with SSDP: {}", err);
+                }
+
+                Some(res)
+            }
+        );
 
-        let success = avahi_res.is_ok() || ssdp_res.is_ok();
+        // Only fail if every enabled backend failed
+        let results = [avahi_res, ssdp_res]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let success = results.is_empty() || results.iter().any(Result::is_ok);
         if !success {
             Err(PlatformAbstractionError::Unsupported)
         } else {
@@ -218,9 +493,18 @@ impl DiscoveryManager for LinuxDiscoveryManager {
     async fn stop_advertising_service(&self) -> Result<(), PlatformAbstractionError> {
         let mut error = None;
 
+        // Stop following the network interfaces first, so nothing is restarted meanwhile
+        if let Some(interface_watch) = self.interface_watch.lock().await.take() {
+            interface_watch.abort();
+            let _ = interface_watch.await;
+        }
+
         // Take the group out of the mutex
         let mut registered_dns_service = self.registered_dns_service.lock().await;
-        if let Some(group) = registered_dns_service.take() {
+        if let Some(AvahiRegistration { group, state_task }) = registered_dns_service.take() {
+            state_task.abort();
+            METRICS.avahi_entry_group_state.set(None);
+
             // Release the group
             if let Err(err) = dbus_call!(group.free()).await {
                 tracing::warn!("Failed to free Avahi group: {}", err);
@@ -228,6 +512,11 @@ impl DiscoveryManager for LinuxDiscoveryManager {
             }
         }
 
+        // Stop the built-in responder, which says goodbye
+        if let Some(responder) = self.mdns_responder.lock().await.take() {
+            responder.stop().await;
+        }
+
         // Stop the SSDP multicast sockets
         self.stop_ssdp().await;
 
@@ -236,4 +525,42 @@ impl DiscoveryManager for LinuxDiscoveryManager {
             Some(err) => Err(err.into()),
         }
     }
+
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, PlatformAbstractionError> {
+        let mut interfaces: Vec<NetworkInterface> = Vec::new();
+
+        Self::walk_interface_addresses(|current| {
+            if current.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint != 0 {
+                return;
+            }
+
+            let name = unsafe { CStr::from_ptr(current.ifa_name) }
+                .to_string_lossy()
+                .into_owned();
+
+            // Every interface shows up once per address family
+            let index = match interfaces.iter().position(|i| i.name == name) {
+                Some(v) => v,
+                None => {
+                    interfaces.push(NetworkInterface {
+                        name,
+                        mac_address: None,
+                        addresses: Vec::new(),
+                    });
+                    interfaces.len() - 1
+                }
+            };
+            let interface = &mut interfaces[index];
+
+            if let Some(mac) = Self::mac_address_of(current) {
+                interface.mac_address = Some(mac);
+            } else if let Some(address) = Self::ip_address_of(current) {
+                interface.addresses.push(address.ip());
+            }
+        })?;
+
+        tracing::trace!("Network interfaces: {:?}", interfaces);
+
+        Ok(interfaces)
+    }
 }
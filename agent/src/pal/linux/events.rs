@@ -0,0 +1,76 @@
+use crate::pal::events::{EventManager, SystemEvent, SystemEventStream};
+use crate::pal::platform::dbus::dbus_call;
+use crate::pal::platform::dbus::login1::Login1ManagerProxy;
+use crate::pal::PlatformAbstractionError;
+use futures::StreamExt;
+
+#[derive(Debug)]
+pub struct LinuxEventManager {
+    login1: Option<Login1ManagerProxy<'static>>,
+}
+
+impl LinuxEventManager {
+    pub async fn new(dbus_connection: &zbus::Connection) -> Self {
+        let login1 = match dbus_call!(Login1ManagerProxy::new(dbus_connection)).await {
+            Ok(v) => Some(v),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to connect to Login1 Manager, system events will be unavailable: {}",
+                    err
+                );
+                None
+            }
+        };
+
+        Self { login1 }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventManager for LinuxEventManager {
+    async fn subscribe(&self) -> Result<SystemEventStream, PlatformAbstractionError> {
+        let Some(login1) = self.login1.as_ref() else {
+            return Err(PlatformAbstractionError::Unsupported);
+        };
+
+        let prepare_for_shutdown = dbus_call!(login1.receive_prepare_for_shutdown())
+            .await?
+            .filter_map(|signal| async move {
+                let args = signal.args().ok()?;
+                Some(SystemEvent::PrepareForShutdown { active: args.start })
+            });
+
+        let prepare_for_sleep = dbus_call!(login1.receive_prepare_for_sleep())
+            .await?
+            .filter_map(|signal| async move {
+                let args = signal.args().ok()?;
+                Some(SystemEvent::PrepareForSleep { active: args.start })
+            });
+
+        let session_new =
+            dbus_call!(login1.receive_session_new())
+                .await?
+                .filter_map(|signal| async move {
+                    let args = signal.args().ok()?;
+                    Some(SystemEvent::SessionAdded {
+                        session_id: args.session_id.to_string(),
+                    })
+                });
+
+        let session_removed = dbus_call!(login1.receive_session_removed())
+            .await?
+            .filter_map(|signal| async move {
+                let args = signal.args().ok()?;
+                Some(SystemEvent::SessionRemoved {
+                    session_id: args.session_id.to_string(),
+                })
+            });
+
+        Ok(Box::pin(futures::stream::select_all([
+            prepare_for_shutdown.boxed(),
+            prepare_for_sleep.boxed(),
+            session_new.boxed(),
+            session_removed.boxed(),
+        ])))
+    }
+}
@@ -1,14 +1,24 @@
 mod dbus;
 mod discovery;
+mod events;
+mod netlink;
 mod power;
 mod status;
+mod system;
 
+use crate::config::Config;
 use crate::pal::platform::discovery::LinuxDiscoveryManager;
+use crate::pal::platform::events::LinuxEventManager;
 use crate::pal::platform::power::LinuxPowerManager;
 use crate::pal::platform::status::LinuxStatusManager;
+use crate::pal::platform::system::LinuxSystemInfoManager;
 use crate::pal::{PlatformAbstractionError, PlatformAbstractionLayer, ShutdownRequestFut};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+/// Default directory for persistent data if systemd does not provide one.
+const DEFAULT_DATA_DIRECTORY: &str = "/var/lib/dragon-claw-agent";
+
 // No init data required on Linux, PAL is initialized in the `new` function
 pub type PlatformInitData = ();
 
@@ -17,6 +27,8 @@ pub struct PlatformAbstractionImpl {
     dbus_system_connection: zbus::Connection,
     discovery_manager: LinuxDiscoveryManager,
     power_manager: Option<LinuxPowerManager>,
+    event_manager: LinuxEventManager,
+    data_directory: PathBuf,
 }
 
 impl PlatformAbstractionImpl {
@@ -28,7 +40,10 @@ impl PlatformAbstractionImpl {
         Ok(main((), crate::pal::ctrl_c_shutdown_fut()))
     }
 
-    pub async fn new(_: PlatformInitData) -> Result<Self, PlatformAbstractionError> {
+    pub async fn new(
+        _: PlatformInitData,
+        config: &Config,
+    ) -> Result<Self, PlatformAbstractionError> {
         // Connect to system D-Bus
         let dbus_system_connection = zbus::Connection::system()
             .await
@@ -43,15 +58,29 @@ impl PlatformAbstractionImpl {
         );
 
         // Attempt to connect to Avahi
-        let service_manager = LinuxDiscoveryManager::new(&dbus_system_connection).await;
+        let service_manager =
+            LinuxDiscoveryManager::new(&dbus_system_connection, config.discovery.clone()).await;
 
         // Connect to Login1 Manager
-        let power_manager = LinuxPowerManager::try_connect(&dbus_system_connection).await;
+        let power_manager =
+            LinuxPowerManager::try_connect(&dbus_system_connection, config.power.sessions.clone())
+                .await;
+
+        // Listen for Login1 signals
+        let event_manager = LinuxEventManager::new(&dbus_system_connection).await;
+
+        // Prefer the state directory assigned by systemd (StateDirectory=), which may contain
+        // multiple colon separated paths
+        let data_directory = std::env::var_os("STATE_DIRECTORY")
+            .and_then(|v| std::env::split_paths(&v).next())
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIRECTORY));
 
         Ok(Self {
             dbus_system_connection,
             discovery_manager: service_manager,
             power_manager,
+            event_manager,
+            data_directory,
         })
     }
 }
@@ -74,6 +103,22 @@ impl PlatformAbstractionLayer for PlatformAbstractionImpl {
     fn status_manager(&self) -> &Self::StatusManager {
         &LinuxStatusManager
     }
+
+    type EventManager = LinuxEventManager;
+
+    fn event_manager(&self) -> &Self::EventManager {
+        &self.event_manager
+    }
+
+    type SystemInfoManager = LinuxSystemInfoManager;
+
+    fn system_info_manager(&self) -> &Self::SystemInfoManager {
+        &LinuxSystemInfoManager
+    }
+
+    fn data_directory(&self) -> &Path {
+        &self.data_directory
+    }
 }
 
 #[derive(Debug, Error)]
@@ -83,4 +128,7 @@ pub enum PlatformError {
 
     #[error("D-Bus request timed out")]
     DbusTimeout,
+
+    #[error("no matching graphical session")]
+    NoGraphicalSession,
 }
@@ -0,0 +1,113 @@
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use tokio::io::unix::AsyncFd;
+
+/// Size of the `nlmsghdr` preceding every netlink message.
+const NETLINK_HEADER_SIZE: usize = 16;
+
+/// Netlink messages are aligned to this many bytes.
+const NETLINK_ALIGNMENT: usize = 4;
+
+/// Subscription to the rtnetlink notifications about network interfaces and their addresses.
+#[derive(Debug)]
+pub struct InterfaceChanges {
+    socket: AsyncFd<OwnedFd>,
+    buffer: Vec<u8>,
+}
+
+impl InterfaceChanges {
+    /// Subscribes to links and IPv4 and IPv6 addresses being added, removed or changed.
+    pub fn subscribe() -> Result<Self, std::io::Error> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // Take ownership right away, so the socket is closed on errors
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups =
+            (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+
+        let res = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+            buffer: vec![0u8; 16 * 1024],
+        })
+    }
+
+    /// Waits until an interface or address changed.
+    pub async fn next(&mut self) -> Result<(), std::io::Error> {
+        loop {
+            let mut guard = self.socket.readable().await?;
+
+            let res = guard.try_io(|socket| {
+                let len = unsafe {
+                    libc::recv(
+                        socket.as_raw_fd(),
+                        self.buffer.as_mut_ptr() as *mut libc::c_void,
+                        self.buffer.len(),
+                        0,
+                    )
+                };
+
+                match len {
+                    -1 => Err(std::io::Error::last_os_error()),
+                    len => Ok(len as usize),
+                }
+            });
+
+            match res {
+                Ok(Ok(len)) if Self::contains_change(&self.buffer[..len]) => return Ok(()),
+                Ok(Ok(_)) => {}
+                // The kernel dropped notifications, so something may have changed unnoticed
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::ENOBUFS) => return Ok(()),
+                Ok(Err(err)) => return Err(err),
+                // Spurious wakeup, the readiness has been cleared
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Tests whether the received netlink messages report a change of an interface or address.
+    fn contains_change(mut data: &[u8]) -> bool {
+        while data.len() >= NETLINK_HEADER_SIZE {
+            let len = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            let message_type = u16::from_ne_bytes([data[4], data[5]]);
+
+            if matches!(
+                message_type,
+                libc::RTM_NEWLINK | libc::RTM_DELLINK | libc::RTM_NEWADDR | libc::RTM_DELADDR
+            ) {
+                return true;
+            }
+
+            // Skip to the next message, a malformed length ends the walk
+            let aligned_len = (len + NETLINK_ALIGNMENT - 1) & !(NETLINK_ALIGNMENT - 1);
+            if len < NETLINK_HEADER_SIZE || aligned_len > data.len() {
+                break;
+            }
+
+            data = &data[aligned_len..];
+        }
+
+        false
+    }
+}
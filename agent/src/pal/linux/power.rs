@@ -1,18 +1,37 @@
+use crate::config::SessionTarget;
 use crate::pal::platform::dbus::dbus_call;
-use crate::pal::platform::dbus::login1::Login1ManagerProxy;
+use crate::pal::platform::dbus::login1::{Login1ManagerProxy, Login1SessionProxy};
 use crate::pal::platform::PlatformError;
-use crate::pal::power::{PowerAction, PowerManager};
+use crate::pal::power::{PowerAction, PowerManager, SleepInhibitor};
 use crate::pal::PlatformAbstractionError;
 use std::future::Future;
 use std::pin::Pin;
+use std::time::SystemTime;
+
+/// Name the agent identifies itself with when taking inhibitor locks.
+const INHIBITOR_NAME: &str = "Dragon Claw Agent";
+
+/// Session types which have a graphical user interface.
+const GRAPHICAL_SESSION_TYPES: &[&str] = &["x11", "wayland", "mir"];
+
+/// A graphical session as reported by login1.
+#[derive(Debug)]
+struct GraphicalSession {
+    id: String,
+    uid: u32,
+}
 
 #[derive(Debug)]
 pub struct LinuxPowerManager {
     login1: Login1ManagerProxy<'static>,
+    sessions: SessionTarget,
 }
 
 impl LinuxPowerManager {
-    pub async fn try_connect(dbus_connection: &zbus::Connection) -> Option<Self> {
+    pub async fn try_connect(
+        dbus_connection: &zbus::Connection,
+        sessions: SessionTarget,
+    ) -> Option<Self> {
         let login1 = match dbus_call!(Login1ManagerProxy::new(dbus_connection)).await {
             Ok(v) => v,
             Err(err) => {
@@ -24,7 +43,81 @@ impl LinuxPowerManager {
             }
         };
 
-        Some(Self { login1 })
+        Some(Self { login1, sessions })
+    }
+
+    /// Retrieves the graphical sessions matching the configured session target.
+    async fn graphical_sessions(&self) -> Result<Vec<GraphicalSession>, PlatformError> {
+        let mut out = Vec::new();
+
+        for (id, uid, user, seat, path) in dbus_call!(self.login1.list_sessions()).await? {
+            if self.sessions.seat.as_ref().is_some_and(|s| *s != seat)
+                || self.sessions.user.as_ref().is_some_and(|u| *u != user)
+            {
+                continue;
+            }
+
+            let session = Login1SessionProxy::builder(self.login1.inner().connection())
+                .path(path)?
+                .cache_properties(zbus::CacheProperties::No)
+                .build();
+            let session_type = match dbus_call!(session).await {
+                Ok(session) => dbus_call!(session.session_type()).await,
+                Err(err) => Err(err),
+            };
+
+            // Sessions may disappear at any time, so don't fail the entire query
+            match session_type {
+                Ok(ty) if GRAPHICAL_SESSION_TYPES.contains(&ty.as_str()) => {
+                    out.push(GraphicalSession { id, uid })
+                }
+                Ok(_) => {}
+                Err(err) => tracing::debug!("Failed to query type of session {}: {}", id, err),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Locks the targeted graphical sessions.
+    async fn lock_sessions(&self) -> Result<(), PlatformError> {
+        if self.sessions.seat.is_none() && self.sessions.user.is_none() {
+            return dbus_call!(self.login1.lock_sessions()).await;
+        }
+
+        let sessions = self.graphical_sessions().await?;
+        if sessions.is_empty() {
+            return Err(PlatformError::NoGraphicalSession);
+        }
+
+        for session in sessions {
+            tracing::debug!("Locking session {}", session.id);
+            dbus_call!(self.login1.lock_session(&session.id)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Logs out of the targeted graphical sessions.
+    ///
+    /// If a user is targeted, all of their sessions are terminated.
+    async fn log_out_sessions(&self) -> Result<(), PlatformError> {
+        let sessions = self.graphical_sessions().await?;
+        let Some(first) = sessions.first() else {
+            return Err(PlatformError::NoGraphicalSession);
+        };
+
+        if self.sessions.user.is_some() {
+            tracing::debug!("Terminating sessions of user {}", first.uid);
+            return dbus_call!(self.login1.terminate_user(first.uid)).await;
+        }
+
+        for session in sessions {
+            tracing::debug!("Terminating session {}", session.id);
+            dbus_call!(self.login1.terminate_session(&session.id)).await?;
+        }
+
+        Ok(())
     }
 }
 
@@ -74,6 +167,12 @@ impl PowerManager for LinuxPowerManager {
             dbus_call!(self.login1.can_hybrid_sleep())
         )
         .await?;
+        test_power_action!(
+            &mut actions,
+            PowerAction::SuspendThenHibernate,
+            dbus_call!(self.login1.can_suspend_then_hibernate())
+        )
+        .await?;
         test_power_action!(
             &mut actions,
             PowerAction::RebootToFirmware,
@@ -81,6 +180,12 @@ impl PowerManager for LinuxPowerManager {
         )
         .await?;
 
+        // Session actions require someone to be logged in graphically
+        if !self.graphical_sessions().await?.is_empty() {
+            actions.push(PowerAction::Lock);
+            actions.push(PowerAction::LogOut);
+        }
+
         Ok(actions)
     }
 
@@ -90,6 +195,13 @@ impl PowerManager for LinuxPowerManager {
     ) -> Result<(), PlatformAbstractionError> {
         tracing::trace!("Performing power action {:?}", action);
 
+        // Session actions don't touch the system power state
+        match action {
+            PowerAction::Lock => return Ok(self.lock_sessions().await?),
+            PowerAction::LogOut => return Ok(self.log_out_sessions().await?),
+            _ => {}
+        }
+
         let reboot_to_firmware = action == PowerAction::RebootToFirmware;
         self.login1
             .set_reboot_to_firmware_setup(reboot_to_firmware)
@@ -104,19 +216,61 @@ impl PowerManager for LinuxPowerManager {
             PowerAction::Reboot => Box::pin(async move { login1.reboot(false).await }),
             PowerAction::Suspend => Box::pin(async move { login1.suspend(false).await }),
             PowerAction::Hibernate => Box::pin(async move { login1.hibernate(false).await }),
-            PowerAction::HybridSuspend => Box::pin(async move { login1.hibernate(false).await }),
+            PowerAction::HybridSuspend => Box::pin(async move { login1.hybrid_sleep(false).await }),
+            PowerAction::SuspendThenHibernate => {
+                Box::pin(async move { login1.suspend_then_hibernate(false).await })
+            }
             PowerAction::RebootToFirmware => Box::pin(async move { login1.reboot(false).await }),
-            PowerAction::Lock => return Err(PlatformAbstractionError::Unsupported),
-            PowerAction::LogOut => return Err(PlatformAbstractionError::Unsupported),
+            PowerAction::Lock | PowerAction::LogOut => unreachable!("Handled above"),
         };
 
-        tokio::spawn(async move {
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            if let Err(err) = action_fut.await {
-                tracing::error!("Failed to perform power action: {}", err);
-            }
-        });
+        dbus_call!(action_fut).await?;
+
+        Ok(())
+    }
+
+    async fn schedule_power_action(
+        &self,
+        action: PowerAction,
+        at: SystemTime,
+    ) -> Result<bool, PlatformAbstractionError> {
+        // login1 only knows how to schedule shutdowns
+        let ty = match action {
+            PowerAction::PowerOff => "poweroff",
+            PowerAction::Reboot => "reboot",
+            _ => return Ok(false),
+        };
+
+        let usec = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+
+        dbus_call!(self.login1.schedule_shutdown(ty, usec)).await?;
+        tracing::debug!("Scheduled {} with login1", ty);
+
+        Ok(true)
+    }
+
+    async fn cancel_scheduled_power_action(&self) -> Result<(), PlatformAbstractionError> {
+        let cancelled = dbus_call!(self.login1.cancel_scheduled_shutdown()).await?;
+        if !cancelled {
+            tracing::warn!("login1 had no scheduled shutdown to cancel");
+        }
 
         Ok(())
     }
+
+    async fn inhibit_sleep(
+        &self,
+        reason: &str,
+    ) -> Result<SleepInhibitor, PlatformAbstractionError> {
+        // login1 holds the lock until the file descriptor is closed
+        let fd = dbus_call!(self
+            .login1
+            .inhibit("sleep:idle", INHIBITOR_NAME, reason, "block"))
+        .await?;
+
+        Ok(Box::new(fd))
+    }
 }
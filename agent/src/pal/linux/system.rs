@@ -0,0 +1,251 @@
+use crate::pal::system::{
+    CpuInfo, DiskUsage, LoadAverage, MemoryUsage, OsRelease, SystemInfo, SystemInfoManager,
+};
+use crate::pal::PlatformAbstractionError;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::time::Duration;
+
+/// os-release(5) locations, in order of precedence.
+const OS_RELEASE_PATHS: [&str; 2] = ["/etc/os-release", "/usr/lib/os-release"];
+
+#[derive(Debug)]
+pub struct LinuxSystemInfoManager;
+
+impl LinuxSystemInfoManager {
+    /// Gathers the system information, reading `/proc` and friends is blocking.
+    fn gather() -> SystemInfo {
+        SystemInfo {
+            os_release: Self::os_release(),
+            kernel_version: read_trimmed("/proc/sys/kernel/osrelease"),
+            uptime: Self::uptime(),
+            boot_time: Self::boot_time(),
+            cpu: Self::cpu(),
+            load_average: Self::load_average(),
+            memory: Self::memory_usage("MemTotal", "MemAvailable"),
+            swap: Self::memory_usage("SwapTotal", "SwapFree"),
+            disks: Self::disks(),
+        }
+    }
+
+    fn os_release() -> Option<OsRelease> {
+        let data = OS_RELEASE_PATHS
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())?;
+
+        let mut values = data
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), unquote(value.trim())))
+            .collect::<HashMap<_, _>>();
+
+        Some(OsRelease {
+            // os-release(5) defines "Linux" as default name
+            name: values.remove("NAME").unwrap_or_else(|| "Linux".to_string()),
+            version: values.remove("VERSION"),
+            id: values.remove("ID"),
+            pretty_name: values.remove("PRETTY_NAME"),
+        })
+    }
+
+    fn uptime() -> Option<Duration> {
+        // The first value is the uptime in seconds, the second one the idle time
+        let data = std::fs::read_to_string("/proc/uptime").ok()?;
+        let seconds = data.split_whitespace().next()?.parse::<f64>().ok()?;
+
+        Some(Duration::from_secs_f64(seconds))
+    }
+
+    fn boot_time() -> Option<u64> {
+        let data = std::fs::read_to_string("/proc/stat").ok()?;
+        data.lines()
+            .find_map(|line| line.strip_prefix("btime "))
+            .and_then(|v| v.trim().parse().ok())
+    }
+
+    fn cpu() -> Option<CpuInfo> {
+        let data = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+
+        let mut model = None;
+        let mut logical_cores = 0;
+        for (key, value) in data.lines().filter_map(|line| line.split_once(':')) {
+            match key.trim() {
+                "processor" => logical_cores += 1,
+                // "Model" is used by ARM boards such as the Raspberry Pi
+                "model name" | "Model" if model.is_none() => model = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+
+        Some(CpuInfo {
+            model,
+            logical_cores,
+        })
+    }
+
+    fn load_average() -> Option<LoadAverage> {
+        let data = std::fs::read_to_string("/proc/loadavg").ok()?;
+        let mut values = data.split_whitespace().map(|v| v.parse::<f64>().ok());
+
+        Some(LoadAverage {
+            one: values.next()??,
+            five: values.next()??,
+            fifteen: values.next()??,
+        })
+    }
+
+    fn memory_usage(total_key: &str, available_key: &str) -> Option<MemoryUsage> {
+        let data = std::fs::read_to_string("/proc/meminfo").ok()?;
+
+        // Values are given in kibibytes, such as "MemTotal:       16318516 kB"
+        let value_of = |key: &str| {
+            data.lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, v)| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+                .map(|v| v * 1024)
+        };
+
+        Some(MemoryUsage {
+            total: value_of(total_key)?,
+            available: value_of(available_key)?,
+        })
+    }
+
+    fn disks() -> Vec<DiskUsage> {
+        let data = match std::fs::read_to_string("/proc/self/mounts") {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::warn!("Failed to read mounts: {}", err);
+                return Vec::new();
+            }
+        };
+
+        let mut disks = Vec::new();
+        for line in data.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(device), Some(mount_point), Some(file_system)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+
+            // Only file systems backed by a device, this skips proc, sysfs, tmpfs and friends
+            if !device.starts_with('/') {
+                continue;
+            }
+
+            let mount_point = unescape_mount_field(mount_point);
+            let Some((total, available)) = statvfs(&mount_point) else {
+                continue;
+            };
+
+            disks.push(DiskUsage {
+                mount_point,
+                device: unescape_mount_field(device),
+                file_system: file_system.to_string(),
+                total,
+                available,
+            });
+        }
+
+        disks
+    }
+}
+
+#[async_trait::async_trait]
+impl SystemInfoManager for LinuxSystemInfoManager {
+    async fn get_system_info(&self) -> Result<SystemInfo, PlatformAbstractionError> {
+        let info = tokio::task::spawn_blocking(Self::gather)
+            .await
+            .map_err(std::io::Error::from)?;
+
+        Ok(info)
+    }
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|v| v.trim().to_string())
+}
+
+/// Removes the quotes around an os-release value, along with the shell escapes inside.
+fn unquote(value: &str) -> String {
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')));
+
+    match inner {
+        None => value.to_string(),
+        Some(inner) => {
+            let mut out = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match (c, chars.clone().next()) {
+                    ('\\', Some(next)) => {
+                        out.push(next);
+                        chars.next();
+                    }
+                    _ => out.push(c),
+                }
+            }
+
+            out
+        }
+    }
+}
+
+/// Decodes the octal escapes (such as `\040` for a space) of fields in `/proc/self/mounts`.
+fn unescape_mount_field(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| u8::from_str_radix(v, 8).ok());
+
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Retrieves the total and available bytes of the file system mounted at the given path.
+#[allow(clippy::unnecessary_cast)] // The field types are only 32 bits wide on some targets
+fn statvfs(path: &str) -> Option<(u64, u64)> {
+    let path = CString::new(path).ok()?;
+
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        tracing::debug!(
+            "Failed to stat file system at {:?}: {}",
+            path,
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    let stat = unsafe { stat.assume_init() };
+    let fragment_size = stat.f_frsize as u64;
+
+    Some((
+        stat.f_blocks as u64 * fragment_size,
+        // Blocks available to unprivileged users, root may be able to use more
+        stat.f_bavail as u64 * fragment_size,
+    ))
+}
@@ -0,0 +1,41 @@
+use crate::pal::discovery::{DiscoveryManager, MacAddress, NetworkInterface, ServiceInfo};
+use crate::pal::PlatformAbstractionError;
+
+/// Name of the simulated network interface.
+const MOCK_INTERFACE_NAME: &str = "mock0";
+
+/// Hardware address of the simulated network interface, a locally administered one.
+const MOCK_MAC_ADDRESS: MacAddress = MacAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+#[derive(Debug)]
+pub struct MockDiscoveryManager;
+
+#[async_trait::async_trait]
+impl DiscoveryManager for MockDiscoveryManager {
+    async fn advertise_service(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
+        // Clients have to connect to the address directly
+        tracing::info!(
+            "Not advertising service on {}, discovery is simulated: {:?}",
+            service.socket_addr,
+            service.properties()
+        );
+
+        Ok(())
+    }
+
+    async fn stop_advertising_service(&self) -> Result<(), PlatformAbstractionError> {
+        Ok(())
+    }
+
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, PlatformAbstractionError> {
+        // Without addresses, so magic packets are never actually sent
+        Ok(vec![NetworkInterface {
+            name: MOCK_INTERFACE_NAME.to_string(),
+            mac_address: Some(MOCK_MAC_ADDRESS),
+            addresses: Vec::new(),
+        }])
+    }
+}
@@ -0,0 +1,7 @@
+use crate::pal::events::EventManager;
+
+#[derive(Debug)]
+pub struct MockEventManager;
+
+// The simulated platform has no events to report
+impl EventManager for MockEventManager {}
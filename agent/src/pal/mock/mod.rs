@@ -0,0 +1,99 @@
+mod discovery;
+mod events;
+mod power;
+mod status;
+mod system;
+
+use crate::config::Config;
+use crate::pal::platform::discovery::MockDiscoveryManager;
+use crate::pal::platform::events::MockEventManager;
+use crate::pal::platform::power::MockPowerManager;
+use crate::pal::platform::status::MockStatusManager;
+use crate::pal::platform::system::MockSystemInfoManager;
+use crate::pal::{PlatformAbstractionError, PlatformAbstractionLayer, ShutdownRequestFut};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the default data directory, inside the temporary directory.
+const DEFAULT_DATA_DIRECTORY_NAME: &str = "dragon-claw-agent-mock";
+
+// Nothing to initialize, the simulated platform does not talk to the system at all
+pub type PlatformInitData = ();
+
+/// Simulated platform which never touches the system, for running the agent during development.
+#[derive(Debug)]
+pub struct PlatformAbstractionImpl {
+    power_manager: MockPowerManager,
+    discovery_manager: MockDiscoveryManager,
+    data_directory: PathBuf,
+}
+
+impl PlatformAbstractionImpl {
+    pub fn dispatch_main<F, R>(main: F) -> Result<R, PlatformAbstractionError>
+    where
+        F: FnOnce(PlatformInitData, ShutdownRequestFut) -> R,
+    {
+        Ok(main((), crate::pal::ctrl_c_shutdown_fut()))
+    }
+
+    pub async fn new(
+        _: PlatformInitData,
+        config: &Config,
+    ) -> Result<Self, PlatformAbstractionError> {
+        tracing::warn!("Using the simulated platform, power actions will not be performed");
+
+        let data_directory = config
+            .mock
+            .data_directory
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(DEFAULT_DATA_DIRECTORY_NAME));
+
+        Ok(Self {
+            power_manager: MockPowerManager::new(
+                config.mock.supported_actions.clone(),
+                config.mock.action_log.clone(),
+            ),
+            discovery_manager: MockDiscoveryManager,
+            data_directory,
+        })
+    }
+}
+
+impl PlatformAbstractionLayer for PlatformAbstractionImpl {
+    type PowerManager = MockPowerManager;
+
+    fn power_manager(&self) -> Option<&Self::PowerManager> {
+        Some(&self.power_manager)
+    }
+
+    type DiscoveryManager = MockDiscoveryManager;
+
+    fn discovery_manager(&self) -> &Self::DiscoveryManager {
+        &self.discovery_manager
+    }
+
+    type StatusManager = MockStatusManager;
+
+    fn status_manager(&self) -> &Self::StatusManager {
+        &MockStatusManager
+    }
+
+    type EventManager = MockEventManager;
+
+    fn event_manager(&self) -> &Self::EventManager {
+        &MockEventManager
+    }
+
+    type SystemInfoManager = MockSystemInfoManager;
+
+    fn system_info_manager(&self) -> &Self::SystemInfoManager {
+        &MockSystemInfoManager
+    }
+
+    fn data_directory(&self) -> &Path {
+        &self.data_directory
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PlatformError {}
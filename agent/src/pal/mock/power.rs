@@ -0,0 +1,94 @@
+use crate::pal::power::{PowerAction, PowerManager, SleepInhibitor};
+use crate::pal::PlatformAbstractionError;
+use crate::util::unix_time_now;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+/// Every power action, claimed to be supported if none are configured.
+const ALL_POWER_ACTIONS: [PowerAction; 9] = [
+    PowerAction::PowerOff,
+    PowerAction::Reboot,
+    PowerAction::RebootToFirmware,
+    PowerAction::Lock,
+    PowerAction::LogOut,
+    PowerAction::Suspend,
+    PowerAction::Hibernate,
+    PowerAction::HybridSuspend,
+    PowerAction::SuspendThenHibernate,
+];
+
+/// Pretends to perform power actions, recording them instead.
+#[derive(Debug)]
+pub struct MockPowerManager {
+    supported_actions: Vec<PowerAction>,
+    action_log: Option<PathBuf>,
+}
+
+impl MockPowerManager {
+    pub fn new(supported_actions: Option<Vec<PowerAction>>, action_log: Option<PathBuf>) -> Self {
+        Self {
+            supported_actions: supported_actions.unwrap_or_else(|| ALL_POWER_ACTIONS.to_vec()),
+            action_log,
+        }
+    }
+
+    /// Appends a performed action to the action log as a JSON line.
+    async fn record(&self, action: PowerAction) -> Result<(), std::io::Error> {
+        let Some(path) = &self.action_log else {
+            return Ok(());
+        };
+
+        let mut line = serde_json::to_vec(&serde_json::json!({
+            "timestamp": unix_time_now(),
+            "action": action,
+        }))?;
+        line.push(b'\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(&line).await
+    }
+}
+
+#[async_trait::async_trait]
+impl PowerManager for MockPowerManager {
+    async fn get_supported_power_actions(
+        &self,
+    ) -> Result<Vec<PowerAction>, PlatformAbstractionError> {
+        Ok(self.supported_actions.clone())
+    }
+
+    async fn perform_power_action(
+        &self,
+        action: PowerAction,
+    ) -> Result<(), PlatformAbstractionError> {
+        if !self.supported_actions.contains(&action) {
+            return Err(PlatformAbstractionError::Unsupported);
+        }
+
+        tracing::warn!("Simulating power action {:?}", action);
+        self.record(action).await?;
+
+        Ok(())
+    }
+
+    async fn inhibit_sleep(
+        &self,
+        reason: &str,
+    ) -> Result<SleepInhibitor, PlatformAbstractionError> {
+        tracing::info!("Simulating sleep inhibitor for \"{}\"", reason);
+        Ok(Box::new(MockSleepInhibitor))
+    }
+}
+
+#[derive(Debug)]
+struct MockSleepInhibitor;
+
+impl Drop for MockSleepInhibitor {
+    fn drop(&mut self) {
+        tracing::info!("Released simulated sleep inhibitor");
+    }
+}
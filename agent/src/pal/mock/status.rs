@@ -0,0 +1,11 @@
+use crate::pal::status::{ApplicationStatus, StatusManager};
+
+#[derive(Debug)]
+pub struct MockStatusManager;
+
+#[async_trait::async_trait]
+impl StatusManager for MockStatusManager {
+    async fn set_status(&self, status: ApplicationStatus) {
+        tracing::debug!("Simulated platform status: {:?}", status);
+    }
+}
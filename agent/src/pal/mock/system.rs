@@ -0,0 +1,7 @@
+use crate::pal::system::SystemInfoManager;
+
+#[derive(Debug)]
+pub struct MockSystemInfoManager;
+
+// There is no simulated system to report about
+impl SystemInfoManager for MockSystemInfoManager {}
@@ -1,23 +1,33 @@
 use std::borrow::Cow;
+use crate::config::Config;
 use crate::pal::discovery::DiscoveryManager;
+use crate::pal::events::EventManager;
 use crate::pal::power::PowerManager;
 use crate::pal::status::StatusManager;
+use crate::pal::system::SystemInfoManager;
 use std::future;
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
 use thiserror::Error;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "mock-platform")))]
 #[path = "linux/mod.rs"]
 mod platform;
 
-#[cfg(windows)]
+#[cfg(all(windows, not(feature = "mock-platform")))]
 #[path = "windows/mod.rs"]
 mod platform;
 
+#[cfg(feature = "mock-platform")]
+#[path = "mock/mod.rs"]
+mod platform;
+
 pub mod discovery;
+pub mod events;
 pub mod power;
 pub mod status;
+pub mod system;
 
 /// Opaque type for platform-specific initialization data.
 pub type PlatformInitData = platform::PlatformInitData;
@@ -36,6 +46,10 @@ pub type PlatformDiscoveryManager =
     <platform::PlatformAbstractionImpl as PlatformAbstractionLayer>::DiscoveryManager;
 pub type PlatformStatusManager =
     <platform::PlatformAbstractionImpl as PlatformAbstractionLayer>::StatusManager;
+pub type PlatformEventManager =
+    <platform::PlatformAbstractionImpl as PlatformAbstractionLayer>::EventManager;
+pub type PlatformSystemInfoManager =
+    <platform::PlatformAbstractionImpl as PlatformAbstractionLayer>::SystemInfoManager;
 
 impl PlatformAbstraction {
     pub fn dispatch_main<F, R>(main: F) -> Result<R, PlatformAbstractionError>
@@ -46,8 +60,11 @@ impl PlatformAbstraction {
     }
 
     /// Creates a new platform abstraction layer.
-    pub async fn new(data: PlatformInitData) -> Result<Self, PlatformAbstractionError> {
-        let platform = platform::PlatformAbstractionImpl::new(data).await?;
+    pub async fn new(
+        data: PlatformInitData,
+        config: &Config,
+    ) -> Result<Self, PlatformAbstractionError> {
+        let platform = platform::PlatformAbstractionImpl::new(data, config).await?;
         Ok(Self { platform })
     }
 
@@ -65,6 +82,21 @@ impl PlatformAbstraction {
     pub fn status_manager(&self) -> &PlatformStatusManager {
         self.platform.status_manager()
     }
+
+    /// Retrieves the event manager.
+    pub fn event_manager(&self) -> &PlatformEventManager {
+        self.platform.event_manager()
+    }
+
+    /// Retrieves the system information manager.
+    pub fn system_info_manager(&self) -> &PlatformSystemInfoManager {
+        self.platform.system_info_manager()
+    }
+
+    /// Retrieves the directory where persistent agent data is stored.
+    pub fn data_directory(&self) -> &Path {
+        self.platform.data_directory()
+    }
 }
 
 pub trait PlatformAbstractionLayer: Send + Sync + 'static {
@@ -85,6 +117,23 @@ pub trait PlatformAbstractionLayer: Send + Sync + 'static {
 
     /// Retrieves the status manager.
     fn status_manager(&self) -> &Self::StatusManager;
+
+    /// The type of the event manager.
+    type EventManager: EventManager;
+
+    /// Retrieves the event manager.
+    fn event_manager(&self) -> &Self::EventManager;
+
+    /// The type of the system information manager.
+    type SystemInfoManager: SystemInfoManager;
+
+    /// Retrieves the system information manager.
+    fn system_info_manager(&self) -> &Self::SystemInfoManager;
+
+    /// Retrieves the directory where persistent agent data is stored.
+    ///
+    /// The directory is not guaranteed to exist yet.
+    fn data_directory(&self) -> &Path;
 }
 
 #[derive(Debug, Error)]
@@ -100,6 +149,7 @@ pub enum PlatformAbstractionError {
 }
 
 /// Fallback name used if the device name cannot be retrieved.
+#[cfg_attr(feature = "mock-platform", allow(dead_code))] // The mock platform has a fixed name
 pub(in crate::pal) const FALLBACK_NAME: Cow<'static, str> = Cow::Borrowed("Dragon Claw Computer");
 
 pub(in crate::pal) fn ctrl_c_shutdown_fut() -> ShutdownRequestFut {
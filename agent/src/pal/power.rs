@@ -1,6 +1,13 @@
 use crate::pal::PlatformAbstractionError;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::time::SystemTime;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Opaque handle keeping the system from sleeping, dropping it releases the lock.
+pub type SleepInhibitor = Box<dyn Debug + Send + Sync>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PowerAction {
     /// Power-off the system
     PowerOff,
@@ -25,6 +32,26 @@ pub enum PowerAction {
 
     /// Hybrid-suspend the system
     HybridSuspend,
+
+    /// Suspend the system and hibernate it after a while
+    SuspendThenHibernate,
+}
+
+impl PowerAction {
+    /// Retrieves the name of the action, as used in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PowerOff => "power_off",
+            Self::Reboot => "reboot",
+            Self::RebootToFirmware => "reboot_to_firmware",
+            Self::Lock => "lock",
+            Self::LogOut => "log_out",
+            Self::Suspend => "suspend",
+            Self::Hibernate => "hibernate",
+            Self::HybridSuspend => "hybrid_suspend",
+            Self::SuspendThenHibernate => "suspend_then_hibernate",
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -39,4 +66,29 @@ pub trait PowerManager: Send + Sync + 'static {
         &self,
         action: PowerAction,
     ) -> Result<(), PlatformAbstractionError>;
+
+    /// Hands a power action scheduled for the given time over to the platform.
+    ///
+    /// Returns `true` if the platform will perform the action by itself, or `false` if the
+    /// caller has to perform it at the given time.
+    async fn schedule_power_action(
+        &self,
+        _action: PowerAction,
+        _at: SystemTime,
+    ) -> Result<bool, PlatformAbstractionError> {
+        Ok(false)
+    }
+
+    /// Cancels a power action previously handed over using `schedule_power_action`.
+    async fn cancel_scheduled_power_action(&self) -> Result<(), PlatformAbstractionError> {
+        Err(PlatformAbstractionError::Unsupported)
+    }
+
+    /// Prevents the system from going to sleep, until the returned handle is dropped.
+    async fn inhibit_sleep(
+        &self,
+        _reason: &str,
+    ) -> Result<SleepInhibitor, PlatformAbstractionError> {
+        Err(PlatformAbstractionError::Unsupported)
+    }
 }
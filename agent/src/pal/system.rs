@@ -0,0 +1,100 @@
+use crate::pal::PlatformAbstractionError;
+use std::time::Duration;
+
+/// Information about the operating system and hardware of the machine.
+///
+/// Every part is optional, so a platform can report whatever it is able to determine.
+#[derive(Debug, Clone, Default)]
+pub struct SystemInfo {
+    /// The operating system release.
+    pub os_release: Option<OsRelease>,
+
+    /// The version of the running kernel.
+    pub kernel_version: Option<String>,
+
+    /// Time since the system has been booted.
+    pub uptime: Option<Duration>,
+
+    /// Time the system has been booted at, in seconds since the unix epoch.
+    pub boot_time: Option<u64>,
+
+    /// The processor of the machine.
+    pub cpu: Option<CpuInfo>,
+
+    /// The system load averages.
+    pub load_average: Option<LoadAverage>,
+
+    /// Usage of the physical memory.
+    pub memory: Option<MemoryUsage>,
+
+    /// Usage of the swap space.
+    pub swap: Option<MemoryUsage>,
+
+    /// Usage of the mounted file systems.
+    pub disks: Vec<DiskUsage>,
+}
+
+/// Identification of the operating system.
+#[derive(Debug, Clone)]
+pub struct OsRelease {
+    /// Name of the operating system, such as `Fedora Linux`.
+    pub name: String,
+
+    /// Version of the operating system, such as `38 (Workstation Edition)`.
+    pub version: Option<String>,
+
+    /// Machine readable identifier of the operating system, such as `fedora`.
+    pub id: Option<String>,
+
+    /// Name including the version, suitable for presentation.
+    pub pretty_name: Option<String>,
+}
+
+/// Information about the processor.
+#[derive(Debug, Clone)]
+pub struct CpuInfo {
+    /// The model name of the processor.
+    pub model: Option<String>,
+
+    /// Number of logical cores.
+    pub logical_cores: u32,
+}
+
+/// Average number of runnable tasks over the last 1, 5 and 15 minutes.
+#[derive(Debug, Copy, Clone)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+/// Usage of memory or swap space, in bytes.
+#[derive(Debug, Copy, Clone)]
+pub struct MemoryUsage {
+    pub total: u64,
+    pub available: u64,
+}
+
+/// Usage of a mounted file system, in bytes.
+#[derive(Debug, Clone)]
+pub struct DiskUsage {
+    /// Where the file system is mounted.
+    pub mount_point: String,
+
+    /// The device backing the file system.
+    pub device: String,
+
+    /// Type of the file system, such as `ext4`.
+    pub file_system: String,
+
+    pub total: u64,
+    pub available: u64,
+}
+
+#[async_trait::async_trait]
+pub trait SystemInfoManager: Send + Sync + 'static {
+    /// Gathers information about the system.
+    async fn get_system_info(&self) -> Result<SystemInfo, PlatformAbstractionError> {
+        Err(PlatformAbstractionError::Unsupported)
+    }
+}
@@ -1,16 +1,18 @@
 use std::borrow::Cow;
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::net::{Ipv4Addr, Ipv6Addr};
 use windows::core::Error as Win32Error;
 
 use tokio::sync::Mutex;
 use windows::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, WIN32_ERROR};
 use windows::Win32::NetworkManagement::IpHelper::{
     GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER,
-    GAA_FLAG_SKIP_FRIENDLY_NAME, GAA_FLAG_SKIP_MULTICAST, IP_ADAPTER_ADDRESSES_LH,
+    GAA_FLAG_SKIP_FRIENDLY_NAME, GAA_FLAG_SKIP_MULTICAST, GET_ADAPTERS_ADDRESSES_FLAGS,
+    IF_TYPE_SOFTWARE_LOOPBACK, IP_ADAPTER_ADDRESSES_LH,
 };
 use windows::Win32::Networking::WinSock::{AF_INET, AF_INET6, SOCKADDR_IN, SOCKADDR_IN6};
 
-use crate::pal::discovery::DiscoveryManager;
+use crate::config::{DiscoveryBackend, DiscoveryConfig};
+use crate::pal::discovery::{DiscoveryManager, MacAddress, NetworkInterface, ServiceInfo};
 use crate::pal::platform::dns::ServiceDnsRegistration;
 use crate::pal::platform::name::ComputerName;
 use crate::pal::platform::PlatformError;
@@ -19,6 +21,7 @@ use crate::ssdp::{IpAddrWithScopeId, SSDPMulticast};
 
 #[derive(Debug)]
 pub struct WindowsDiscoveryManager {
+    config: DiscoveryConfig,
     service_name: Cow<'static, str>,
     computer_name: Option<ComputerName>,
     dns_registration: Mutex<Option<ServiceDnsRegistration>>,
@@ -26,7 +29,7 @@ pub struct WindowsDiscoveryManager {
 }
 
 impl WindowsDiscoveryManager {
-    pub fn new() -> Self {
+    pub fn new(config: DiscoveryConfig) -> Self {
         let computer_name = match ComputerName::determine() {
             Ok(name) => Some(name),
             Err(err) => {
@@ -45,6 +48,7 @@ impl WindowsDiscoveryManager {
         };
 
         Self {
+            config,
             service_name,
             computer_name,
             dns_registration: Mutex::new(None),
@@ -52,7 +56,10 @@ impl WindowsDiscoveryManager {
         }
     }
 
-    async fn advertise_with_mdns(&self, addr: SocketAddr) -> Result<(), PlatformAbstractionError> {
+    async fn advertise_with_mdns(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
         // We need the computer name in order to advertise the service using DNS
         let computer_name = match self.computer_name.clone() {
             None => return Err(PlatformAbstractionError::Unsupported),
@@ -69,9 +76,13 @@ impl WindowsDiscoveryManager {
         }
 
         // Attempt to register the service
-        let registration =
-            ServiceDnsRegistration::create(addr, computer_name, self.service_name.as_ref())
-                .map_err(PlatformError::Win32)?;
+        let registration = ServiceDnsRegistration::create(
+            service,
+            computer_name,
+            self.service_name.as_ref(),
+            &self.config.service_type,
+        )
+        .map_err(PlatformError::Win32)?;
         registration
             .perform_registration()
             .await
@@ -97,12 +108,17 @@ impl WindowsDiscoveryManager {
     }
 
     /// Advertises the service using SSDP.
-    async fn advertise_with_ssdp(&self, addr: SocketAddr) -> Result<(), PlatformAbstractionError> {
+    async fn advertise_with_ssdp(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
         self.stop_ssdp().await;
 
         let multicast_manager = SSDPMulticast::setup(
             self.service_name.to_string(),
-            addr,
+            service,
+            self.config.ssdp_max_age(),
+            self.config.ssdp_description_port,
             Self::get_local_addresses,
         )
         .await?;
@@ -121,6 +137,23 @@ impl WindowsDiscoveryManager {
     }
 
     fn get_local_addresses() -> Result<Vec<IpAddrWithScopeId>, std::io::Error> {
+        let buffer = Self::get_adapter_addresses(GAA_FLAG_SKIP_FRIENDLY_NAME)?;
+
+        let mut addresses = Vec::new();
+        Self::walk_adapters(&buffer, |adapter| {
+            addresses.extend(Self::unicast_addresses_of(adapter));
+        });
+
+        tracing::trace!("Found local addresses: {:?}", addresses);
+
+        Ok(addresses)
+    }
+
+    /// Retrieves the adapter addresses into a buffer, which can be walked using
+    /// [`Self::walk_adapters`].
+    fn get_adapter_addresses(
+        flags: GET_ADAPTERS_ADDRESSES_FLAGS,
+    ) -> Result<Vec<u8>, std::io::Error> {
         let mut ok = false;
         let mut buffer = Vec::new();
         for i in 1usize..16usize {
@@ -135,7 +168,7 @@ impl WindowsDiscoveryManager {
                     GAA_FLAG_SKIP_ANYCAST
                         | GAA_FLAG_SKIP_MULTICAST
                         | GAA_FLAG_SKIP_DNS_SERVER
-                        | GAA_FLAG_SKIP_FRIENDLY_NAME,
+                        | flags,
                     None,
                     Some(buffer.as_mut_ptr() as _),
                     &mut buffer_size,
@@ -167,44 +200,15 @@ impl WindowsDiscoveryManager {
             ));
         }
 
-        let mut addresses = Vec::new();
+        Ok(buffer)
+    }
 
+    /// Calls the given function for every adapter in a buffer filled by
+    /// [`Self::get_adapter_addresses`].
+    fn walk_adapters(buffer: &[u8], mut f: impl FnMut(&IP_ADAPTER_ADDRESSES_LH)) {
         let mut current_adapter = unsafe { &*(buffer.as_ptr() as *const IP_ADAPTER_ADDRESSES_LH) };
         loop {
-            if !current_adapter.FirstUnicastAddress.is_null() {
-                // Get all unicast addresses
-                let mut current_address = unsafe { &*current_adapter.FirstUnicastAddress };
-                loop {
-                    let address = unsafe { &*current_address.Address.lpSockaddr };
-                    let address = match address.sa_family {
-                        AF_INET => {
-                            let address = unsafe { &*(address as *const _ as *const SOCKADDR_IN) };
-                            Some(IpAddrWithScopeId::V4(Ipv4Addr::from(address.sin_addr)))
-                        }
-                        AF_INET6 => {
-                            let address = unsafe { &*(address as *const _ as *const SOCKADDR_IN6) };
-                            Some(IpAddrWithScopeId::V6 {
-                                addr: Ipv6Addr::from(address.sin6_addr),
-                                scope_id: current_adapter.Ipv6IfIndex,
-                            })
-                        }
-                        // Ignore unknown address families
-                        _ => None,
-                    };
-
-                    // Add the address if it has been converted
-                    if let Some(address) = address {
-                        addresses.push(address);
-                    }
-
-                    if current_address.Next.is_null() {
-                        break;
-                    }
-
-                    // Advance the iterator
-                    current_address = unsafe { &*current_address.Next };
-                }
-            }
+            f(current_adapter);
 
             if current_adapter.Next.is_null() {
                 break;
@@ -213,30 +217,91 @@ impl WindowsDiscoveryManager {
             // Advance the iterator
             current_adapter = unsafe { &*current_adapter.Next };
         }
+    }
 
-        tracing::trace!("Found local addresses: {:?}", addresses);
+    /// Collects the unicast addresses of an adapter.
+    fn unicast_addresses_of(adapter: &IP_ADAPTER_ADDRESSES_LH) -> Vec<IpAddrWithScopeId> {
+        let mut addresses = Vec::new();
+        if adapter.FirstUnicastAddress.is_null() {
+            return addresses;
+        }
 
-        Ok(addresses)
+        // Get all unicast addresses
+        let mut current_address = unsafe { &*adapter.FirstUnicastAddress };
+        loop {
+            let address = unsafe { &*current_address.Address.lpSockaddr };
+            let address = match address.sa_family {
+                AF_INET => {
+                    let address = unsafe { &*(address as *const _ as *const SOCKADDR_IN) };
+                    Some(IpAddrWithScopeId::V4(Ipv4Addr::from(address.sin_addr)))
+                }
+                AF_INET6 => {
+                    let address = unsafe { &*(address as *const _ as *const SOCKADDR_IN6) };
+                    Some(IpAddrWithScopeId::V6 {
+                        addr: Ipv6Addr::from(address.sin6_addr),
+                        scope_id: adapter.Ipv6IfIndex,
+                    })
+                }
+                // Ignore unknown address families
+                _ => None,
+            };
+
+            // Add the address if it has been converted
+            if let Some(address) = address {
+                addresses.push(address);
+            }
+
+            if current_address.Next.is_null() {
+                break;
+            }
+
+            // Advance the iterator
+            current_address = unsafe { &*current_address.Next };
+        }
+
+        addresses
     }
 }
 
 #[async_trait::async_trait]
 impl DiscoveryManager for WindowsDiscoveryManager {
-    async fn advertise_service(&self, addr: SocketAddr) -> Result<(), PlatformAbstractionError> {
+    async fn advertise_service(
+        &self,
+        service: &ServiceInfo,
+    ) -> Result<(), PlatformAbstractionError> {
         let (mdns_res, ssdp_res) = tokio::join!(
-            self.advertise_with_mdns(addr),
-            self.advertise_with_ssdp(addr)
-        );
+            async {
+                if !self.config.is_enabled(DiscoveryBackend::Mdns) {
+                    return None;
+                }
 
-        if let Err(err) = &mdns_res {
-            tracing::warn!("Failed to advertise with mDNS: {}", err);
-        }
+                let res = self.advertise_with_mdns(service).await;
+                if let Err(err) = &res {
+                    tracing::warn!("Failed to advertise with mDNS: {}", err);
+                }
 
-        if let Err(err) = &ssdp_res {
-            tracing::warn!("Failed to advertise with SSDP: {}", err);
-        }
+                Some(res)
+            },
+            async {
+                if !self.config.is_enabled(DiscoveryBackend::Ssdp) {
+                    return None;
+                }
 
-        let success = mdns_res.is_ok() || ssdp_res.is_ok();
+                let res = self.advertise_with_ssdp(service).await;
+                if let Err(err) = &res {
+                    tracing::warn!("Failed to advertise with SSDP: {}", err);
+                }
+
+                Some(res)
+            }
+        );
+
+        // Only fail if every enabled backend failed
+        let results = [mdns_res, ssdp_res]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let success = results.is_empty() || results.iter().any(Result::is_ok);
         if !success {
             Err(PlatformAbstractionError::Unsupported)
         } else {
@@ -248,4 +313,39 @@ impl DiscoveryManager for WindowsDiscoveryManager {
         self.stop_ssdp().await;
         self.stop_advertising_with_mdns().await
     }
+
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterface>, PlatformAbstractionError> {
+        let buffer = Self::get_adapter_addresses(GET_ADAPTERS_ADDRESSES_FLAGS(0))?;
+
+        let mut interfaces = Vec::new();
+        Self::walk_adapters(&buffer, |adapter| {
+            if adapter.IfType == IF_TYPE_SOFTWARE_LOOPBACK {
+                return;
+            }
+
+            let name = unsafe { adapter.FriendlyName.to_string() }.unwrap_or_default();
+
+            // Only Ethernet style addresses are useful for Wake-on-LAN
+            let mac_address = if adapter.PhysicalAddressLength == 6 {
+                let mut mac = [0; 6];
+                mac.copy_from_slice(&adapter.PhysicalAddress[..6]);
+                Some(MacAddress(mac))
+            } else {
+                None
+            };
+
+            interfaces.push(NetworkInterface {
+                name,
+                mac_address,
+                addresses: Self::unicast_addresses_of(adapter)
+                    .iter()
+                    .map(IpAddrWithScopeId::ip)
+                    .collect(),
+            });
+        });
+
+        tracing::trace!("Network interfaces: {:?}", interfaces);
+
+        Ok(interfaces)
+    }
 }
@@ -1,3 +1,4 @@
+use crate::pal::discovery::ServiceInfo;
 use crate::pal::platform::name::ComputerName;
 use std::net::SocketAddr;
 use std::sync::Mutex;
@@ -25,10 +26,12 @@ pub struct ServiceDnsRegistration {
 
 impl ServiceDnsRegistration {
     pub fn create(
-        addr: SocketAddr,
+        service: &ServiceInfo,
         computer_name: ComputerName,
         service_name: &str,
+        service_type: &str,
     ) -> Result<Self, Win32Error> {
+        let addr = service.socket_addr;
         let mut host_name = computer_name.into_dns_host_name();
 
         // Append .local to host name
@@ -36,7 +39,7 @@ impl ServiceDnsRegistration {
         host_name.push(0);
 
         // Format and encode to UTF-16
-        let service_instance_name = format!("{service_name}._dragon-claw._tcp.local");
+        let service_instance_name = format!("{service_name}.{service_type}.local");
         tracing::trace!("Service name: {}", service_instance_name);
 
         let service_instance_name = service_instance_name
@@ -46,6 +49,8 @@ impl ServiceDnsRegistration {
 
         // Translate the rust address representation to the Win32 representation
         let (ipv4, ipv6) = match addr {
+            // Listening on all addresses, the system publishes the addresses of the host
+            addr if addr.ip().is_unspecified() => (None, None),
             SocketAddr::V4(addr) => {
                 let ipv4 = u32::from_be_bytes(addr.ip().octets());
 
@@ -60,6 +65,27 @@ impl ServiceDnsRegistration {
             }
         };
 
+        // Encode the service properties (TXT record entries) to UTF-16, the service instance
+        // copies them, so they only need to live until the instance has been constructed
+        let (property_keys, property_values): (Vec<_>, Vec<_>) = service
+            .properties()
+            .into_iter()
+            .map(|(key, value)| {
+                (
+                    key.encode_utf16().chain([0u16]).collect::<Vec<u16>>(),
+                    value.encode_utf16().chain([0u16]).collect::<Vec<u16>>(),
+                )
+            })
+            .unzip();
+        let property_key_ptrs = property_keys
+            .iter()
+            .map(|v| PCWSTR::from_raw(v.as_ptr()))
+            .collect::<Vec<_>>();
+        let property_value_ptrs = property_values
+            .iter()
+            .map(|v| PCWSTR::from_raw(v.as_ptr()))
+            .collect::<Vec<_>>();
+
         // Construct a DNS service instance to register
         let service_instance = unsafe {
             DnsServiceConstructInstance(
@@ -70,9 +96,9 @@ impl ServiceDnsRegistration {
                 addr.port(),
                 0,
                 0,
-                0,
-                std::ptr::null_mut(),
-                std::ptr::null_mut(),
+                property_key_ptrs.len() as u32,
+                property_key_ptrs.as_ptr(),
+                property_value_ptrs.as_ptr(),
             )
         };
 
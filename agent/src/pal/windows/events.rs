@@ -0,0 +1,7 @@
+use crate::pal::events::EventManager;
+
+#[derive(Debug)]
+pub struct WindowsEventManager;
+
+// System events are not supported yet
+impl EventManager for WindowsEventManager {}
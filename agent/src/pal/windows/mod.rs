@@ -1,19 +1,25 @@
 mod discovery;
 mod dns;
+mod events;
 mod name;
 mod power;
 mod process;
 mod service;
 mod status;
+mod system;
 mod util;
 
+use crate::config::Config;
 use crate::pal::platform::discovery::WindowsDiscoveryManager;
+use crate::pal::platform::events::WindowsEventManager;
 use crate::pal::platform::power::WindowsPowerManager;
 use crate::pal::platform::process::OwnProcess;
 use crate::pal::platform::service::dispatcher::ServiceDispatcher;
 use crate::pal::platform::service::ServiceEnvironment;
 use crate::pal::platform::status::WindowsStatusManager;
+use crate::pal::platform::system::WindowsSystemInfoManager;
 use crate::pal::{PlatformAbstractionError, PlatformAbstractionLayer, ShutdownRequestFut};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use thiserror::Error;
 use windows::core::Error as Win32Error;
@@ -34,6 +40,7 @@ pub struct PlatformAbstractionImpl {
     power_manager: WindowsPowerManager,
     discovery_manager: WindowsDiscoveryManager,
     status_manager: WindowsStatusManager,
+    data_directory: PathBuf,
 }
 
 impl PlatformAbstractionImpl {
@@ -105,16 +112,26 @@ impl PlatformAbstractionImpl {
         })
     }
 
-    pub async fn new(data: PlatformInitData) -> Result<Self, PlatformAbstractionError> {
+    pub async fn new(
+        data: PlatformInitData,
+        config: &Config,
+    ) -> Result<Self, PlatformAbstractionError> {
+        // Persistent data lives in %ProgramData%, which is writable by the service account
+        let data_directory = std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"))
+            .join("DragonClawAgent");
+
         Ok(Self {
             process: data.process,
             service_environment: data.service_environment,
-            discovery_manager: WindowsDiscoveryManager::new(),
+            discovery_manager: WindowsDiscoveryManager::new(config.discovery.clone()),
             status_manager: WindowsStatusManager::new(data.service_dispatcher),
             power_manager: WindowsPowerManager::new(
                 data.has_shutdown_privilege,
                 data.has_system_environment_privilege,
             ),
+            data_directory,
         })
     }
 }
@@ -138,6 +155,22 @@ impl PlatformAbstractionLayer for PlatformAbstractionImpl {
     fn status_manager(&self) -> &Self::StatusManager {
         &self.status_manager
     }
+
+    type EventManager = WindowsEventManager;
+
+    fn event_manager(&self) -> &Self::EventManager {
+        &WindowsEventManager
+    }
+
+    type SystemInfoManager = WindowsSystemInfoManager;
+
+    fn system_info_manager(&self) -> &Self::SystemInfoManager {
+        &WindowsSystemInfoManager
+    }
+
+    fn data_directory(&self) -> &Path {
+        &self.data_directory
+    }
 }
 
 #[derive(Debug, Error)]
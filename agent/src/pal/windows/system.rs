@@ -0,0 +1,7 @@
+use crate::pal::system::SystemInfoManager;
+
+#[derive(Debug)]
+pub struct WindowsSystemInfoManager;
+
+// System information is not gathered yet
+impl SystemInfoManager for WindowsSystemInfoManager {}
@@ -0,0 +1,183 @@
+use crate::config::{PolicyRuleConfig, PowerPolicyConfig};
+use crate::pal::power::PowerAction;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// File the policy was read from before it became part of the configuration.
+const LEGACY_POLICY_FILE_NAME: &str = "power-policy.toml";
+
+/// An IP subnet in CIDR notation, such as `192.168.1.0/24`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Subnet {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    /// Tests whether the subnet contains the given address.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = match s.split_once('/') {
+            Some((network, prefix_len)) => (network, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let network = IpAddr::from_str(network)
+            .map_err(|err| format!("invalid subnet address {}: {}", network, err))?;
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+
+        // A plain address is a subnet containing only that address
+        let prefix_len = match prefix_len {
+            None => max_prefix_len,
+            Some(v) => u8::from_str(v)
+                .ok()
+                .filter(|v| *v <= max_prefix_len)
+                .ok_or_else(|| format!("invalid subnet prefix length {}", v))?,
+        };
+
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
+
+impl PolicyRuleConfig {
+    fn matches(&self, device_id: Option<&str>, peer: Option<IpAddr>) -> bool {
+        let device_matches = device_id.is_some_and(|id| self.devices.iter().any(|d| d == id));
+        let subnet_matches = peer.is_some_and(|addr| self.subnets.iter().any(|s| s.contains(addr)));
+
+        device_matches || subnet_matches
+    }
+}
+
+/// Decides which power actions a client is allowed to perform.
+#[derive(Debug)]
+pub struct PowerPolicy {
+    config: PowerPolicyConfig,
+    enabled: Option<Vec<PowerAction>>,
+}
+
+impl PowerPolicy {
+    /// Creates the policy from its configuration.
+    ///
+    /// Only the enabled actions are ever allowed, all actions are enabled if `None`. Without any
+    /// rules or default, every client may perform every enabled action.
+    pub fn new(config: PowerPolicyConfig, enabled: Option<Vec<PowerAction>>) -> Self {
+        Self { config, enabled }
+    }
+
+    /// Fails if the data directory still contains a policy file, which is not read anymore.
+    ///
+    /// Silently ignoring it would lift the restrictions it used to impose.
+    pub async fn check_legacy_file(data_directory: &Path) -> Result<(), PolicyError> {
+        let path = data_directory.join(LEGACY_POLICY_FILE_NAME);
+
+        if tokio::fs::try_exists(&path).await? {
+            return Err(PolicyError::LegacyFile(path));
+        }
+
+        Ok(())
+    }
+
+    /// Tests whether the given action has been enabled in the configuration.
+    pub fn is_enabled(&self, action: PowerAction) -> bool {
+        match &self.enabled {
+            None => true,
+            Some(enabled) => enabled.contains(&action),
+        }
+    }
+
+    /// Tests whether the client may perform the given action.
+    pub fn is_allowed(
+        &self,
+        device_id: Option<&str>,
+        peer: Option<IpAddr>,
+        action: PowerAction,
+    ) -> bool {
+        if !self.is_enabled(action) {
+            return false;
+        }
+
+        match self.allowed_actions(device_id, peer) {
+            None => true,
+            Some(actions) => actions.contains(&action),
+        }
+    }
+
+    /// Filters the given actions down to those the client may perform.
+    pub fn filter(
+        &self,
+        device_id: Option<&str>,
+        peer: Option<IpAddr>,
+        actions: Vec<PowerAction>,
+    ) -> Vec<PowerAction> {
+        let actions = actions.into_iter().filter(|a| self.is_enabled(*a));
+
+        match self.allowed_actions(device_id, peer) {
+            None => actions.collect(),
+            Some(allowed) => actions.filter(|a| allowed.contains(a)).collect(),
+        }
+    }
+
+    /// Retrieves the actions the client may perform, or `None` if it may perform all of them.
+    ///
+    /// The first matching rule wins.
+    fn allowed_actions(
+        &self,
+        device_id: Option<&str>,
+        peer: Option<IpAddr>,
+    ) -> Option<&[PowerAction]> {
+        self.config
+            .rules
+            .iter()
+            .find(|r| r.matches(device_id, peer))
+            .map(|r| r.actions.as_slice())
+            .or(self.config.default.as_deref())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error(
+        "{} is not read anymore, move its contents to the power_policy section of the configuration",
+        .0.display()
+    )]
+    LegacyFile(PathBuf),
+}
@@ -0,0 +1,25 @@
+use super::{ActionState as ProtoActionState, ActionStatus as ProtoActionStatus, PowerAction};
+use crate::tracker::{ActionState as TrackerActionState, ActionStatus as TrackerActionStatus};
+
+impl From<TrackerActionState> for ProtoActionState {
+    fn from(value: TrackerActionState) -> Self {
+        match value {
+            TrackerActionState::Pending => Self::Pending,
+            TrackerActionState::Executing => Self::Executing,
+            TrackerActionState::Failed => Self::Failed,
+            TrackerActionState::Completed => Self::Completed,
+        }
+    }
+}
+
+impl From<TrackerActionStatus> for ProtoActionStatus {
+    fn from(value: TrackerActionStatus) -> Self {
+        Self {
+            action_id: value.id,
+            action: PowerAction::from(value.action) as i32,
+            state: ProtoActionState::from(value.state) as i32,
+            error: value.error,
+            updated_at: value.updated_at,
+        }
+    }
+}
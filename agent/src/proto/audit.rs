@@ -0,0 +1,36 @@
+use super::{AuditRecord as ProtoAuditRecord, PowerAction as ProtoPowerAction};
+use crate::audit::{AuditRecord, AuditResult};
+
+impl From<AuditRecord> for ProtoAuditRecord {
+    fn from(value: AuditRecord) -> Self {
+        let confirmation_required =
+            matches!(value.result, AuditResult::ConfirmationRequired { .. });
+        let accepted = !matches!(
+            value.result,
+            AuditResult::ConfirmationRequired { .. } | AuditResult::Rejected { .. }
+        );
+        let (action_id, scheduled_at, status_code, status_message) = match value.result {
+            AuditResult::Accepted { action_id } => (Some(action_id), None, None, None),
+            AuditResult::ConfirmationRequired { .. } | AuditResult::Delegated => {
+                (None, None, None, None)
+            }
+            AuditResult::Scheduled { at } => (None, Some(at), None, None),
+            AuditResult::Rejected { code, message } => (None, None, Some(code), Some(message)),
+        };
+
+        Self {
+            timestamp: value.timestamp,
+            peer_address: value.peer_address.map(|v| v.to_string()),
+            device_id: value.device_id,
+            device_name: value.device_name,
+            action: value.action.map(|v| ProtoPowerAction::from(v) as i32),
+            accepted,
+            action_id,
+            status_code,
+            status_message,
+            confirmation_required,
+            scheduled_id: value.scheduled_id,
+            scheduled_at,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::pairing::PairingManager;
+use std::sync::Arc;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// Identity of a paired client, attached to authenticated requests.
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    /// Identifier of the paired device.
+    pub device_id: String,
+
+    /// Human readable name of the paired device.
+    pub device_name: String,
+
+    /// Whether the device may manage what other devices own.
+    pub privileged: bool,
+}
+
+impl ClientIdentity {
+    /// Retrieves the identity of the client which sent the request.
+    ///
+    /// Fails with `UNAUTHENTICATED` if the client is not paired.
+    pub fn require<T>(request: &Request<T>) -> Result<&Self, Status> {
+        request
+            .extensions()
+            .get::<Self>()
+            .ok_or_else(|| Status::unauthenticated("Device is not paired"))
+    }
+
+    /// Tests whether the client may manage something owned by the given device, such as its
+    /// pairing.
+    ///
+    /// Devices only manage what they own themselves, unless they are privileged.
+    pub fn may_manage(&self, owner_device_id: &str) -> bool {
+        self.privileged || self.device_id == owner_device_id
+    }
+}
+
+/// Interceptor validating the bearer token of incoming requests.
+///
+/// Requests without a token are passed on without an identity, so that unauthenticated RPCs such
+/// as pairing remain usable. Requests with an invalid token are rejected right away.
+#[derive(Debug, Clone)]
+pub struct AuthInterceptor {
+    pairing: Arc<PairingManager>,
+}
+
+impl AuthInterceptor {
+    pub fn new(pairing: Arc<PairingManager>) -> Self {
+        Self { pairing }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(authorization) = request.metadata().get("authorization") else {
+            return Ok(request);
+        };
+
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("Malformed authorization metadata"))?;
+
+        let device = self
+            .pairing
+            .authenticate(token.trim())
+            .ok_or_else(|| Status::unauthenticated("Invalid access token"))?;
+
+        request.extensions_mut().insert(ClientIdentity {
+            privileged: self.pairing.is_privileged(&device.id),
+            device_id: device.id,
+            device_name: device.name,
+        });
+
+        Ok(request)
+    }
+}
@@ -0,0 +1,22 @@
+use super::PowerActionChallenge;
+use crate::confirm::{Challenge, ConfirmationError};
+use tonic::Status;
+
+impl From<Challenge> for PowerActionChallenge {
+    fn from(value: Challenge) -> Self {
+        Self {
+            token: value.token,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+impl From<ConfirmationError> for Status {
+    fn from(value: ConfirmationError) -> Self {
+        match value {
+            ConfirmationError::UnknownToken => Status::not_found(value.to_string()),
+            ConfirmationError::Expired => Status::deadline_exceeded(value.to_string()),
+            ConfirmationError::AlreadyUsed => Status::failed_precondition(value.to_string()),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use super::{
+    custom_action_output, CustomAction as ProtoCustomAction,
+    CustomActionExit as ProtoCustomActionExit, CustomActionOutput as ProtoCustomActionOutput,
+};
+use crate::config::CustomActionConfig;
+use crate::custom::{CustomActionError, CustomActionExit, CustomActionOutput};
+use tonic::Status;
+
+impl From<CustomActionConfig> for ProtoCustomAction {
+    fn from(value: CustomActionConfig) -> Self {
+        Self {
+            name: value.name,
+            description: value.description,
+            timeout_seconds: value.timeout_seconds,
+        }
+    }
+}
+
+impl From<CustomActionExit> for ProtoCustomActionExit {
+    fn from(value: CustomActionExit) -> Self {
+        Self {
+            exit_code: value.code,
+            timed_out: value.timed_out,
+        }
+    }
+}
+
+impl From<CustomActionOutput> for ProtoCustomActionOutput {
+    fn from(value: CustomActionOutput) -> Self {
+        let output = match value {
+            CustomActionOutput::Stdout(data) => custom_action_output::Output::Stdout(data),
+            CustomActionOutput::Stderr(data) => custom_action_output::Output::Stderr(data),
+            CustomActionOutput::Exited(exit) => custom_action_output::Output::Exit(exit.into()),
+        };
+
+        Self {
+            output: Some(output),
+        }
+    }
+}
+
+impl From<CustomActionError> for Status {
+    fn from(value: CustomActionError) -> Self {
+        match value {
+            CustomActionError::UnknownAction => Status::not_found(value.to_string()),
+            CustomActionError::NotAllowed => Status::permission_denied(value.to_string()),
+            CustomActionError::UnknownUser | CustomActionError::Spawn(_) => {
+                Status::internal(value.to_string())
+            }
+        }
+    }
+}
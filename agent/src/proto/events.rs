@@ -0,0 +1,58 @@
+use super::agent_event::Event;
+use super::{
+    AgentEvent as ProtoAgentEvent, AgentStatus as ProtoAgentStatus, AgentStatusChanged,
+    PowerAction, PowerActionRequested, PrepareForShutdown, PrepareForSleep, SessionAdded,
+    SessionRemoved,
+};
+use crate::events::{AgentEvent, AgentEventKind, AgentStatus};
+use crate::pal::events::SystemEvent;
+
+impl From<AgentStatus> for ProtoAgentStatus {
+    fn from(value: AgentStatus) -> Self {
+        match value {
+            AgentStatus::Starting => Self::Starting,
+            AgentStatus::Running => Self::Running,
+            AgentStatus::Stopping => Self::Stopping,
+            AgentStatus::Stopped => Self::Stopped,
+            AgentStatus::Failed => Self::Failed,
+        }
+    }
+}
+
+impl From<AgentEvent> for ProtoAgentEvent {
+    fn from(value: AgentEvent) -> Self {
+        let event = match value.kind {
+            AgentEventKind::StatusChanged(status) => Event::StatusChanged(AgentStatusChanged {
+                status: ProtoAgentStatus::from(status) as i32,
+            }),
+            AgentEventKind::System(SystemEvent::PrepareForShutdown { active }) => {
+                Event::PrepareForShutdown(PrepareForShutdown { active })
+            }
+            AgentEventKind::System(SystemEvent::PrepareForSleep { active }) => {
+                Event::PrepareForSleep(PrepareForSleep { active })
+            }
+            AgentEventKind::System(SystemEvent::SessionAdded { session_id }) => {
+                Event::SessionAdded(SessionAdded { session_id })
+            }
+            AgentEventKind::System(SystemEvent::SessionRemoved { session_id }) => {
+                Event::SessionRemoved(SessionRemoved { session_id })
+            }
+            AgentEventKind::PowerActionRequested {
+                action_id,
+                action,
+                device_id,
+                device_name,
+            } => Event::PowerActionRequested(PowerActionRequested {
+                action_id,
+                action: PowerAction::from(action) as i32,
+                device_id,
+                device_name,
+            }),
+        };
+
+        Self {
+            timestamp: value.timestamp,
+            event: Some(event),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use super::KeepAwakeLease as ProtoKeepAwakeLease;
+use crate::keepawake::{KeepAwakeError, KeepAwakeLease};
+use crate::pal::PlatformAbstractionError;
+use tonic::Status;
+
+impl From<KeepAwakeLease> for ProtoKeepAwakeLease {
+    fn from(value: KeepAwakeLease) -> Self {
+        Self {
+            lease_id: value.id,
+            reason: value.reason,
+            requested_by: value.requested_by,
+            acquired_at: value.acquired_at,
+            expires_at: value.expires_at,
+        }
+    }
+}
+
+impl From<KeepAwakeError> for Status {
+    fn from(value: KeepAwakeError) -> Self {
+        match value {
+            KeepAwakeError::Platform(PlatformAbstractionError::Unsupported) => {
+                Status::unimplemented("Keeping the system awake is not supported")
+            }
+            KeepAwakeError::Platform(_) => Status::internal(value.to_string()),
+            KeepAwakeError::InvalidDuration => Status::invalid_argument(value.to_string()),
+            KeepAwakeError::UnknownLease => Status::not_found(value.to_string()),
+        }
+    }
+}
@@ -1,25 +1,364 @@
+// Status is large, but it is what the RPC handlers return anyway
+#![allow(clippy::result_large_err)]
+
+mod action;
+mod audit;
+mod auth;
+mod confirm;
+mod custom;
+mod events;
+mod keepawake;
+mod network;
 mod power;
+mod system;
+mod wol;
 
 tonic::include_proto!("net.janrupf.dc");
 
-use crate::pal::PlatformAbstraction;
+/// Encoded file descriptor set of the protocol, used for server reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    tonic::include_file_descriptor_set!("dragon_claw_descriptor");
+
+use crate::audit::{AuditFilter, AuditLog, AuditRecord as LoggedAuditRecord, AuditResult};
+use crate::confirm::ConfirmationManager;
+use crate::custom::CustomActionRunner;
+use crate::events::{AgentEventKind, EventBus};
+use crate::keepawake::{KeepAwakeError, KeepAwakeManager};
+use crate::pairing::{PairingError, PairingManager, PAIRING_TIMEOUT};
+use crate::pal::discovery::{DiscoveryManager, InvalidMacAddress};
+use crate::pal::system::SystemInfoManager;
+use crate::pal::{PlatformAbstraction, PlatformAbstractionError};
+use crate::policy::PowerPolicy;
+use crate::schedule::{ScheduleError, Scheduler};
+use crate::tracker::ActionTracker;
+use crate::util::unix_time_now;
+use crate::wol::{PeerRegistry, WakeTarget};
 use dragon_claw_agent_server::*;
+use futures::Stream;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 use tonic::{Request, Response, Status};
 
+pub use auth::{AuthInterceptor, ClientIdentity};
+
+/// Upper bound for the number of audit records returned by a single query.
+const MAX_AUDIT_RECORDS: usize = 1000;
+
 #[derive(Debug)]
 pub struct DragonClawAgentImpl {
     pal: Arc<PlatformAbstraction>,
+    pairing: Arc<PairingManager>,
+    policy: Arc<PowerPolicy>,
+    scheduler: Arc<Scheduler>,
+    tracker: Arc<ActionTracker>,
+    events: Arc<EventBus>,
+    peers: Arc<PeerRegistry>,
+    keep_awake: Arc<KeepAwakeManager>,
+    custom_actions: Arc<CustomActionRunner>,
+    audit: Arc<AuditLog>,
+    confirmations: Arc<ConfirmationManager>,
 }
 
 impl DragonClawAgentImpl {
-    pub fn new(pal: Arc<PlatformAbstraction>) -> Self {
-        Self { pal }
+    #[allow(clippy::too_many_arguments)] // One for every service backing the RPCs
+    pub fn new(
+        pal: Arc<PlatformAbstraction>,
+        pairing: Arc<PairingManager>,
+        policy: Arc<PowerPolicy>,
+        scheduler: Arc<Scheduler>,
+        tracker: Arc<ActionTracker>,
+        events: Arc<EventBus>,
+        peers: Arc<PeerRegistry>,
+        keep_awake: Arc<KeepAwakeManager>,
+        custom_actions: Arc<CustomActionRunner>,
+        audit: Arc<AuditLog>,
+        confirmations: Arc<ConfirmationManager>,
+    ) -> Self {
+        Self {
+            pal,
+            pairing,
+            policy,
+            scheduler,
+            tracker,
+            events,
+            peers,
+            keep_awake,
+            custom_actions,
+            audit,
+            confirmations,
+        }
+    }
+
+    /// Makes sure the client is allowed to perform the given power action.
+    fn check_policy(
+        &self,
+        client: &ClientIdentity,
+        peer: Option<IpAddr>,
+        action: PowerAction,
+    ) -> Result<(), Status> {
+        if !self.policy.is_enabled(action.into()) {
+            return Err(Status::unimplemented("Power action is disabled"));
+        }
+
+        if self
+            .policy
+            .is_allowed(Some(&client.device_id), peer, action.into())
+        {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "Device \"{}\" ({}) is not allowed to perform power action {:?}",
+            client.device_name,
+            client.device_id,
+            action
+        );
+        Err(Status::permission_denied(
+            "Power action is not allowed for this device",
+        ))
+    }
+
+    /// Accepts a power action for execution, or issues a challenge if it has to be confirmed.
+    ///
+    /// Clients which can't handle challenges are rejected instead.
+    fn accept_power_action(
+        &self,
+        client: Option<&ClientIdentity>,
+        peer: Option<IpAddr>,
+        action: Option<PowerAction>,
+        can_confirm: bool,
+    ) -> Result<PowerActionAccepted, Status> {
+        let client = client.ok_or_else(|| Status::unauthenticated("Device is not paired"))?;
+
+        if self.pal.power_manager().is_none() {
+            // Power management is not supported
+            return Err(Status::unimplemented("Power management is not supported"));
+        };
+
+        let Some(action) = action else {
+            return Err(Status::invalid_argument("Invalid power action"));
+        };
+
+        tracing::info!(
+            "Device \"{}\" ({}) requested power action {:?}",
+            client.device_name,
+            client.device_id,
+            action
+        );
+
+        self.check_policy(client, peer, action)?;
+
+        if self.confirmations.is_required(action.into()) {
+            if !can_confirm {
+                return Err(Status::failed_precondition(
+                    "Power action has to be confirmed, which requires SubmitPowerAction",
+                ));
+            }
+
+            let challenge = self.confirmations.issue(action.into(), &client.device_id);
+            tracing::info!(
+                "Power action {:?} has to be confirmed until {}",
+                action,
+                challenge.expires_at
+            );
+
+            return Ok(PowerActionAccepted {
+                action_id: String::new(),
+                challenge: Some(challenge.into()),
+            });
+        }
+
+        Ok(PowerActionAccepted {
+            action_id: self.execute_power_action(client, action),
+            challenge: None,
+        })
+    }
+
+    /// Hands a power action to the tracker, returning the identifier of the action.
+    fn execute_power_action(&self, client: &ClientIdentity, action: PowerAction) -> String {
+        let action_id = self.tracker.submit(action.into());
+        self.events.publish(AgentEventKind::PowerActionRequested {
+            action_id: action_id.clone(),
+            action: action.into(),
+            device_id: client.device_id.clone(),
+            device_name: client.device_name.clone(),
+        });
+
+        action_id
+    }
+
+    /// Records the outcome of a power action call in the audit log.
+    async fn audit(
+        &self,
+        peer_address: Option<SocketAddr>,
+        client: Option<&ClientIdentity>,
+        action: Option<PowerAction>,
+        res: &Result<PowerActionAccepted, Status>,
+    ) {
+        let result = match res {
+            Ok(PowerActionAccepted {
+                challenge: Some(challenge),
+                ..
+            }) => AuditResult::ConfirmationRequired {
+                expires_at: challenge.expires_at,
+            },
+            Ok(accepted) => AuditResult::Accepted {
+                action_id: accepted.action_id.clone(),
+            },
+            Err(status) => Self::rejected(status),
+        };
+
+        self.write_audit_record(peer_address, client, action, None, result)
+            .await;
+    }
+
+    /// Records the outcome of a call scheduling a power action in the audit log.
+    async fn audit_schedule(
+        &self,
+        peer_address: Option<SocketAddr>,
+        client: Option<&ClientIdentity>,
+        action: Option<PowerAction>,
+        res: &Result<crate::schedule::ScheduledAction, Status>,
+    ) {
+        let (scheduled_id, result) = match res {
+            Ok(scheduled) => (
+                Some(scheduled.id.clone()),
+                AuditResult::Scheduled { at: scheduled.at },
+            ),
+            Err(status) => (None, Self::rejected(status)),
+        };
+
+        self.write_audit_record(peer_address, client, action, scheduled_id, result)
+            .await;
+    }
+
+    fn rejected(status: &Status) -> AuditResult {
+        AuditResult::Rejected {
+            code: status.code() as i32,
+            message: status.message().to_string(),
+        }
+    }
+
+    async fn write_audit_record(
+        &self,
+        peer_address: Option<SocketAddr>,
+        client: Option<&ClientIdentity>,
+        action: Option<PowerAction>,
+        scheduled_id: Option<String>,
+        result: AuditResult,
+    ) {
+        let record = LoggedAuditRecord {
+            timestamp: unix_time_now(),
+            peer_address,
+            device_id: client.map(|c| c.device_id.clone()),
+            device_name: client.map(|c| c.device_name.clone()),
+            action: action.map(Into::into),
+            scheduled_id,
+            result,
+        };
+
+        // The call itself has been handled already, so don't fail it
+        if let Err(err) = self.audit.record(&record).await {
+            tracing::error!("Failed to write audit record {:?}: {}", record, err);
+        }
+    }
+
+    /// Schedules a power action for the client, see [`Scheduler::schedule`].
+    async fn schedule_power_action(
+        &self,
+        client: Option<&ClientIdentity>,
+        peer: Option<IpAddr>,
+        action: Option<PowerAction>,
+        when: Option<schedule_action_request::When>,
+    ) -> Result<crate::schedule::ScheduledAction, Status> {
+        let client = client.ok_or_else(|| Status::unauthenticated("Device is not paired"))?;
+
+        let Some(action) = action else {
+            return Err(Status::invalid_argument("Invalid power action"));
+        };
+
+        let now = unix_time_now();
+        let at = match when {
+            Some(schedule_action_request::When::DelaySeconds(delay)) => now.saturating_add(delay),
+            Some(schedule_action_request::When::At(at)) if at >= now => at,
+            Some(schedule_action_request::When::At(_)) => {
+                return Err(Status::invalid_argument("Time is in the past"))
+            }
+            None => return Err(Status::invalid_argument("Missing time")),
+        };
+
+        self.check_policy(client, peer, action)?;
+
+        // Nobody would be around to confirm the action once it is due
+        if self.confirmations.is_required(action.into()) {
+            return Err(Status::failed_precondition(
+                "Power action has to be confirmed and can't be scheduled",
+            ));
+        }
+
+        let scheduled = self
+            .scheduler
+            .schedule(action.into(), at, client.device_id.clone(), peer)
+            .await?;
+        Ok(scheduled)
+    }
+}
+
+/// Parses an optional IPv4 broadcast address of a request.
+fn parse_broadcast_address(value: Option<String>) -> Result<Option<Ipv4Addr>, Status> {
+    value
+        .map(|v| v.parse())
+        .transpose()
+        .map_err(|_| Status::invalid_argument("Invalid IPv4 broadcast address"))
+}
+
+impl From<PairingError> for Status {
+    fn from(value: PairingError) -> Self {
+        match value {
+            PairingError::Storage(_) => Status::internal(value.to_string()),
+            PairingError::UnknownPairing => Status::not_found(value.to_string()),
+            PairingError::InvalidPin => Status::permission_denied(value.to_string()),
+            PairingError::TooManyPendingPairings => Status::resource_exhausted(value.to_string()),
+            PairingError::LockedOut => Status::resource_exhausted(value.to_string()),
+            PairingError::UnknownDevice => Status::not_found(value.to_string()),
+        }
+    }
+}
+
+impl From<ScheduleError> for Status {
+    fn from(value: ScheduleError) -> Self {
+        match value {
+            ScheduleError::Storage(_) => Status::internal(value.to_string()),
+            ScheduleError::Platform(PlatformAbstractionError::Unsupported) => {
+                Status::unimplemented("Power action is not supported")
+            }
+            ScheduleError::Platform(_) => Status::internal(value.to_string()),
+            ScheduleError::UnknownAction => Status::not_found(value.to_string()),
+        }
+    }
+}
+
+impl From<crate::schedule::ScheduledAction> for ScheduledAction {
+    fn from(value: crate::schedule::ScheduledAction) -> Self {
+        Self {
+            id: value.id,
+            action: PowerAction::from(value.action) as i32,
+            at: value.at,
+            requested_by: value.requested_by,
+        }
     }
 }
 
 #[tonic::async_trait]
 impl DragonClawAgent for DragonClawAgentImpl {
+    type WatchActionStream = Pin<Box<dyn Stream<Item = Result<ActionStatus, Status>> + Send>>;
+    type WatchEventsStream = Pin<Box<dyn Stream<Item = Result<AgentEvent, Status>> + Send>>;
+    type RunCustomActionStream =
+        Pin<Box<dyn Stream<Item = Result<CustomActionOutput, Status>> + Send>>;
+
     async fn get_agent_version(
         &self,
         _request: Request<()>,
@@ -46,18 +385,24 @@ impl DragonClawAgent for DragonClawAgentImpl {
 
     async fn get_supported_power_actions(
         &self,
-        _request: Request<()>,
+        request: Request<()>,
     ) -> Result<Response<SupportedPowerActions>, Status> {
+        let client = ClientIdentity::require(&request)?;
+
         let Some(power) = self.pal.power_manager() else {
             // Power management is not supported
             return Ok(Response::new(SupportedPowerActions { actions: vec![] }));
         };
 
-        // Collect supported actions and translate to RPC
-        let actions = power
+        let supported = power
             .get_supported_power_actions()
             .await
-            .map_err(|err| Status::internal(err.to_string()))?
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        // Only report the actions the client is allowed to perform and translate to RPC
+        let actions = self
+            .policy
+            .filter(Some(&client.device_id), peer_ip(&request), supported)
             .into_iter()
             .map(PowerAction::from)
             .map(|v| v as i32)
@@ -69,23 +414,573 @@ impl DragonClawAgent for DragonClawAgentImpl {
         &self,
         request: Request<PowerActionRequest>,
     ) -> Result<Response<()>, Status> {
-        let Some(power) = self.pal.power_manager() else {
-            // Power management is not supported
-            return Err(Status::unimplemented("Power management is not supported"));
+        // Every call is audited, including the ones from clients which are not paired
+        let client = ClientIdentity::require(&request).ok().cloned();
+        let action = PowerAction::from_i32(request.get_ref().action);
+
+        let res = self.accept_power_action(client.as_ref(), peer_ip(&request), action, false);
+        self.audit(peer_address(&request), client.as_ref(), action, &res)
+            .await;
+
+        res.map(|_| Response::new(()))
+    }
+
+    async fn submit_power_action(
+        &self,
+        request: Request<PowerActionRequest>,
+    ) -> Result<Response<PowerActionAccepted>, Status> {
+        // Every call is audited, including the ones from clients which are not paired
+        let client = ClientIdentity::require(&request).ok().cloned();
+        let action = PowerAction::from_i32(request.get_ref().action);
+
+        let res = self.accept_power_action(client.as_ref(), peer_ip(&request), action, true);
+        self.audit(peer_address(&request), client.as_ref(), action, &res)
+            .await;
+
+        res.map(Response::new)
+    }
+
+    async fn confirm_power_action(
+        &self,
+        request: Request<ConfirmPowerActionRequest>,
+    ) -> Result<Response<PowerActionAccepted>, Status> {
+        let client = ClientIdentity::require(&request).ok().cloned();
+        let peer = peer_ip(&request);
+        let peer_address = peer_address(&request);
+        let token = request.into_inner().token;
+
+        let (action, res) = match &client {
+            None => (None, Err(Status::unauthenticated("Device is not paired"))),
+            Some(client) => match self.confirmations.confirm(&token, &client.device_id) {
+                Err(err) => (None, Err(Status::from(err))),
+                Ok(action) => {
+                    let action = PowerAction::from(action);
+                    tracing::info!(
+                        "Device \"{}\" ({}) confirmed power action {:?}",
+                        client.device_name,
+                        client.device_id,
+                        action
+                    );
+
+                    // The policy may have changed in the meantime
+                    let res =
+                        self.check_policy(client, peer, action)
+                            .map(|()| PowerActionAccepted {
+                                action_id: self.execute_power_action(client, action),
+                                challenge: None,
+                            });
+                    (Some(action), res)
+                }
+            },
+        };
+
+        self.audit(peer_address, client.as_ref(), action, &res)
+            .await;
+        res.map(Response::new)
+    }
+
+    async fn get_action_status(
+        &self,
+        request: Request<ActionStatusRequest>,
+    ) -> Result<Response<ActionStatus>, Status> {
+        ClientIdentity::require(&request)?;
+
+        self.tracker
+            .status(&request.into_inner().action_id)
+            .map(|status| Response::new(status.into()))
+            .ok_or_else(|| Status::not_found("Unknown action"))
+    }
+
+    async fn watch_action(
+        &self,
+        request: Request<ActionStatusRequest>,
+    ) -> Result<Response<Self::WatchActionStream>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let receiver = self
+            .tracker
+            .watch(&request.into_inner().action_id)
+            .ok_or_else(|| Status::not_found("Unknown action"))?;
+
+        // Emit the current status first and then every change, until the action is done
+        let stream = futures::stream::unfold(
+            (receiver, true, false),
+            |(mut receiver, first, done)| async move {
+                if done || (!first && receiver.changed().await.is_err()) {
+                    return None;
+                }
+
+                let status = receiver.borrow_and_update().clone();
+                let done = status.state.is_final();
+
+                Some((Ok(ActionStatus::from(status)), (receiver, false, done)))
+            },
+        );
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn watch_events(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<Self::WatchEventsStream>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let receiver = self.events.subscribe();
+
+        // End the stream after the agent announced it is stopping, so the server can shut down
+        let stream =
+            futures::stream::unfold((receiver, false), |(mut receiver, done)| async move {
+                if done {
+                    return None;
+                }
+
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => {
+                            let done = event.is_stopping();
+                            return Some((Ok(AgentEvent::from(event)), (receiver, done)));
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            tracing::warn!(
+                                "Event subscriber lagged behind, skipped {} events",
+                                count
+                            );
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_network_interfaces(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<NetworkInterfaces>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let interfaces = self
+            .pal
+            .discovery_manager()
+            .network_interfaces()
+            .map_err(|err| Status::internal(err.to_string()))?
+            .into_iter()
+            .map(NetworkInterface::from)
+            .collect();
+
+        Ok(Response::new(NetworkInterfaces { interfaces }))
+    }
+
+    async fn get_system_info(&self, request: Request<()>) -> Result<Response<SystemInfo>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let info = self
+            .pal
+            .system_info_manager()
+            .get_system_info()
+            .await
+            .map_err(|err| match err {
+                PlatformAbstractionError::Unsupported => {
+                    Status::unimplemented("System information is not supported")
+                }
+                err => Status::internal(err.to_string()),
+            })?;
+
+        Ok(Response::new(info.into()))
+    }
+
+    async fn acquire_keep_awake(
+        &self,
+        request: Request<AcquireKeepAwakeRequest>,
+    ) -> Result<Response<KeepAwakeLease>, Status> {
+        let client = ClientIdentity::require(&request)?.clone();
+        let request = request.into_inner();
+
+        let reason = if request.reason.trim().is_empty() {
+            format!("Requested by {}", client.device_name)
+        } else {
+            request.reason
         };
 
-        let action = match PowerAction::from_i32(request.into_inner().action) {
-            Some(v) => v,
-            None => return Err(Status::invalid_argument("Invalid power action")),
+        let lease = self
+            .keep_awake
+            .acquire(
+                Duration::from_secs(request.duration_seconds),
+                reason,
+                client.device_id,
+            )
+            .await?;
+
+        Ok(Response::new(lease.into()))
+    }
+
+    async fn release_keep_awake(
+        &self,
+        request: Request<ReleaseKeepAwakeRequest>,
+    ) -> Result<Response<()>, Status> {
+        let client = ClientIdentity::require(&request)?.clone();
+        let lease_id = request.into_inner().lease_id;
+
+        let lease = self
+            .keep_awake
+            .get(&lease_id)
+            .ok_or(KeepAwakeError::UnknownLease)?;
+        if !client.may_manage(&lease.requested_by) {
+            tracing::warn!(
+                "Device \"{}\" ({}) is not allowed to release keep awake lease {} of device {}",
+                client.device_name,
+                client.device_id,
+                lease_id,
+                lease.requested_by
+            );
+            return Err(Status::permission_denied(
+                "Only privileged devices may release leases acquired by other devices",
+            ));
+        }
+
+        self.keep_awake.release(&lease_id)?;
+        Ok(Response::new(()))
+    }
+
+    async fn list_keep_awake_leases(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<KeepAwakeLeases>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let leases = self
+            .keep_awake
+            .leases()
+            .into_iter()
+            .map(KeepAwakeLease::from)
+            .collect();
+        Ok(Response::new(KeepAwakeLeases { leases }))
+    }
+
+    async fn list_custom_actions(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<CustomActions>, Status> {
+        let client = ClientIdentity::require(&request)?;
+
+        let actions = self
+            .custom_actions
+            .actions_for(&client.device_id)
+            .into_iter()
+            .map(CustomAction::from)
+            .collect();
+        Ok(Response::new(CustomActions { actions }))
+    }
+
+    async fn run_custom_action(
+        &self,
+        request: Request<RunCustomActionRequest>,
+    ) -> Result<Response<Self::RunCustomActionStream>, Status> {
+        let client = ClientIdentity::require(&request)?.clone();
+
+        let receiver = self
+            .custom_actions
+            .run(&request.into_inner().name, &client.device_id)?;
+
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+            let output = receiver.recv().await?;
+            Some((Ok(CustomActionOutput::from(output)), receiver))
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn send_wake_on_lan(
+        &self,
+        request: Request<WakeOnLanRequest>,
+    ) -> Result<Response<()>, Status> {
+        let client = ClientIdentity::require(&request)?.clone();
+        let request = request.into_inner();
+
+        let mut target = match request.target {
+            None => return Err(Status::invalid_argument("No target given")),
+            Some(wake_on_lan_request::Target::MacAddress(mac_address)) => WakeTarget {
+                mac_address: mac_address
+                    .parse()
+                    .map_err(|err: InvalidMacAddress| Status::invalid_argument(err.to_string()))?,
+                interface: None,
+                broadcast_address: None,
+            },
+            Some(wake_on_lan_request::Target::PeerId(peer_id)) => self
+                .peers
+                .get(&peer_id)
+                .ok_or_else(|| Status::not_found("Unknown peer"))?
+                .wake_target(),
+        };
+
+        if request.interface.is_some() {
+            target.interface = request.interface;
+        }
+
+        if let Some(broadcast_address) = parse_broadcast_address(request.broadcast_address)? {
+            target.broadcast_address = Some(broadcast_address);
+        }
+
+        tracing::info!(
+            "Device \"{}\" ({}) requested to wake {}",
+            client.device_name,
+            client.device_id,
+            target.mac_address
+        );
+
+        crate::wol::send_magic_packet(&self.pal, &target).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn add_known_peer(
+        &self,
+        request: Request<AddKnownPeerRequest>,
+    ) -> Result<Response<KnownPeer>, Status> {
+        ClientIdentity::require(&request)?;
+        let request = request.into_inner();
+
+        if request.name.trim().is_empty() {
+            return Err(Status::invalid_argument("Peer name must not be empty"));
+        }
+
+        let mac_address = request
+            .mac_address
+            .parse()
+            .map_err(|err: InvalidMacAddress| Status::invalid_argument(err.to_string()))?;
+        let broadcast_address = parse_broadcast_address(request.broadcast_address)?;
+
+        let peer = self
+            .peers
+            .add(
+                request.name,
+                mac_address,
+                request.interface,
+                broadcast_address,
+            )
+            .await?;
+
+        Ok(Response::new(peer.into()))
+    }
+
+    async fn list_known_peers(&self, request: Request<()>) -> Result<Response<KnownPeers>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let peers = self
+            .peers
+            .peers()
+            .into_iter()
+            .map(KnownPeer::from)
+            .collect();
+        Ok(Response::new(KnownPeers { peers }))
+    }
+
+    async fn remove_known_peer(
+        &self,
+        request: Request<RemoveKnownPeerRequest>,
+    ) -> Result<Response<()>, Status> {
+        ClientIdentity::require(&request)?;
+
+        self.peers.remove(&request.into_inner().peer_id).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn request_pairing(
+        &self,
+        request: Request<PairingRequest>,
+    ) -> Result<Response<PairingChallenge>, Status> {
+        let device_name = request.into_inner().device_name;
+        if device_name.trim().is_empty() {
+            return Err(Status::invalid_argument("Device name must not be empty"));
+        }
+
+        let pairing_id = self.pairing.request_pairing(device_name)?;
+
+        Ok(Response::new(PairingChallenge {
+            pairing_id,
+            expires_in_seconds: PAIRING_TIMEOUT.as_secs() as u32,
+        }))
+    }
+
+    async fn complete_pairing(
+        &self,
+        request: Request<CompletePairingRequest>,
+    ) -> Result<Response<PairingResult>, Status> {
+        let request = request.into_inner();
+        let (device, access_token) = self
+            .pairing
+            .complete_pairing(&request.pairing_id, request.pin.trim())
+            .await?;
+
+        Ok(Response::new(PairingResult {
+            device_id: device.id,
+            access_token,
+        }))
+    }
+
+    async fn list_paired_devices(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<PairedDevices>, Status> {
+        let client = ClientIdentity::require(&request)?;
+
+        // Devices which are not privileged only get to see themselves
+        let devices = self
+            .pairing
+            .paired_devices()
+            .into_iter()
+            .filter(|d| client.may_manage(&d.id))
+            .map(|d| PairedDevice {
+                device_id: d.id,
+                device_name: d.name,
+                paired_at: d.paired_at,
+            })
+            .collect();
+        Ok(Response::new(PairedDevices { devices }))
+    }
+
+    async fn revoke_pairing(
+        &self,
+        request: Request<RevokePairingRequest>,
+    ) -> Result<Response<()>, Status> {
+        let client = ClientIdentity::require(&request)?.clone();
+        let device_id = request.into_inner().device_id;
+
+        if !client.may_manage(&device_id) {
+            tracing::warn!(
+                "Device \"{}\" ({}) is not allowed to revoke the pairing of device {}",
+                client.device_name,
+                client.device_id,
+                device_id
+            );
+            return Err(Status::permission_denied(
+                "Only privileged devices may revoke other devices",
+            ));
+        }
+
+        self.pairing.revoke(&device_id).await?;
+
+        // Actions the device scheduled must not be performed anymore
+        self.scheduler.recheck();
+
+        Ok(Response::new(()))
+    }
+
+    async fn schedule_action(
+        &self,
+        request: Request<ScheduleActionRequest>,
+    ) -> Result<Response<ScheduledAction>, Status> {
+        // Every call is audited, including the ones from clients which are not paired
+        let client = ClientIdentity::require(&request).ok().cloned();
+        let peer_address = peer_address(&request);
+        let peer = peer_ip(&request);
+        let request = request.into_inner();
+        let action = PowerAction::from_i32(request.action);
+
+        let res = self
+            .schedule_power_action(client.as_ref(), peer, action, request.when)
+            .await;
+        self.audit_schedule(peer_address, client.as_ref(), action, &res)
+            .await;
+
+        res.map(|v| Response::new(v.into()))
+    }
+
+    async fn list_scheduled_actions(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<ScheduledActions>, Status> {
+        ClientIdentity::require(&request)?;
+
+        let actions = self
+            .scheduler
+            .list()
+            .await
+            .into_iter()
+            .map(ScheduledAction::from)
+            .collect();
+        Ok(Response::new(ScheduledActions { actions }))
+    }
+
+    async fn cancel_scheduled_action(
+        &self,
+        request: Request<CancelScheduledActionRequest>,
+    ) -> Result<Response<()>, Status> {
+        let client = ClientIdentity::require(&request)?.clone();
+        let id = request.into_inner().id;
+
+        let scheduled = self
+            .scheduler
+            .get(&id)
+            .await
+            .ok_or(ScheduleError::UnknownAction)?;
+        if !client.may_manage(&scheduled.requested_by) {
+            tracing::warn!(
+                "Device \"{}\" ({}) is not allowed to cancel scheduled action {} of device {}",
+                client.device_name,
+                client.device_id,
+                id,
+                scheduled.requested_by
+            );
+            return Err(Status::permission_denied(
+                "Only privileged devices may cancel actions scheduled by other devices",
+            ));
+        }
+
+        self.scheduler.cancel(&id).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn query_audit_log(
+        &self,
+        request: Request<QueryAuditLogRequest>,
+    ) -> Result<Response<AuditRecords>, Status> {
+        ClientIdentity::require(&request)?;
+        let request = request.into_inner();
+
+        let actions = request
+            .actions
+            .into_iter()
+            .map(|v| PowerAction::from_i32(v).map(Into::into))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Status::invalid_argument("Invalid power action"))?;
+
+        let filter = AuditFilter {
+            since: request.since,
+            until: request.until,
+            actions,
+            limit: request
+                .limit
+                .map_or(MAX_AUDIT_RECORDS, |v| v as usize)
+                .min(MAX_AUDIT_RECORDS),
         };
 
-        power
-            .perform_power_action(action.into())
+        let records = self
+            .audit
+            .query(&filter)
             .await
-            .map_err(|err| Status::internal(err.to_string()))
-            .map(Response::new)
+            .map_err(|err| Status::internal(err.to_string()))?
+            .into_iter()
+            .map(AuditRecord::from)
+            .collect();
+        Ok(Response::new(AuditRecords { records }))
     }
 }
 
 use crate::pal::power::PowerManager;
 pub use dragon_claw_agent_server::DragonClawAgentServer;
+
+/// Retrieves the IP address of the client which sent the request.
+///
+/// IPv4 clients connecting to the dual-stack listener are reported with their IPv4 address.
+fn peer_ip<T>(request: &Request<T>) -> Option<IpAddr> {
+    request.remote_addr().map(|addr| addr.ip().to_canonical())
+}
+
+/// Retrieves the socket address of the client which sent the request, see [`peer_ip`].
+fn peer_address<T>(request: &Request<T>) -> Option<SocketAddr> {
+    request
+        .remote_addr()
+        .map(|addr| SocketAddr::new(addr.ip().to_canonical(), addr.port()))
+}
@@ -0,0 +1,12 @@
+use super::NetworkInterface as ProtoNetworkInterface;
+use crate::pal::discovery::NetworkInterface as PalNetworkInterface;
+
+impl From<PalNetworkInterface> for ProtoNetworkInterface {
+    fn from(value: PalNetworkInterface) -> Self {
+        Self {
+            name: value.name,
+            mac_address: value.mac_address.map(|v| v.to_string()),
+            addresses: value.addresses.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
@@ -12,6 +12,7 @@ impl From<PalPowerAction> for ProtoPowerAction {
             PalPowerAction::Suspend => Self::Suspend,
             PalPowerAction::Hibernate => Self::Hibernate,
             PalPowerAction::HybridSuspend => Self::HybridSuspend,
+            PalPowerAction::SuspendThenHibernate => Self::SuspendThenHibernate,
         }
     }
 }
@@ -27,6 +28,7 @@ impl From<ProtoPowerAction> for PalPowerAction {
             ProtoPowerAction::Suspend => Self::Suspend,
             ProtoPowerAction::Hibernate => Self::Hibernate,
             ProtoPowerAction::HybridSuspend => Self::HybridSuspend,
+            ProtoPowerAction::SuspendThenHibernate => Self::SuspendThenHibernate,
         }
     }
 }
@@ -0,0 +1,72 @@
+use super::{
+    CpuInfo as ProtoCpuInfo, DiskUsage as ProtoDiskUsage, LoadAverage as ProtoLoadAverage,
+    MemoryUsage as ProtoMemoryUsage, OsRelease as ProtoOsRelease, SystemInfo as ProtoSystemInfo,
+};
+use crate::pal::system::{CpuInfo, DiskUsage, LoadAverage, MemoryUsage, OsRelease, SystemInfo};
+
+impl From<SystemInfo> for ProtoSystemInfo {
+    fn from(value: SystemInfo) -> Self {
+        Self {
+            os_release: value.os_release.map(From::from),
+            kernel_version: value.kernel_version,
+            uptime_seconds: value.uptime.map(|v| v.as_secs()),
+            boot_time: value.boot_time,
+            cpu: value.cpu.map(From::from),
+            load_average: value.load_average.map(From::from),
+            memory: value.memory.map(From::from),
+            swap: value.swap.map(From::from),
+            disks: value.disks.into_iter().map(From::from).collect(),
+        }
+    }
+}
+
+impl From<OsRelease> for ProtoOsRelease {
+    fn from(value: OsRelease) -> Self {
+        Self {
+            name: value.name,
+            version: value.version,
+            id: value.id,
+            pretty_name: value.pretty_name,
+        }
+    }
+}
+
+impl From<CpuInfo> for ProtoCpuInfo {
+    fn from(value: CpuInfo) -> Self {
+        Self {
+            model: value.model,
+            logical_cores: value.logical_cores,
+        }
+    }
+}
+
+impl From<LoadAverage> for ProtoLoadAverage {
+    fn from(value: LoadAverage) -> Self {
+        Self {
+            one: value.one,
+            five: value.five,
+            fifteen: value.fifteen,
+        }
+    }
+}
+
+impl From<MemoryUsage> for ProtoMemoryUsage {
+    fn from(value: MemoryUsage) -> Self {
+        Self {
+            total_bytes: value.total,
+            available_bytes: value.available,
+        }
+    }
+}
+
+impl From<DiskUsage> for ProtoDiskUsage {
+    fn from(value: DiskUsage) -> Self {
+        Self {
+            mount_point: value.mount_point,
+            device: value.device,
+            file_system: value.file_system,
+            total_bytes: value.total,
+            available_bytes: value.available,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use super::KnownPeer as ProtoKnownPeer;
+use crate::wol::{KnownPeer as WolKnownPeer, WakeOnLanError};
+use tonic::Status;
+
+impl From<WolKnownPeer> for ProtoKnownPeer {
+    fn from(value: WolKnownPeer) -> Self {
+        Self {
+            peer_id: value.id,
+            name: value.name,
+            mac_address: value.mac_address.to_string(),
+            interface: value.interface,
+            broadcast_address: value.broadcast_address.map(|v| v.to_string()),
+            added_at: value.added_at,
+        }
+    }
+}
+
+impl From<WakeOnLanError> for Status {
+    fn from(value: WakeOnLanError) -> Self {
+        match value {
+            WakeOnLanError::UnknownPeer | WakeOnLanError::UnknownInterface => {
+                Status::not_found(value.to_string())
+            }
+            WakeOnLanError::NoIpv4Address => Status::failed_precondition(value.to_string()),
+            WakeOnLanError::Storage(_) | WakeOnLanError::Platform(_) | WakeOnLanError::Io(_) => {
+                Status::internal(value.to_string())
+            }
+        }
+    }
+}
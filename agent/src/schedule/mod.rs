@@ -0,0 +1,431 @@
+use crate::audit::{AuditLog, AuditRecord, AuditResult};
+use crate::pairing::PairingManager;
+use crate::pal::power::{PowerAction, PowerManager};
+use crate::pal::{PlatformAbstraction, PlatformAbstractionError};
+use crate::policy::PowerPolicy;
+use crate::storage::{JsonFile, StorageError};
+use crate::tracker::ActionTracker;
+use crate::util::{from_unix_time, random_hex, unix_time_now};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+
+const SCHEDULED_ACTIONS_FILE_NAME: &str = "scheduled-actions.json";
+
+/// Scheduled actions which are overdue by more than this when the agent starts are discarded
+/// instead of being performed.
+const OVERDUE_GRACE_PERIOD: u64 = 5 * 60;
+
+/// Upper bound for sleeping until the next action, so changes of the wall clock are picked up.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// A power action scheduled for a later time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledAction {
+    /// Identifier of the scheduled action.
+    pub id: String,
+
+    /// The power action to perform.
+    pub action: PowerAction,
+
+    /// Time to perform the action at, in seconds since the unix epoch.
+    pub at: u64,
+
+    /// Identifier of the device which scheduled the action.
+    pub requested_by: String,
+
+    /// Address of the client which scheduled the action, checked against the power policy again
+    /// once the action is due.
+    #[serde(default)]
+    requested_from: Option<IpAddr>,
+
+    /// Whether the platform performs the action by itself.
+    #[serde(default)]
+    delegated: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ScheduledActionsDocument {
+    actions: Vec<ScheduledAction>,
+}
+
+impl ScheduledAction {
+    /// Tests whether the device which scheduled the action may still perform it.
+    fn is_authorized(&self, pairing: &PairingManager, policy: &PowerPolicy) -> bool {
+        pairing.is_paired(&self.requested_by)
+            && policy.is_allowed(Some(&self.requested_by), self.requested_from, self.action)
+    }
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    storage: JsonFile,
+    actions: Mutex<Vec<ScheduledAction>>,
+    changed: Notify,
+}
+
+/// Performs power actions at a later time.
+///
+/// Scheduled actions are persisted, so they survive a restart of the agent.
+#[derive(Debug)]
+pub struct Scheduler {
+    pal: Arc<PlatformAbstraction>,
+    state: Arc<SchedulerState>,
+    task: JoinHandle<()>,
+}
+
+impl Scheduler {
+    /// Loads the scheduled actions and starts the scheduler.
+    ///
+    /// Due actions are submitted to the given tracker for execution and recorded in the audit log.
+    /// Actions of devices which have been unpaired since, or which the power policy does not allow
+    /// them anymore, are discarded instead.
+    pub async fn start(
+        pal: Arc<PlatformAbstraction>,
+        tracker: Arc<ActionTracker>,
+        audit: Arc<AuditLog>,
+        pairing: Arc<PairingManager>,
+        policy: Arc<PowerPolicy>,
+    ) -> Result<Self, ScheduleError> {
+        let storage = JsonFile::new(pal.data_directory(), SCHEDULED_ACTIONS_FILE_NAME);
+        let mut document = storage.load::<ScheduledActionsDocument>().await?;
+
+        let now = unix_time_now();
+        document.actions.retain(|a| {
+            if a.delegated && a.at <= now {
+                // The platform performed it already, which is likely why the agent restarted
+                tracing::info!(
+                    "Dropping scheduled action {} ({:?}) handled by platform",
+                    a.id,
+                    a.action
+                );
+                return false;
+            }
+
+            let keep = a.delegated || a.at + OVERDUE_GRACE_PERIOD >= now;
+            if !keep {
+                tracing::warn!(
+                    "Discarding overdue scheduled action {} ({:?})",
+                    a.id,
+                    a.action
+                );
+            }
+
+            keep
+        });
+
+        // The platform forgets about actions handed over to it when the machine restarts, so
+        // hand them over again, or perform them ourselves if that fails
+        for scheduled in document.actions.iter_mut().filter(|a| a.delegated) {
+            scheduled.delegated = match pal.power_manager() {
+                Some(power) => match power
+                    .schedule_power_action(scheduled.action, from_unix_time(scheduled.at))
+                    .await
+                {
+                    Ok(v) => v,
+                    Err(err) => {
+                        tracing::warn!(
+                            "Failed to schedule power action {} with platform again: {}",
+                            scheduled.id,
+                            err
+                        );
+                        false
+                    }
+                },
+                None => false,
+            };
+
+            if !scheduled.delegated {
+                tracing::info!(
+                    "Scheduled action {} ({:?}) is performed by the agent instead of the platform",
+                    scheduled.id,
+                    scheduled.action
+                );
+            }
+        }
+
+        // Which actions are handed over may have changed
+        storage.store(&document).await?;
+
+        tracing::debug!("Loaded {} scheduled actions", document.actions.len());
+
+        let state = Arc::new(SchedulerState {
+            storage,
+            actions: Mutex::new(document.actions),
+            changed: Notify::new(),
+        });
+
+        let task = tokio::spawn(Self::run(
+            pal.clone(),
+            tracker,
+            audit,
+            pairing,
+            policy,
+            state.clone(),
+        ));
+
+        Ok(Self { pal, state, task })
+    }
+
+    /// Schedules a power action for the given time on behalf of the given device and address.
+    pub async fn schedule(
+        &self,
+        action: PowerAction,
+        at: u64,
+        requested_by: String,
+        requested_from: Option<IpAddr>,
+    ) -> Result<ScheduledAction, ScheduleError> {
+        let power = self
+            .pal
+            .power_manager()
+            .ok_or(PlatformAbstractionError::Unsupported)?;
+
+        if !power.get_supported_power_actions().await?.contains(&action) {
+            return Err(PlatformAbstractionError::Unsupported.into());
+        }
+
+        let mut actions = self.state.actions.lock().await;
+
+        // The platform can keep track of one action at most, so only hand it over if there is
+        // no other one yet
+        let delegated = if actions.iter().any(|a| a.delegated) {
+            false
+        } else {
+            match power
+                .schedule_power_action(action, from_unix_time(at))
+                .await
+            {
+                Ok(v) => v,
+                Err(err) => {
+                    tracing::warn!("Failed to schedule power action with platform: {}", err);
+                    false
+                }
+            }
+        };
+
+        let scheduled = ScheduledAction {
+            id: random_hex(8),
+            action,
+            at,
+            requested_by,
+            requested_from,
+            delegated,
+        };
+
+        actions.push(scheduled.clone());
+        self.state.persist(&actions).await?;
+        self.state.changed.notify_one();
+
+        tracing::info!(
+            "Scheduled power action {:?} at {} as {}",
+            scheduled.action,
+            scheduled.at,
+            scheduled.id
+        );
+
+        Ok(scheduled)
+    }
+
+    /// Retrieves all scheduled actions, ordered by the time they are due.
+    pub async fn list(&self) -> Vec<ScheduledAction> {
+        let mut actions = self.state.actions.lock().await.clone();
+        actions.sort_by_key(|a| a.at);
+        actions
+    }
+
+    /// Retrieves the scheduled action with the given identifier.
+    pub async fn get(&self, id: &str) -> Option<ScheduledAction> {
+        self.state
+            .actions
+            .lock()
+            .await
+            .iter()
+            .find(|a| a.id == id)
+            .cloned()
+    }
+
+    /// Cancels a scheduled action.
+    pub async fn cancel(&self, id: &str) -> Result<(), ScheduleError> {
+        let mut actions = self.state.actions.lock().await;
+        let Some(index) = actions.iter().position(|a| a.id == id) else {
+            return Err(ScheduleError::UnknownAction);
+        };
+
+        if actions[index].delegated {
+            let power = self
+                .pal
+                .power_manager()
+                .ok_or(PlatformAbstractionError::Unsupported)?;
+            power.cancel_scheduled_power_action().await?;
+        }
+
+        let cancelled = actions.remove(index);
+        self.state.persist(&actions).await?;
+        self.state.changed.notify_one();
+
+        tracing::info!(
+            "Cancelled scheduled power action {:?} ({})",
+            cancelled.action,
+            cancelled.id
+        );
+
+        Ok(())
+    }
+
+    /// Makes the scheduler check whether the scheduled actions may still be performed, such as
+    /// after a pairing has been revoked.
+    pub fn recheck(&self) {
+        self.state.changed.notify_one();
+    }
+
+    /// Stops the scheduler, scheduled actions stay persisted.
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+
+    async fn run(
+        pal: Arc<PlatformAbstraction>,
+        tracker: Arc<ActionTracker>,
+        audit: Arc<AuditLog>,
+        pairing: Arc<PairingManager>,
+        policy: Arc<PowerPolicy>,
+        state: Arc<SchedulerState>,
+    ) {
+        loop {
+            let (due, rejected) = {
+                let mut actions = state.actions.lock().await;
+                let now = unix_time_now();
+
+                // The device may have been unpaired since it scheduled the action
+                let (pending, rejected): (Vec<_>, Vec<_>) = actions
+                    .drain(..)
+                    .partition(|a| a.is_authorized(&pairing, &policy));
+                let (due, pending): (Vec<_>, Vec<_>) =
+                    pending.into_iter().partition(|a| a.at <= now);
+                *actions = pending;
+
+                if !due.is_empty() || !rejected.is_empty() {
+                    if let Err(err) = state.persist(&actions).await {
+                        tracing::error!("Failed to persist scheduled actions: {}", err);
+                    }
+                }
+
+                (due, rejected)
+            };
+
+            for scheduled in rejected {
+                tracing::warn!(
+                    "Discarding scheduled action {} ({:?}), device {} may not perform it anymore",
+                    scheduled.id,
+                    scheduled.action,
+                    scheduled.requested_by
+                );
+
+                // Otherwise the platform would perform it anyway
+                if scheduled.delegated {
+                    if let Some(power) = pal.power_manager() {
+                        if let Err(err) = power.cancel_scheduled_power_action().await {
+                            tracing::error!(
+                                "Failed to cancel scheduled action {} with platform: {}",
+                                scheduled.id,
+                                err
+                            );
+                        }
+                    }
+                }
+
+                let record = AuditRecord {
+                    timestamp: unix_time_now(),
+                    peer_address: None,
+                    device_id: Some(scheduled.requested_by),
+                    device_name: None,
+                    action: Some(scheduled.action),
+                    scheduled_id: Some(scheduled.id),
+                    result: AuditResult::Rejected {
+                        code: tonic::Code::PermissionDenied as i32,
+                        message: "Device may not perform the scheduled action anymore".to_string(),
+                    },
+                };
+                if let Err(err) = audit.record(&record).await {
+                    tracing::error!("Failed to write audit record {:?}: {}", record, err);
+                }
+            }
+
+            for scheduled in due {
+                let result = if scheduled.delegated {
+                    // The platform takes care of this one
+                    tracing::info!(
+                        "Scheduled action {} ({:?}) handled by platform",
+                        scheduled.id,
+                        scheduled.action
+                    );
+                    AuditResult::Delegated
+                } else {
+                    let action_id = tracker.submit(scheduled.action);
+                    tracing::info!(
+                        "Performing scheduled power action {:?} ({}) as {}",
+                        scheduled.action,
+                        scheduled.id,
+                        action_id
+                    );
+                    AuditResult::Accepted { action_id }
+                };
+
+                let record = AuditRecord {
+                    timestamp: unix_time_now(),
+                    peer_address: None,
+                    device_id: Some(scheduled.requested_by),
+                    device_name: None,
+                    action: Some(scheduled.action),
+                    scheduled_id: Some(scheduled.id),
+                    result,
+                };
+                if let Err(err) = audit.record(&record).await {
+                    tracing::error!("Failed to write audit record {:?}: {}", record, err);
+                }
+            }
+
+            let sleep_duration = {
+                let actions = state.actions.lock().await;
+                let now = unix_time_now();
+
+                actions
+                    .iter()
+                    .map(|a| Duration::from_secs(a.at.saturating_sub(now)))
+                    .min()
+                    .map_or(MAX_SLEEP, |d| d.min(MAX_SLEEP))
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(sleep_duration) => {},
+                _ = state.changed.notified() => {},
+            }
+        }
+    }
+}
+
+impl SchedulerState {
+    async fn persist(&self, actions: &[ScheduledAction]) -> Result<(), StorageError> {
+        self.storage
+            .store(&ScheduledActionsDocument {
+                actions: actions.to_vec(),
+            })
+            .await
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("failed to persist scheduled actions: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Platform(#[from] PlatformAbstractionError),
+
+    #[error("unknown scheduled action")]
+    UnknownAction,
+}
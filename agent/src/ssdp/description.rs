@@ -0,0 +1,229 @@
+use crate::net;
+use crate::ssdp::{Advertisement, SSDP_DEVICE_TYPE};
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Path the device description is served at.
+pub const DESCRIPTION_PATH: &str = "/description.xml";
+
+/// Path of the presentation page, which shows the same information to humans.
+const PRESENTATION_PATH: &str = "/";
+
+/// Namespace of the vendor specific elements of the device description.
+const DESCRIPTION_VENDOR_NAMESPACE: &str = "urn:dragon-claw:device-1-0";
+
+/// Serves the UPnP device description the SSDP announcements point to.
+///
+/// The server outlives updates of the announcements, so that their location stays the same.
+#[derive(Debug)]
+pub struct DescriptionServer {
+    advertisement: Arc<RwLock<Arc<Advertisement>>>,
+    task: JoinHandle<()>,
+}
+
+impl DescriptionServer {
+    /// Binds the listener for the description next to the RPC server, a random port is picked
+    /// if `port` is 0.
+    pub fn bind(service_addr: SocketAddr, port: u16) -> Result<TcpListener, std::io::Error> {
+        net::bind_listener(SocketAddr::new(service_addr.ip(), port))
+    }
+
+    /// Starts serving the description of the given advertisement.
+    pub fn start(
+        listener: TcpListener,
+        advertisement: Arc<Advertisement>,
+    ) -> Result<Self, std::io::Error> {
+        let port = listener.local_addr()?.port();
+        let incoming = AddrIncoming::from_listener(listener).map_err(std::io::Error::other)?;
+
+        let advertisement = Arc::new(RwLock::new(advertisement));
+        let shared_advertisement = advertisement.clone();
+        let make_service = make_service_fn(move |connection: &AddrStream| {
+            let local_addr = connection.local_addr();
+            let remote_addr = connection.remote_addr();
+            let advertisement = shared_advertisement.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let advertisement = advertisement
+                        .read()
+                        .expect("Poisoned advertisement lock")
+                        .clone();
+                    handle_request(request, advertisement, local_addr, remote_addr)
+                }))
+            }
+        });
+
+        let server = Server::builder(incoming).serve(make_service);
+        let task = tokio::spawn(async move {
+            if let Err(err) = server.await {
+                tracing::warn!("UPnP device description server failed: {}", err);
+            }
+        });
+
+        tracing::debug!("Serving UPnP device description on port {}", port);
+
+        Ok(Self {
+            advertisement,
+            task,
+        })
+    }
+
+    /// Serves the description of the given advertisement from now on.
+    #[cfg(target_os = "linux")]
+    pub fn replace(&self, advertisement: Arc<Advertisement>) {
+        *self
+            .advertisement
+            .write()
+            .expect("Poisoned advertisement lock") = advertisement;
+    }
+
+    /// Stops serving the description.
+    pub async fn stop(self) {
+        self.task.abort();
+        let _ = self.task.await;
+    }
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    advertisement: Arc<Advertisement>,
+    local_addr: SocketAddr,
+    remote_addr: SocketAddr,
+) -> Result<Response<Body>, Infallible> {
+    // The RPC server is reachable on the address the client reached us on
+    let rpc_location =
+        advertisement.rpc_location(advertisement.location_for(local_addr, remote_addr));
+
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, DESCRIPTION_PATH) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/xml; charset=\"utf-8\"")
+            .body(Body::from(device_description(
+                &advertisement,
+                &rpc_location,
+            ))),
+        (&Method::GET, PRESENTATION_PATH) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(presentation_page(&advertisement, &rpc_location))),
+        (_, DESCRIPTION_PATH | PRESENTATION_PATH) => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(hyper::header::ALLOW, "GET")
+            .body(Body::empty()),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+
+    Ok(response.expect("Device description response is valid"))
+}
+
+/// Renders the UPnP device description.
+///
+/// The agent is controlled via gRPC rather than SOAP, so the description lists no UPnP services.
+/// The RPC endpoint and the service properties are included as vendor specific elements
+/// instead.
+fn device_description(advertisement: &Advertisement, rpc_location: &str) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "<?xml version=\"1.0\" encoding=\"utf-8\"?>");
+    let _ = writeln!(
+        out,
+        "<root xmlns=\"urn:schemas-upnp-org:device-1-0\" xmlns:dc=\"{}\" configId=\"{}\">",
+        DESCRIPTION_VENDOR_NAMESPACE, advertisement.config_id
+    );
+    let _ = writeln!(out, "  <specVersion>");
+    let _ = writeln!(out, "    <major>1</major>");
+    let _ = writeln!(out, "    <minor>1</minor>");
+    let _ = writeln!(out, "  </specVersion>");
+    let _ = writeln!(out, "  <device>");
+    let _ = writeln!(out, "    <deviceType>{}</deviceType>", SSDP_DEVICE_TYPE);
+    let _ = writeln!(
+        out,
+        "    <friendlyName>{}</friendlyName>",
+        escape_xml(&advertisement.name)
+    );
+    let _ = writeln!(out, "    <manufacturer>Dragon Claw</manufacturer>");
+    let _ = writeln!(
+        out,
+        "    <modelDescription>Remote power control for computers</modelDescription>"
+    );
+    let _ = writeln!(out, "    <modelName>Dragon Claw Agent</modelName>");
+    let _ = writeln!(
+        out,
+        "    <modelNumber>{}</modelNumber>",
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(out, "    <UDN>{}</UDN>", escape_xml(&advertisement.udn));
+    let _ = writeln!(
+        out,
+        "    <presentationURL>{}</presentationURL>",
+        PRESENTATION_PATH
+    );
+    let _ = writeln!(
+        out,
+        "    <dc:rpcLocation>{}</dc:rpcLocation>",
+        escape_xml(rpc_location)
+    );
+    for (key, value) in &advertisement.properties {
+        let _ = writeln!(out, "    <dc:{key}>{}</dc:{key}>", escape_xml(value));
+    }
+    let _ = writeln!(out, "  </device>");
+    let _ = writeln!(out, "</root>");
+
+    out
+}
+
+/// Renders a page showing what the device description says.
+fn presentation_page(advertisement: &Advertisement, rpc_location: &str) -> String {
+    let name = escape_xml(&advertisement.name);
+    let mut out = String::new();
+
+    let _ = writeln!(out, "<!DOCTYPE html>");
+    let _ = writeln!(out, "<html>");
+    let _ = writeln!(out, "<head>");
+    let _ = writeln!(out, "<meta charset=\"utf-8\">");
+    let _ = writeln!(out, "<title>{}</title>", name);
+    let _ = writeln!(out, "</head>");
+    let _ = writeln!(out, "<body>");
+    let _ = writeln!(out, "<h1>{}</h1>", name);
+    let _ = writeln!(
+        out,
+        "<p>Dragon Claw Agent {}, use the Dragon Claw App to control this computer.</p>",
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(out, "<dl>");
+    let _ = writeln!(
+        out,
+        "<dt>RPC endpoint</dt><dd>{}</dd>",
+        escape_xml(rpc_location)
+    );
+    let _ = writeln!(
+        out,
+        "<dt>UDN</dt><dd>{}</dd>",
+        escape_xml(&advertisement.udn)
+    );
+    for (key, value) in &advertisement.properties {
+        let _ = writeln!(out, "<dt>{}</dt><dd>{}</dd>", key, escape_xml(value));
+    }
+    let _ = writeln!(out, "</dl>");
+    let _ = writeln!(out, "</body>");
+    let _ = writeln!(out, "</html>");
+
+    out
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
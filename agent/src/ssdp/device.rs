@@ -0,0 +1,32 @@
+use crate::storage::{JsonFile, StorageError};
+use crate::util::random_uuid;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const UPNP_DEVICE_FILE_NAME: &str = "upnp-device.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UpnpDeviceDocument {
+    uuid: Option<String>,
+}
+
+/// Loads the UUID the agent is announced with as UPnP device, or generates and persists a new
+/// one if there is none yet.
+///
+/// Control points remember devices by this UUID, so it has to survive restarts of the agent.
+pub async fn load_or_generate_device_uuid(data_directory: &Path) -> Result<String, StorageError> {
+    let storage = JsonFile::new(data_directory, UPNP_DEVICE_FILE_NAME);
+
+    let mut document = storage.load::<UpnpDeviceDocument>().await?;
+    if let Some(uuid) = document.uuid {
+        return Ok(uuid);
+    }
+
+    let uuid = random_uuid();
+    document.uuid = Some(uuid.clone());
+    storage.store(&document).await?;
+
+    tracing::info!("Generated UPnP device UUID {}", uuid);
+
+    Ok(uuid)
+}
@@ -1,12 +1,25 @@
-use socket2::{Domain, Protocol, Socket};
+use crate::metrics::METRICS;
+use crate::pal::discovery::ServiceInfo;
+use crate::util::unix_time_now;
+use futures::stream::{FuturesUnordered, StreamExt};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use socket2::{Domain, Protocol, SockRef, Socket};
 use std::fmt::{Display, Formatter};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::UdpSocket;
 use tokio::sync::Notify;
 use tokio::task::JoinHandle;
 
+mod description;
+mod device;
+
+use description::{DescriptionServer, DESCRIPTION_PATH};
+pub use device::load_or_generate_device_uuid;
+
 const SSDP_ANY_IPV4: Ipv4Addr = Ipv4Addr::new(0, 0, 0, 0);
 const SSDP_ANY_IPV4_SOCKET: SocketAddr = SocketAddr::V4(SocketAddrV4::new(SSDP_ANY_IPV4, 1900));
 const SSDP_ANY_IPV6: Ipv6Addr = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0);
@@ -19,7 +32,32 @@ const SSDP_MULTICAST_IPV6: Ipv6Addr = Ipv6Addr::new(0xff05, 0, 0, 0, 0, 0, 0, 0x
 const SSDP_MULTICAST_IPV6_SOCKET: SocketAddr =
     SocketAddr::V6(SocketAddrV6::new(SSDP_MULTICAST_IPV6, 1900, 0, 0));
 
+const SSDP_DEVICE_TYPE: &str = "urn:dragon-claw:device:DragonClawAgent:1";
 const SSDP_SERVICE_TYPE: &str = "urn:dragon-claw:service:DragonClawAgent:1";
+const SSDP_ROOT_DEVICE: &str = "upnp:rootdevice";
+const SSDP_ALL: &str = "ssdp:all";
+
+/// Upper bound of the `MX` header, searchers asking for a longer delay get this one.
+const SSDP_MAX_MX: u64 = 5;
+
+/// Largest boot id UPnP allows, a 31 bit value.
+const SSDP_MAX_BOOT_ID: u32 = 0x7fff_ffff;
+
+/// Upper bound of the random delay before the first announcements.
+const SSDP_MAX_INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+/// How often the announcements are sent when they start, as some of them may get lost.
+const SSDP_INITIAL_ANNOUNCEMENTS: u32 = 3;
+
+/// Time between the initial announcements.
+const SSDP_INITIAL_ANNOUNCEMENT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Search responses waiting for their delay per receiver, further searches are dropped.
+const SSDP_MAX_PENDING_RESPONSES: usize = 64;
+
+/// Suffix of the vendor specific headers carrying the service properties, for example
+/// `FINGERPRINT.DRAGON-CLAW.JANRUPF.NET` for the TLS certificate fingerprint.
+const SSDP_PROPERTY_HEADER_SUFFIX: &str = ".DRAGON-CLAW.JANRUPF.NET";
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum IpAddrWithScopeId {
@@ -57,6 +95,14 @@ impl IpAddrWithScopeId {
         }
     }
 
+    /// Retrieves the IP address without the scope id.
+    pub fn ip(&self) -> IpAddr {
+        match self {
+            IpAddrWithScopeId::V4(v) => IpAddr::V4(*v),
+            IpAddrWithScopeId::V6 { addr, .. } => IpAddr::V6(*addr),
+        }
+    }
+
     /// Returns true if the address is a loopback address.
     pub fn is_loopback(&self) -> bool {
         match self {
@@ -77,6 +123,322 @@ impl Display for IpAddrWithScopeId {
     }
 }
 
+/// Kind of a NOTIFY message, the `NTS` header.
+#[derive(Debug, Clone, Copy)]
+enum NotifyKind {
+    /// The agent is available.
+    Alive,
+
+    /// The agent stays available under a new boot id, announced before using it. Only sent when
+    /// following interface changes, which is done on Linux only.
+    #[cfg(target_os = "linux")]
+    Update { previous_boot_id: u32 },
+
+    /// The agent is going away.
+    ByeBye,
+}
+
+impl NotifyKind {
+    fn nts(&self) -> &'static str {
+        match self {
+            NotifyKind::Alive => "ssdp:alive",
+            #[cfg(target_os = "linux")]
+            NotifyKind::Update { .. } => "ssdp:update",
+            NotifyKind::ByeBye => "ssdp:byebye",
+        }
+    }
+}
+
+/// Something the agent announces, identified by its notification type.
+#[derive(Debug, Clone)]
+struct Notification {
+    /// The notification type, which is also the search target it is found by.
+    nt: String,
+
+    /// Unique service name of the announced entity.
+    usn: String,
+}
+
+/// Everything the agent announces, shared by the announcements and the search responses.
+#[derive(Debug, Clone)]
+struct Advertisement {
+    name: String,
+    udn: String,
+    service_addr: SocketAddr,
+    notifications: Vec<Notification>,
+    properties: Vec<(&'static str, String)>,
+    max_age: Duration,
+    description_port: u16,
+    boot_id: u32,
+    config_id: u32,
+}
+
+impl Advertisement {
+    fn new(
+        name: String,
+        service: &ServiceInfo,
+        max_age: Duration,
+        description_port: u16,
+    ) -> Self {
+        // A root device announces itself, its UDN and its device type, followed by its services
+        let udn = format!("uuid:{}", service.device_uuid);
+        let notifications = [SSDP_ROOT_DEVICE, &udn, SSDP_DEVICE_TYPE, SSDP_SERVICE_TYPE]
+            .into_iter()
+            .map(|nt| Notification {
+                nt: nt.to_string(),
+                usn: match nt == udn {
+                    true => udn.clone(),
+                    false => format!("{}::{}", udn, nt),
+                },
+            })
+            .collect();
+
+        let properties = service.properties();
+        let config_id = Self::config_id(&name, &properties);
+
+        Self {
+            name,
+            udn,
+            service_addr: service.socket_addr,
+            notifications,
+            properties,
+            max_age,
+            description_port,
+            boot_id: Self::next_boot_id(None),
+            config_id,
+        }
+    }
+
+    /// The same advertisement under the next boot id, for when the network state changed.
+    #[cfg(target_os = "linux")]
+    fn with_next_boot_id(&self) -> Self {
+        Self {
+            boot_id: Self::next_boot_id(Some(self.boot_id)),
+            ..self.clone()
+        }
+    }
+
+    /// Derives the configuration id from what the device description says, so that it changes
+    /// whenever the description does.
+    fn config_id(name: &str, properties: &[(&'static str, String)]) -> u32 {
+        let mut hasher = Sha256::new();
+        hasher.update(name.as_bytes());
+        hasher.update(b"\n");
+        for (key, value) in properties {
+            hasher.update(key.as_bytes());
+            hasher.update(b"=");
+            hasher.update(value.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        // UPnP only allows 24 bit configuration ids
+        let digest = hasher.finalize();
+        u32::from_be_bytes([0, digest[0], digest[1], digest[2]])
+    }
+
+    /// Picks the boot id for a new set of announcements, which has to be larger every time.
+    ///
+    /// The current time keeps increasing across restarts of the agent, so nothing needs to be
+    /// persisted.
+    fn next_boot_id(previous: Option<u32>) -> u32 {
+        let now = (unix_time_now() & SSDP_MAX_BOOT_ID as u64) as u32;
+        match previous {
+            Some(previous) => now.max(previous.wrapping_add(1) & SSDP_MAX_BOOT_ID),
+            None => now,
+        }
+    }
+
+    /// Picks a random time until the next announcement, below half of the max age so that an
+    /// announcement may get lost without the agent expiring.
+    fn reannounce_interval(&self) -> Duration {
+        let half_max_age = self.max_age.as_millis() as u64 / 2;
+        Duration::from_millis(rand::thread_rng().gen_range(half_max_age / 2..half_max_age))
+    }
+
+    /// Builds a NOTIFY message for the given notification, announcing the given service address.
+    fn build_notify(
+        &self,
+        service_addr: SocketAddr,
+        kind: NotifyKind,
+        notification: &Notification,
+    ) -> Vec<u8> {
+        // Host to announce in the SSDP message
+        let host = match service_addr.ip() {
+            IpAddr::V4(_) => "239.255.255.250:1900",
+            IpAddr::V6(_) => "[ff05::c]:1900",
+        };
+
+        // Build the SSDP request
+        let mut http_request = http::Request::builder()
+            .method("NOTIFY")
+            .uri("*")
+            .version(http::Version::HTTP_11)
+            .header("HOST", host)
+            .header("NT", &notification.nt)
+            .header("NTS", kind.nts())
+            .header("USN", &notification.usn)
+            .header("CONFIGID.UPNP.ORG", self.config_id);
+
+        http_request = match kind {
+            NotifyKind::Alive => http_request
+                .header(
+                    "CACHE-CONTROL",
+                    format!("max-age={}", self.max_age.as_secs()),
+                )
+                .header("LOCATION", self.description_url(service_addr))
+                .header("SERVER", SSDPMulticast::server_header())
+                .header("BOOTID.UPNP.ORG", self.boot_id),
+            #[cfg(target_os = "linux")]
+            NotifyKind::Update { previous_boot_id } => http_request
+                .header("LOCATION", self.description_url(service_addr))
+                .header("BOOTID.UPNP.ORG", previous_boot_id)
+                .header("NEXTBOOTID.UPNP.ORG", self.boot_id),
+            NotifyKind::ByeBye => http_request.header("BOOTID.UPNP.ORG", self.boot_id),
+        };
+
+        // Clients tell agents apart by these, so they are part of every message
+        for (key, value) in self.identity_properties(service_addr) {
+            http_request = http_request.header(SSDPMulticast::property_header_name(key), value);
+        }
+
+        if let NotifyKind::Alive = kind {
+            for (key, value) in &self.properties {
+                http_request = http_request.header(SSDPMulticast::property_header_name(key), value);
+            }
+        }
+
+        let http_request = http_request.body(()).unwrap();
+
+        SSDPMulticast::http_request_to_data(http_request)
+    }
+
+    /// Builds the unicast response to an M-SEARCH request for the given notification.
+    fn build_search_response(&self, location: SocketAddr, notification: &Notification) -> Vec<u8> {
+        let mut http_response = http::Response::builder()
+            .status(http::StatusCode::OK)
+            .version(http::Version::HTTP_11)
+            .header(
+                "CACHE-CONTROL",
+                format!("max-age={}", self.max_age.as_secs()),
+            )
+            .header("EXT", "")
+            .header("LOCATION", self.description_url(location))
+            .header("SERVER", SSDPMulticast::server_header())
+            .header("ST", &notification.nt)
+            .header("USN", &notification.usn)
+            .header("BOOTID.UPNP.ORG", self.boot_id)
+            .header("CONFIGID.UPNP.ORG", self.config_id);
+
+        for (key, value) in self
+            .identity_properties(location)
+            .iter()
+            .chain(&self.properties)
+        {
+            http_response = http_response.header(SSDPMulticast::property_header_name(key), value);
+        }
+
+        let http_response = http_response.body(()).unwrap();
+
+        SSDPMulticast::http_response_to_data(http_response)
+    }
+
+    /// URL of the device description as served on the given service address.
+    fn description_url(&self, service_addr: SocketAddr) -> String {
+        let host = match SSDPMulticast::location_addr(service_addr) {
+            SocketAddr::V4(addr) => addr.ip().to_string(),
+            // The zone index has to be percent-encoded in URLs
+            SocketAddr::V6(addr) if addr.scope_id() != 0 => {
+                format!("[{}%25{}]", addr.ip(), addr.scope_id())
+            }
+            SocketAddr::V6(addr) => format!("[{}]", addr.ip()),
+        };
+
+        format!(
+            "http://{}:{}{}",
+            host, self.description_port, DESCRIPTION_PATH
+        )
+    }
+
+    /// Location of the RPC server as reachable via the given service address.
+    fn rpc_location(&self, service_addr: SocketAddr) -> String {
+        format!("tcp://{}", SSDPMulticast::location_addr(service_addr))
+    }
+
+    /// The properties identifying the agent, which are not part of the service properties.
+    fn identity_properties(&self, service_addr: SocketAddr) -> [(&'static str, String); 2] {
+        [
+            ("rpc", self.rpc_location(service_addr)),
+            ("name", self.name.clone()),
+        ]
+    }
+
+    /// Determines the notifications to answer an M-SEARCH request with, none if the request
+    /// is not a search the agent takes part in.
+    fn search_targets(&self, request: &http::Request<()>) -> Vec<&Notification> {
+        let header = |name| request.headers().get(name).and_then(|v| v.to_str().ok());
+
+        if request.method().as_str() != "M-SEARCH" || header("MAN") != Some("\"ssdp:discover\"") {
+            return Vec::new();
+        }
+
+        match header("ST") {
+            None => Vec::new(),
+            Some(SSDP_ALL) => self.notifications.iter().collect(),
+            Some(st) => self.notifications.iter().filter(|n| n.nt == st).collect(),
+        }
+    }
+
+    /// Sends the responses for the given notifications to the searcher after the given delay.
+    async fn respond(&self, searcher: SocketAddr, targets: Vec<&Notification>, delay: Duration) {
+        tokio::time::sleep(delay).await;
+
+        if let Err(err) = self.send_responses(searcher, &targets).await {
+            tracing::warn!("Failed to answer SSDP search from {}: {}", searcher, err);
+        } else {
+            tracing::trace!("Answered SSDP search from {}", searcher);
+        }
+    }
+
+    async fn send_responses(
+        &self,
+        searcher: SocketAddr,
+        targets: &[&Notification],
+    ) -> Result<(), std::io::Error> {
+        let bind_addr = match searcher {
+            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(SSDP_ANY_IPV4), 0),
+            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(SSDP_ANY_IPV6), 0),
+        };
+
+        // Connecting lets the routing table pick the local address the searcher can reach
+        let socket = UdpSocket::bind(bind_addr).await?;
+        socket.connect(searcher).await?;
+        let location = self.location_for(socket.local_addr()?, searcher);
+
+        for notification in targets {
+            let data = self.build_search_response(location, notification);
+            SSDPMulticast::send_all_to(&socket, &data, &searcher).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Determines the service address to announce to a searcher the given local address is
+    /// used to talk to.
+    fn location_for(&self, local_addr: SocketAddr, searcher: SocketAddr) -> SocketAddr {
+        if !self.service_addr.ip().is_unspecified() {
+            return self.service_addr;
+        }
+
+        let mut location = SocketAddr::new(local_addr.ip(), self.service_addr.port());
+        if let (SocketAddr::V6(location), SocketAddr::V6(searcher)) = (&mut location, searcher) {
+            // Link-local addresses are only reachable on the interface the search came from
+            location.set_scope_id(searcher.scope_id());
+        }
+
+        location
+    }
+}
+
 #[derive(Debug)]
 struct SendTask {
     socket: UdpSocket,
@@ -101,63 +463,295 @@ impl SendTask {
     }
 }
 
+/// Announcements on a single local address.
 #[derive(Debug)]
-struct SendTaskNotifiers {
+struct Announcer {
+    /// Only needed to follow interface changes.
+    #[cfg(target_os = "linux")]
+    address: IpAddrWithScopeId,
     shutdown: Arc<AtomicBool>,
-    notifiers: Vec<Arc<Notify>>,
+    notify: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl Announcer {
+    /// Starts announcing on the given address using the given sender socket.
+    fn spawn(
+        address: IpAddrWithScopeId,
+        socket: UdpSocket,
+        advertisement: Arc<Advertisement>,
+    ) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let notify = Arc::new(Notify::new());
+
+        let send_task = SendTask::new(
+            socket,
+            shutdown.clone(),
+            notify.clone(),
+            address.to_socket_addr(advertisement.service_addr.port()),
+        );
+        let task = tokio::spawn(SSDPMulticast::send_loop(send_task, advertisement));
+
+        Self {
+            #[cfg(target_os = "linux")]
+            address,
+            shutdown,
+            notify,
+            task,
+        }
+    }
+
+    /// Makes the announcer say goodbye and stop, the task finishes once it did.
+    fn withdraw(&self) {
+        self.shutdown.store(true, Ordering::Release);
+        self.notify.notify_waiters();
+    }
+}
+
+/// Multicast receiver for one address family, along with the local addresses it joined the
+/// multicast group on.
+///
+/// Everything but the task is only needed to follow interface changes.
+#[derive(Debug)]
+struct Receiver {
+    #[cfg(target_os = "linux")]
+    is_ipv4: bool,
+    #[cfg(target_os = "linux")]
+    local_addresses: Vec<IpAddrWithScopeId>,
+    #[cfg(target_os = "linux")]
+    socket: Arc<UdpSocket>,
+    task: JoinHandle<()>,
+}
+
+/// A receiver socket which has joined the multicast group, but has not been started yet.
+#[derive(Debug)]
+struct BoundReceiver {
+    #[cfg(target_os = "linux")]
+    is_ipv4: bool,
+    #[cfg(target_os = "linux")]
+    local_addresses: Vec<IpAddrWithScopeId>,
+    socket: UdpSocket,
+}
+
+/// Sockets bound for a set of announcements, which have not been started yet.
+#[derive(Debug)]
+struct BoundSockets {
+    receivers: Vec<BoundReceiver>,
+    senders: Vec<(IpAddrWithScopeId, UdpSocket)>,
 }
 
 #[derive(Debug)]
 pub struct SSDPMulticast {
-    notifiers: SendTaskNotifiers,
-    send_task: JoinHandle<()>,
-    receive_task: JoinHandle<()>,
+    receivers: Vec<Receiver>,
+    announcers: Vec<Announcer>,
+    advertisement: Arc<Advertisement>,
+    description: DescriptionServer,
 }
 
 impl SSDPMulticast {
     /// Set's up the SSDP multicast and begins SSDP multicast announcements
     /// for the given service address.
+    ///
+    /// Announcements are valid for `max_age` and repeated at random intervals below half of it.
+    /// They point to a UPnP device description with the given name, which is served on
+    /// `description_port`, or a random port if it is 0.
     pub async fn setup<F>(
-        usn: String,
-        service_addr: SocketAddr,
+        name: String,
+        service: &ServiceInfo,
+        max_age: Duration,
+        description_port: u16,
         get_local_addresses: F,
     ) -> Result<Self, std::io::Error>
     where
         F: FnOnce() -> Result<Vec<IpAddrWithScopeId>, std::io::Error>,
     {
-        let is_unspecified = service_addr.ip().is_unspecified();
+        let sockets = Self::bind_sockets(service.socket_addr, get_local_addresses)?;
+        let listener = DescriptionServer::bind(service.socket_addr, description_port)?;
+
+        let advertisement = Arc::new(Advertisement::new(
+            name,
+            service,
+            max_age,
+            listener.local_addr()?.port(),
+        ));
+        let description = DescriptionServer::start(listener, advertisement.clone())?;
+
+        Ok(Self::start(sockets, advertisement, description))
+    }
 
-        // Determine the local addresses we need to listen for SSDP multicast requests on
-        let (local_ipv4, local_ipv6) = match is_unspecified {
+    /// Follows changes of the local addresses, such as after a DHCP lease changed or a VPN came
+    /// up.
+    ///
+    /// Addresses which went away are withdrawn with `ssdp:byebye`. As UPnP requires for changes
+    /// of the network state, the announcements on all other addresses then carry on under the
+    /// next boot id, introduced with `ssdp:update`, and new addresses are announced like on
+    /// startup.
+    #[cfg(target_os = "linux")]
+    pub async fn refresh_addresses<F>(
+        &mut self,
+        get_local_addresses: F,
+    ) -> Result<(), std::io::Error>
+    where
+        F: FnOnce() -> Result<Vec<IpAddrWithScopeId>, std::io::Error>,
+    {
+        let local_addresses =
+            Self::local_addresses(self.advertisement.service_addr, get_local_addresses)?;
+
+        let is_unchanged = self.announcers.len() == local_addresses.len()
+            && self
+                .announcers
+                .iter()
+                .all(|v| local_addresses.contains(&v.address));
+        if is_unchanged {
+            return Ok(());
+        }
+
+        // Say goodbye on the addresses which went away first, a new address might replace them
+        let (withdrawn, remaining) = std::mem::take(&mut self.announcers)
+            .into_iter()
+            .partition::<Vec<_>, _>(|v| !local_addresses.contains(&v.address));
+
+        for announcer in &withdrawn {
+            tracing::info!(
+                "Local address {} went away, withdrawing SSDP announcements",
+                announcer.address
+            );
+            announcer.withdraw();
+        }
+        futures::future::join_all(withdrawn.into_iter().map(|v| v.task)).await;
+
+        // The remaining announcements are replaced by ones under the next boot id, without
+        // saying goodbye
+        let previous_boot_id = self.advertisement.boot_id;
+        self.advertisement = Arc::new(self.advertisement.with_next_boot_id());
+        self.description.replace(self.advertisement.clone());
+
+        for announcer in &remaining {
+            announcer.task.abort();
+        }
+        let remaining_addresses = remaining.iter().map(|v| v.address).collect::<Vec<_>>();
+        futures::future::join_all(remaining.into_iter().map(|v| v.task)).await;
+
+        // Follow the changes with the receivers, dropping those which have no addresses left
+        let (local_ipv4, local_ipv6) = local_addresses
+            .iter()
+            .partition::<Vec<IpAddrWithScopeId>, _>(|v| v.is_ipv4());
+        self.refresh_receiver(true, local_ipv4);
+        self.refresh_receiver(false, local_ipv6);
+
+        let senders = local_addresses
+            .iter()
+            .filter_map(|&address| {
+                Self::bind_multicast_sender(address)
+                    .and_then(Self::socket2_to_tokio)
+                    .map(|socket| (address, socket))
+            })
+            .collect::<Vec<_>>();
+
+        // Addresses which have been announced before learn about the new boot id, before
+        // anything is announced under it
+        let update = NotifyKind::Update { previous_boot_id };
+        for (address, socket) in &senders {
+            if remaining_addresses.contains(address) {
+                let addr = address.to_socket_addr(self.advertisement.service_addr.port());
+                Self::send_notifications(socket, addr, &self.advertisement, update).await;
+                tracing::debug!("Sent SSDP update request for {}", addr);
+            }
+        }
+
+        for (address, socket) in senders {
+            if !remaining_addresses.contains(&address) {
+                tracing::info!("Local address {} appeared, announcing via SSDP", address);
+            }
+
+            self.announcers.push(Announcer::spawn(
+                address,
+                socket,
+                self.advertisement.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Makes the receiver of an address family listen on the given addresses of that family.
+    #[cfg(target_os = "linux")]
+    fn refresh_receiver(&mut self, is_ipv4: bool, local_addresses: Vec<IpAddrWithScopeId>) {
+        let existing = self.receivers.iter().position(|v| v.is_ipv4 == is_ipv4);
+
+        match existing {
+            Some(index) if local_addresses.is_empty() => {
+                // Searches in this address family can't be answered anymore
+                self.receivers.swap_remove(index).task.abort();
+            }
+            Some(index) => {
+                let receiver = &mut self.receivers[index];
+                Self::update_memberships(
+                    &receiver.socket,
+                    &receiver.local_addresses,
+                    &local_addresses,
+                );
+                receiver.local_addresses = local_addresses;
+
+                // Search responses carry the boot id, so the receiver picks up the current one
+                receiver.task.abort();
+                receiver.task = tokio::spawn(Self::receive_loop(
+                    receiver.socket.clone(),
+                    self.advertisement.clone(),
+                ));
+            }
+            None => {
+                if let Some(bound) = Self::bind_multicast_receiver(local_addresses) {
+                    let receiver = Self::spawn_receiver(bound, self.advertisement.clone());
+                    self.receivers.push(receiver);
+                }
+            }
+        }
+    }
+
+    /// Determines the local addresses to announce the service on.
+    fn local_addresses<F>(
+        service_addr: SocketAddr,
+        get_local_addresses: F,
+    ) -> Result<Vec<IpAddrWithScopeId>, std::io::Error>
+    where
+        F: FnOnce() -> Result<Vec<IpAddrWithScopeId>, std::io::Error>,
+    {
+        // An unspecified IPv6 address means the service is reachable via both address families
+        let addresses = match service_addr.ip().is_unspecified() {
             true => get_local_addresses()?,
             false => vec![IpAddrWithScopeId::derive_from(&service_addr)],
         }
-        .iter()
+        .into_iter()
         .filter(|v| !v.is_loopback())
-        .partition::<Vec<IpAddrWithScopeId>, _>(|v| v.is_ipv4());
+        .filter(|v| service_addr.is_ipv6() || v.is_ipv4())
+        .collect();
 
-        // Attempt to bind a multicast receiver for each address family
-        let mut receive_sockets = Vec::new();
-        let mut send_tasks = Vec::new();
+        Ok(addresses)
+    }
 
-        let shutdown = Arc::new(AtomicBool::new(false));
+    fn bind_sockets<F>(
+        service_addr: SocketAddr,
+        get_local_addresses: F,
+    ) -> Result<BoundSockets, std::io::Error>
+    where
+        F: FnOnce() -> Result<Vec<IpAddrWithScopeId>, std::io::Error>,
+    {
+        // Determine the local addresses we need to listen for SSDP multicast requests on
+        let (local_ipv4, local_ipv6) = Self::local_addresses(service_addr, get_local_addresses)?
+            .into_iter()
+            .partition::<Vec<IpAddrWithScopeId>, _>(|v| v.is_ipv4());
 
-        Self::bind_multicast_sockets(
-            &local_ipv4,
-            &mut receive_sockets,
-            &mut send_tasks,
-            shutdown.clone(),
-            service_addr.port(),
-        );
-        Self::bind_multicast_sockets(
-            &local_ipv6,
-            &mut receive_sockets,
-            &mut send_tasks,
-            shutdown.clone(),
-            service_addr.port(),
-        );
+        // Attempt to bind a multicast receiver for each address family
+        let mut sockets = BoundSockets {
+            receivers: Vec::new(),
+            senders: Vec::new(),
+        };
 
-        if receive_sockets.is_empty() {
+        Self::bind_multicast_sockets(local_ipv4, &mut sockets);
+        Self::bind_multicast_sockets(local_ipv6, &mut sockets);
+
+        if sockets.receivers.is_empty() {
             // Could not bind to any local interface for receiving SSDP multicast requests
             return Err(std::io::Error::new(
                 std::io::ErrorKind::AddrNotAvailable,
@@ -165,64 +759,69 @@ impl SSDPMulticast {
             ));
         }
 
-        let notifiers = send_tasks
-            .iter()
-            .map(|v| v.notify.clone())
-            .collect::<Vec<_>>();
-
-        let send_task = tokio::spawn(Self::send_task(send_tasks, usn));
-        let receive_task = tokio::spawn(Self::receive_task(receive_sockets, notifiers.clone()));
+        Ok(sockets)
+    }
 
-        let notifiers = SendTaskNotifiers {
-            shutdown,
-            notifiers,
-        };
+    fn start(
+        sockets: BoundSockets,
+        advertisement: Arc<Advertisement>,
+        description: DescriptionServer,
+    ) -> Self {
+        let announcers = sockets
+            .senders
+            .into_iter()
+            .map(|(address, socket)| Announcer::spawn(address, socket, advertisement.clone()))
+            .collect();
+
+        let receivers = sockets
+            .receivers
+            .into_iter()
+            .map(|v| Self::spawn_receiver(v, advertisement.clone()))
+            .collect();
 
-        Ok(Self {
-            notifiers,
-            send_task,
-            receive_task,
-        })
+        Self {
+            receivers,
+            announcers,
+            advertisement,
+            description,
+        }
     }
 
-    fn bind_multicast_sockets(
-        local_addresses: &[IpAddrWithScopeId],
-        receive_sockets: &mut Vec<UdpSocket>,
-        send_tasks: &mut Vec<SendTask>,
-        shutdown: Arc<AtomicBool>,
-        service_port: u16,
-    ) {
-        let receiver =
-            match Self::bind_multicast_receiver(local_addresses).and_then(Self::socket2_to_tokio) {
-                Some(socket) => socket,
-                None => return,
-            };
+    fn spawn_receiver(bound: BoundReceiver, advertisement: Arc<Advertisement>) -> Receiver {
+        let socket = Arc::new(bound.socket);
+        let task = tokio::spawn(Self::receive_loop(socket.clone(), advertisement));
+
+        Receiver {
+            #[cfg(target_os = "linux")]
+            is_ipv4: bound.is_ipv4,
+            #[cfg(target_os = "linux")]
+            local_addresses: bound.local_addresses,
+            #[cfg(target_os = "linux")]
+            socket,
+            task,
+        }
+    }
 
-        let len_before = send_tasks.len();
-        send_tasks.extend(
+    fn bind_multicast_sockets(local_addresses: Vec<IpAddrWithScopeId>, sockets: &mut BoundSockets) {
+        let len_before = sockets.senders.len();
+        sockets.senders.extend(
             local_addresses
                 .iter()
                 .map(|&a| Self::bind_multicast_sender(a).map(|v| (a, v)))
-                .filter_map(|v| v.and_then(|(a, v)| Self::socket2_to_tokio(v).map(|v| (a, v))))
-                .map(|(a, s)| {
-                    SendTask::new(
-                        s,
-                        shutdown.clone(),
-                        Arc::new(Notify::new()),
-                        a.to_socket_addr(service_port),
-                    )
-                }),
+                .filter_map(|v| v.and_then(|(a, v)| Self::socket2_to_tokio(v).map(|v| (a, v)))),
         );
 
-        if len_before == send_tasks.len() {
-            // No senders were created for this receiver
+        if len_before == sockets.senders.len() {
+            // No senders were created, so there is no point in receiving
             return;
         }
 
-        receive_sockets.push(receiver);
+        if let Some(receiver) = Self::bind_multicast_receiver(local_addresses) {
+            sockets.receivers.push(receiver);
+        }
     }
 
-    fn bind_multicast_receiver(local_addresses: &[IpAddrWithScopeId]) -> Option<Socket> {
+    fn bind_multicast_receiver(local_addresses: Vec<IpAddrWithScopeId>) -> Option<BoundReceiver> {
         let domain = match local_addresses.first() {
             // No socket to bind in this address family
             None => return None,
@@ -268,35 +867,97 @@ impl SSDPMulticast {
                 _ => unreachable!(),
             }
 
-            // Join the multicast group on all local addresses
-            for local_address in local_addresses {
-                let res = match (domain, local_address) {
-                    (Domain::IPV4, IpAddrWithScopeId::V4(addr)) => {
-                        socket.join_multicast_v4(&SSDP_MULTICAST_IPV4, addr)
-                    }
-                    (Domain::IPV6, IpAddrWithScopeId::V6 { scope_id, .. }) => {
-                        socket.join_multicast_v6(&SSDP_MULTICAST_IPV6, *scope_id)
-                    }
-                    _ => panic!("Attempted to mix IPv4 and IPv6 addresses"),
-                };
-
-                if let Err(err) = res {
-                    tracing::warn!(
-                        "Failed to join multicast group on {}: {}",
-                        local_address,
-                        err
-                    );
-                }
-            }
-
             Ok(socket)
         };
 
-        match do_bind() {
-            Ok(v) => Some(v),
+        let socket = match do_bind() {
+            Ok(v) => Self::socket2_to_tokio(v)?,
             Err(e) => {
                 tracing::warn!("Failed to bind multicast receiver: {}", e);
-                None
+                return None;
+            }
+        };
+
+        Self::update_memberships(&socket, &[], &local_addresses);
+
+        Some(BoundReceiver {
+            #[cfg(target_os = "linux")]
+            is_ipv4: domain == Domain::IPV4,
+            #[cfg(target_os = "linux")]
+            local_addresses,
+            socket,
+        })
+    }
+
+    /// Joins the multicast group on the local addresses which are new and leaves it on those
+    /// which went away.
+    ///
+    /// IPv6 groups are joined per interface, and an interface usually has multiple addresses.
+    fn update_memberships(
+        socket: &UdpSocket,
+        previous: &[IpAddrWithScopeId],
+        current: &[IpAddrWithScopeId],
+    ) {
+        let socket = SockRef::from(socket);
+        let has_interface = |addresses: &[IpAddrWithScopeId], address: &IpAddrWithScopeId| {
+            addresses.iter().any(|v| match (v, address) {
+                (
+                    IpAddrWithScopeId::V6 { scope_id: a, .. },
+                    IpAddrWithScopeId::V6 { scope_id: b, .. },
+                ) => a == b,
+                (a, b) => a == b,
+            })
+        };
+
+        for (index, local_address) in current.iter().enumerate() {
+            if has_interface(previous, local_address)
+                || has_interface(&current[..index], local_address)
+            {
+                continue;
+            }
+
+            let res = match local_address {
+                IpAddrWithScopeId::V4(addr) => socket.join_multicast_v4(&SSDP_MULTICAST_IPV4, addr),
+                IpAddrWithScopeId::V6 { scope_id, .. } => {
+                    socket.join_multicast_v6(&SSDP_MULTICAST_IPV6, *scope_id)
+                }
+            };
+
+            match res {
+                Ok(()) => {}
+                // The interface joined through another one of its addresses already
+                Err(err) if err.kind() == std::io::ErrorKind::AddrInUse => {}
+                Err(err) => tracing::warn!(
+                    "Failed to join multicast group on {}: {}",
+                    local_address,
+                    err
+                ),
+            }
+        }
+
+        for (index, local_address) in previous.iter().enumerate() {
+            if has_interface(current, local_address)
+                || has_interface(&previous[..index], local_address)
+            {
+                continue;
+            }
+
+            let res = match local_address {
+                IpAddrWithScopeId::V4(addr) => {
+                    socket.leave_multicast_v4(&SSDP_MULTICAST_IPV4, addr)
+                }
+                IpAddrWithScopeId::V6 { scope_id, .. } => {
+                    socket.leave_multicast_v6(&SSDP_MULTICAST_IPV6, *scope_id)
+                }
+            };
+
+            // The membership is usually gone along with the interface already
+            if let Err(err) = res {
+                tracing::debug!(
+                    "Failed to leave multicast group on {}: {}",
+                    local_address,
+                    err
+                );
             }
         }
     }
@@ -356,33 +1017,48 @@ impl SSDPMulticast {
         }
     }
 
-    fn build_ssdp_message(service_addr: SocketAddr, nts: &str, usn: &str) -> Vec<u8> {
-        // Host to announce in the SSDP message
-        let host = match service_addr.ip() {
-            IpAddr::V4(_) => "239.255.255.250:1900",
-            IpAddr::V6(_) => "[ff05::c]:1900",
-        };
+    /// Value of the `SERVER` header, which describes the agent.
+    fn server_header() -> String {
+        format!(
+            "{} UPnP/1.1 DragonClaw/{}",
+            std::env::consts::OS,
+            env!("CARGO_PKG_VERSION")
+        )
+    }
 
-        // Build the SSDP request
-        let http_request = http::Request::builder()
-            .method("NOTIFY")
-            .uri("*")
-            .version(http::Version::HTTP_11)
-            .header("HOST", host)
-            .header("NT", SSDP_SERVICE_TYPE)
-            .header(
-                "USER-AGENT",
-                concat!("DragonClaw/", env!("CARGO_PKG_VERSION")),
-            )
-            .header("NTS", nts)
-            .header("CACHE-CONTROL", "max-age=30")
-            .header("LOCATION", format!("tcp://{}", service_addr))
-            .header("MAN", "\"ssdp:discover\"")
-            .header("USN", usn)
-            .body(())
-            .unwrap();
+    /// Name of the vendor specific header carrying the given service property.
+    fn property_header_name(key: &str) -> String {
+        format!("{}{}", key.to_uppercase(), SSDP_PROPERTY_HEADER_SUFFIX)
+    }
+
+    /// Picks a random delay for answering an M-SEARCH request, bounded by its `MX` header.
+    ///
+    /// Unicast searches come without `MX` and are answered right away.
+    fn search_response_delay(request: &http::Request<()>) -> Duration {
+        let mx = request
+            .headers()
+            .get("MX")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0)
+            .min(SSDP_MAX_MX);
+
+        Duration::from_millis(rand::thread_rng().gen_range(0..=mx * 1000))
+    }
 
-        Self::http_request_to_data(http_request)
+    /// Determines the address to announce as location of the service.
+    ///
+    /// The scope id is only meaningful for link-local IPv6 addresses, for any other address it
+    /// is just the interface the announcement is sent on.
+    fn location_addr(mut service_addr: SocketAddr) -> SocketAddr {
+        if let SocketAddr::V6(addr) = &mut service_addr {
+            let is_link_local = addr.ip().segments()[0] & 0xffc0 == 0xfe80;
+            if !is_link_local {
+                addr.set_scope_id(0);
+            }
+        }
+
+        service_addr
     }
 
     /// Helper function to always send an entire buffer over a UDP socket
@@ -401,126 +1077,182 @@ impl SSDPMulticast {
             send_index += bytes_sent;
         }
 
+        METRICS.ssdp_packets_sent.inc();
         Ok(())
     }
 
-    async fn send_task(sockets: Vec<SendTask>, usn: String) {
-        async fn send_loop(
-            SendTask {
-                addr,
-                socket,
-                shutdown,
-                notify,
-            }: SendTask,
-            usn: &str,
-        ) {
-            let multicast_address = if addr.is_ipv4() {
-                SSDP_MULTICAST_IPV4_SOCKET
-            } else {
-                SSDP_MULTICAST_IPV6_SOCKET
+    /// Sends a NOTIFY message of the given kind for every notification from the given address.
+    async fn send_notifications(
+        socket: &UdpSocket,
+        addr: SocketAddr,
+        advertisement: &Advertisement,
+        kind: NotifyKind,
+    ) {
+        let multicast_address = if addr.is_ipv4() {
+            SSDP_MULTICAST_IPV4_SOCKET
+        } else {
+            SSDP_MULTICAST_IPV6_SOCKET
+        };
+
+        for notification in &advertisement.notifications {
+            let data = advertisement.build_notify(addr, kind, notification);
+
+            // Make sure we always write out the entire request
+            let Err(err) = Self::send_all_to(socket, &data, &multicast_address).await else {
+                continue;
             };
-            let alive_data = SSDPMulticast::build_ssdp_message(addr, "ssdp:alive", usn);
 
-            loop {
-                // Make sure we always write out the entire request
-                if let Err(err) =
-                    SSDPMulticast::send_all_to(&socket, &alive_data, &multicast_address).await
-                {
-                    tracing::warn!("Failed to send SSDP alive request for {}: {}", addr, err);
-                }
-                tracing::trace!(
-                    "Sent SSDP request for {}, sleeping for 30 seconds (or until notify)",
-                    addr
-                );
+            // The other notifications would fail just the same, and goodbyes usually fail
+            // because the address went away already
+            match kind {
+                NotifyKind::ByeBye => tracing::debug!(
+                    "Failed to send SSDP {} request for {}: {}",
+                    kind.nts(),
+                    addr,
+                    err
+                ),
+                _ => tracing::warn!(
+                    "Failed to send SSDP {} request for {}: {}",
+                    kind.nts(),
+                    addr,
+                    err
+                ),
+            }
+            break;
+        }
+    }
 
-                // Send SSDP request every 30 seconds or until we are notified
-                tokio::select!(
-                    _ = tokio::time::sleep(std::time::Duration::from_secs(30)) => {},
-                    _ = notify.notified() => {},
-                );
+    async fn send_loop(task: SendTask, advertisement: Arc<Advertisement>) {
+        /// Waits for the given time, returns true if the announcements should stop.
+        async fn wait(task: &SendTask, duration: Duration) -> bool {
+            let notified = task.notify.notified();
+            tokio::pin!(notified);
 
-                if shutdown.load(Ordering::Acquire) {
-                    // Shutdown requested
-                    break;
-                }
+            // Register before checking the flag, so a shutdown in between is not missed
+            notified.as_mut().enable();
+            if task.shutdown.load(Ordering::Acquire) {
+                return true;
             }
 
-            let byebye_data = SSDPMulticast::build_ssdp_message(addr, "ssdp:byebye", usn);
-            if let Err(err) =
-                SSDPMulticast::send_all_to(&socket, &byebye_data, &multicast_address).await
-            {
-                tracing::warn!("Failed to send SSDP byebye request: {}", err);
+            tokio::select!(
+                _ = tokio::time::sleep(duration) => {},
+                _ = notified => {},
+            );
+
+            task.shutdown.load(Ordering::Acquire)
+        }
+
+        // Don't let machines which come up at the same time announce themselves in lockstep
+        let initial_delay = rand::thread_rng().gen_range(Duration::ZERO..=SSDP_MAX_INITIAL_DELAY);
+        if wait(&task, initial_delay).await {
+            // Nothing has been announced yet, so there is nothing to say goodbye to
+            return;
+        }
+
+        // The first announcements are repeated right away, in case some of them get lost
+        let mut initial_announcements = SSDP_INITIAL_ANNOUNCEMENTS;
+
+        loop {
+            Self::send_notifications(&task.socket, task.addr, &advertisement, NotifyKind::Alive)
+                .await;
+
+            let interval = if initial_announcements > 1 {
+                initial_announcements -= 1;
+                SSDP_INITIAL_ANNOUNCEMENT_INTERVAL
             } else {
-                tracing::debug!("Sent SSDP byebye request for {}", addr);
+                advertisement.reannounce_interval()
+            };
+            tracing::trace!(
+                "Sent SSDP alive requests for {}, sleeping for {} ms (or until notify)",
+                task.addr,
+                interval.as_millis()
+            );
+
+            if wait(&task, interval).await {
+                // Shutdown requested
+                break;
             }
         }
 
-        // Send SSDP requests on all sockets
-        futures::future::join_all(sockets.into_iter().map(|t| send_loop(t, &usn))).await;
+        Self::send_notifications(&task.socket, task.addr, &advertisement, NotifyKind::ByeBye).await;
+        tracing::debug!("Sent SSDP byebye requests for {}", task.addr);
     }
 
-    async fn receive_task(sockets: Vec<UdpSocket>, notifiers: Vec<Arc<Notify>>) {
-        async fn receive_loop(socket: UdpSocket, notifiers: &[Arc<Notify>]) {
-            let mut receive_buffer = Vec::with_capacity(1024);
+    async fn receive_loop(socket: Arc<UdpSocket>, advertisement: Arc<Advertisement>) {
+        let mut receive_buffer = Vec::with_capacity(1024);
+        let mut pending_responses = FuturesUnordered::new();
 
-            loop {
-                match socket.recv_buf(&mut receive_buffer).await {
-                    Ok(v) => v,
+        loop {
+            let source = tokio::select! {
+                res = socket.recv_buf_from(&mut receive_buffer) => match res {
+                    Ok((_, source)) => source,
                     Err(err) => {
                         tracing::warn!("Failed to receive SSDP response: {}", err);
                         continue;
                     }
-                };
+                },
+                // Drive the responses waiting for their delay
+                Some(()) = pending_responses.next(), if !pending_responses.is_empty() => continue,
+            };
+            METRICS.ssdp_packets_received.inc();
 
-                let requests = SSDPMulticast::data_to_http_request(&mut receive_buffer);
+            let requests = SSDPMulticast::data_to_http_request(&mut receive_buffer);
 
-                if receive_buffer.len() > 4096 {
-                    // Either someone is attempting to send us a very large SSDP requests or
-                    // we only received parts of many requests. Either way, clear the buffer.
-                    tracing::warn!("Receive buffer has grown too large, clearing");
-                    receive_buffer.clear();
-                }
+            if receive_buffer.len() > 4096 {
+                // Either someone is attempting to send us a very large SSDP requests or
+                // we only received parts of many requests. Either way, clear the buffer.
+                tracing::warn!("Receive buffer has grown too large, clearing");
+                receive_buffer.clear();
+            }
 
-                let mut do_notify = false;
+            for request in requests {
+                // Check if there is a search request dragon_claw_agent has to answer
+                let targets = advertisement.search_targets(&request);
+                if targets.is_empty() {
+                    continue;
+                }
 
-                for request in requests {
-                    let method = request.method();
+                METRICS.ssdp_search_hits.inc();
+                tracing::trace!(
+                    "Received SSDP search from {} for {} notifications",
+                    source,
+                    targets.len()
+                );
 
-                    // Check if there is a search request for dragon_claw_agent
-                    if method.as_str() == "M-SEARCH" {
-                        let st = request.headers().get("ST").and_then(|v| v.to_str().ok());
-                        if st.is_some_and(|st| st == SSDP_SERVICE_TYPE) {
-                            do_notify = true;
-                            tracing::trace!("Received SSDP search for dragon_claw_agent");
-                        }
-                    };
+                if pending_responses.len() >= SSDP_MAX_PENDING_RESPONSES {
+                    tracing::debug!("Too many pending SSDP responses, ignoring search");
+                    continue;
                 }
 
-                if do_notify {
-                    // Notify the senders to send a new SSDP request
-                    for notify in notifiers {
-                        notify.notify_waiters();
-                    }
-                }
+                let delay = SSDPMulticast::search_response_delay(&request);
+                pending_responses.push(advertisement.respond(source, targets, delay));
             }
         }
-
-        // Receive SSDP requests on all sockets
-        futures::future::join_all(sockets.into_iter().map(|s| receive_loop(s, &notifiers))).await;
     }
 
     /// Stops the ssdp multicast announcements.
     pub async fn stop(self) {
-        // We can just abort the receive tasks, they don't need to do any cleanup
-        self.receive_task.abort();
+        // We can just abort the receive tasks, they don't need to do any cleanup, pending
+        // responses are dropped along with them
+        for receiver in &self.receivers {
+            receiver.task.abort();
+        }
 
-        // For the send tasks, we need to notify them to stop and then wait for them to finish
-        self.notifiers.shutdown.store(true, Ordering::Release);
-        for notifier in self.notifiers.notifiers {
-            notifier.notify_waiters();
+        // The announcers need to say goodbye, so we wait for them to finish
+        for announcer in &self.announcers {
+            announcer.withdraw();
         }
 
-        let _ = tokio::join!(self.receive_task, self.send_task);
+        futures::future::join_all(
+            self.receivers
+                .into_iter()
+                .map(|v| v.task)
+                .chain(self.announcers.into_iter().map(|v| v.task)),
+        )
+        .await;
+
+        // Only once the goodbyes are out, nobody should follow the location anymore
+        self.description.stop().await;
     }
 
     /// Converts an HTTP request into a byte vector.
@@ -539,14 +1271,37 @@ impl SSDPMulticast {
             _ => unreachable!("Unsupported HTTP version"),
         });
         data.extend_from_slice(b"\r\n");
-        for (name, value) in request.headers() {
+        Self::write_headers(&mut data, request.headers());
+        data
+    }
+
+    /// Converts an HTTP response into a byte vector.
+    fn http_response_to_data(response: http::Response<()>) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"HTTP/1.1 ");
+        data.extend_from_slice(response.status().as_str().as_bytes());
+        data.push(b' ');
+        data.extend_from_slice(
+            response
+                .status()
+                .canonical_reason()
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+        data.extend_from_slice(b"\r\n");
+        Self::write_headers(&mut data, response.headers());
+        data
+    }
+
+    /// Appends the headers followed by the empty line ending them.
+    fn write_headers(data: &mut Vec<u8>, headers: &http::HeaderMap) {
+        for (name, value) in headers {
             data.extend_from_slice(name.as_str().to_uppercase().as_bytes());
             data.extend_from_slice(b": ");
             data.extend_from_slice(value.as_bytes());
             data.extend_from_slice(b"\r\n");
         }
         data.extend_from_slice(b"\r\n");
-        data
     }
 
     /// This is a very bad implementation of an HTTP request parser - but its fault tolerant
@@ -568,14 +1323,14 @@ impl SSDPMulticast {
                 let Some(end_of_line) = Self::find_subsequence(response_data, b"\r\n") else { break };
                 let line = &response_data[..end_of_line];
 
+                // Advance the response data
+                response_data = &response_data[end_of_line + 2..];
+
                 if line.is_empty() {
                     // Ignore empty lines
                     continue;
                 }
 
-                // Advance the response data
-                response_data = &response_data[end_of_line + 2..];
-
                 if !begin_found {
                     // We need the http version, uri and http version
                     let mut parts = line.splitn(3, |c| *c == b' ');
@@ -680,3 +1435,379 @@ impl SSDPMulticast {
         &input[start_index..end_index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_ADDR: &str = "192.168.1.2:37121";
+    const DEVICE_UUID: &str = "01234567-89ab-4cde-8f01-23456789abcd";
+    const MAX_AGE: Duration = Duration::from_secs(1800);
+    const DESCRIPTION_PORT: u16 = 37122;
+
+    fn advertisement() -> Advertisement {
+        let service = ServiceInfo {
+            socket_addr: SERVICE_ADDR.parse().unwrap(),
+            certificate_fingerprint: "ab".repeat(32),
+            mac_addresses: Vec::new(),
+            device_uuid: DEVICE_UUID.to_string(),
+        };
+
+        Advertisement::new("Desktop".to_string(), &service, MAX_AGE, DESCRIPTION_PORT)
+    }
+
+    fn search(headers: &[(&str, &str)]) -> http::Request<()> {
+        let mut request = http::Request::builder().method("M-SEARCH").uri("*");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        request.body(()).unwrap()
+    }
+
+    /// Splits a message into its start line and headers.
+    fn parse_message(data: &[u8]) -> (String, Vec<(String, String)>) {
+        let message = std::str::from_utf8(data).unwrap();
+        let message = message.strip_suffix("\r\n\r\n").unwrap();
+
+        let mut lines = message.split("\r\n");
+        let start_line = lines.next().unwrap().to_string();
+        let headers = lines
+            .map(|line| {
+                let (name, value) = line.split_once(": ").unwrap();
+                (name.to_string(), value.to_string())
+            })
+            .collect();
+
+        (start_line, headers)
+    }
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn searches_are_answered_for_matching_targets() {
+        let advertisement = advertisement();
+        let targets = |st: &str| {
+            advertisement
+                .search_targets(&search(&[("MAN", "\"ssdp:discover\""), ("ST", st)]))
+                .into_iter()
+                .map(|n| n.nt.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(targets(SSDP_SERVICE_TYPE), [SSDP_SERVICE_TYPE]);
+        assert_eq!(targets(SSDP_ROOT_DEVICE), [SSDP_ROOT_DEVICE]);
+        assert_eq!(
+            targets(&format!("uuid:{}", DEVICE_UUID)),
+            [format!("uuid:{}", DEVICE_UUID)]
+        );
+        assert_eq!(targets(SSDP_ALL).len(), 4);
+        assert!(targets("urn:schemas-upnp-org:device:MediaServer:1").is_empty());
+    }
+
+    #[test]
+    fn only_discover_searches_are_answered() {
+        let advertisement = advertisement();
+
+        assert!(advertisement
+            .search_targets(&search(&[("ST", SSDP_ALL)]))
+            .is_empty());
+        assert!(advertisement
+            .search_targets(&search(&[("MAN", "\"ssdp:discover\"")]))
+            .is_empty());
+
+        let notify = http::Request::builder()
+            .method("NOTIFY")
+            .uri("*")
+            .header("MAN", "\"ssdp:discover\"")
+            .header("ST", SSDP_ALL)
+            .body(())
+            .unwrap();
+        assert!(advertisement.search_targets(&notify).is_empty());
+    }
+
+    #[test]
+    fn search_response_describes_the_agent() {
+        let advertisement = advertisement();
+        let notification = advertisement
+            .notifications
+            .iter()
+            .find(|n| n.nt == SSDP_SERVICE_TYPE)
+            .unwrap();
+
+        let data = advertisement.build_search_response(SERVICE_ADDR.parse().unwrap(), notification);
+        let (start_line, headers) = parse_message(&data);
+
+        assert_eq!(start_line, "HTTP/1.1 200 OK");
+        assert_eq!(header(&headers, "ST"), Some(SSDP_SERVICE_TYPE));
+        assert_eq!(
+            header(&headers, "USN"),
+            Some(format!("uuid:{}::{}", DEVICE_UUID, SSDP_SERVICE_TYPE).as_str())
+        );
+        assert_eq!(header(&headers, "CACHE-CONTROL"), Some("max-age=1800"));
+        assert_eq!(header(&headers, "EXT"), Some(""));
+        assert_eq!(
+            header(&headers, "LOCATION"),
+            Some(
+                format!(
+                    "http://192.168.1.2:{}{}",
+                    DESCRIPTION_PORT, DESCRIPTION_PATH
+                )
+                .as_str()
+            )
+        );
+        assert_eq!(
+            header(&headers, "RPC.DRAGON-CLAW.JANRUPF.NET"),
+            Some("tcp://192.168.1.2:37121")
+        );
+        assert_eq!(
+            header(&headers, "NAME.DRAGON-CLAW.JANRUPF.NET"),
+            Some("Desktop")
+        );
+        assert_eq!(
+            header(&headers, "FINGERPRINT.DRAGON-CLAW.JANRUPF.NET"),
+            Some(format!("sha256:{}", "ab".repeat(32)).as_str())
+        );
+    }
+
+    #[test]
+    fn unspecified_service_address_is_replaced_by_reachable_one() {
+        let mut advertisement = advertisement();
+        advertisement.service_addr = "[::]:37121".parse().unwrap();
+
+        assert_eq!(
+            advertisement.location_for(
+                "192.168.1.2:50000".parse().unwrap(),
+                "192.168.1.3:1900".parse().unwrap()
+            ),
+            "192.168.1.2:37121".parse().unwrap()
+        );
+        assert_eq!(
+            advertisement.location_for(
+                "[fe80::2]:50000".parse().unwrap(),
+                "[fe80::3%3]:1900".parse().unwrap()
+            ),
+            SocketAddr::V6(SocketAddrV6::new("fe80::2".parse().unwrap(), 37121, 0, 3))
+        );
+    }
+
+    #[test]
+    fn search_response_delay_is_bounded_by_mx() {
+        assert_eq!(
+            SSDPMulticast::search_response_delay(&search(&[])),
+            Duration::ZERO
+        );
+
+        for _ in 0..100 {
+            assert!(
+                SSDPMulticast::search_response_delay(&search(&[("MX", "2")]))
+                    <= Duration::from_secs(2)
+            );
+            assert!(
+                SSDPMulticast::search_response_delay(&search(&[("MX", "120")]))
+                    <= Duration::from_secs(SSDP_MAX_MX)
+            );
+        }
+    }
+
+    #[test]
+    fn requests_are_parsed_from_received_data() {
+        let mut data = b"M-SEARCH * HTTP/1.1\r\n\
+            HOST: 239.255.255.250:1900\r\n\
+            MAN: \"ssdp:discover\"\r\n\
+            MX:  3 \r\n\
+            ST: ssdp:all\r\n\
+            \r\n\
+            not a request\r\n\
+            \r\n\
+            NOTIFY * HTTP/1.1\r\n\
+            NT: upnp:rootdevice\r\n\
+            \r\n\
+            M-SEARCH * HTTP/1.1\r\n"
+            .to_vec();
+
+        let requests = SSDPMulticast::data_to_http_request(&mut data);
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method().as_str(), "M-SEARCH");
+        assert_eq!(requests[0].headers()["MX"], "3");
+        assert_eq!(requests[0].headers()["ST"], "ssdp:all");
+        assert_eq!(requests[1].method().as_str(), "NOTIFY");
+        assert_eq!(requests[1].headers()["NT"], "upnp:rootdevice");
+
+        // The incomplete request stays for the next datagram
+        assert_eq!(data, b"M-SEARCH * HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn empty_lines_are_skipped() {
+        let mut data = b"\r\n\r\n\r\nM-SEARCH * HTTP/1.1\r\nST: ssdp:all\r\n\r\n".to_vec();
+
+        let requests = SSDPMulticast::data_to_http_request(&mut data);
+
+        assert_eq!(requests.len(), 1);
+        assert!(data.is_empty());
+    }
+
+    fn service_notification(advertisement: &Advertisement) -> &Notification {
+        advertisement
+            .notifications
+            .iter()
+            .find(|n| n.nt == SSDP_SERVICE_TYPE)
+            .unwrap()
+    }
+
+    #[test]
+    fn root_device_announces_itself_and_its_service() {
+        let advertisement = advertisement();
+        let udn = format!("uuid:{}", DEVICE_UUID);
+
+        let notifications = advertisement
+            .notifications
+            .iter()
+            .map(|n| (n.nt.as_str(), n.usn.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notifications,
+            [
+                (
+                    SSDP_ROOT_DEVICE,
+                    format!("{}::{}", udn, SSDP_ROOT_DEVICE).as_str()
+                ),
+                (udn.as_str(), udn.as_str()),
+                (
+                    SSDP_DEVICE_TYPE,
+                    format!("{}::{}", udn, SSDP_DEVICE_TYPE).as_str()
+                ),
+                (
+                    SSDP_SERVICE_TYPE,
+                    format!("{}::{}", udn, SSDP_SERVICE_TYPE).as_str()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn alive_notification_describes_the_agent() {
+        let advertisement = advertisement();
+        let data = advertisement.build_notify(
+            SERVICE_ADDR.parse().unwrap(),
+            NotifyKind::Alive,
+            service_notification(&advertisement),
+        );
+        let (start_line, headers) = parse_message(&data);
+
+        assert_eq!(start_line, "NOTIFY * HTTP/1.1");
+        assert_eq!(header(&headers, "HOST"), Some("239.255.255.250:1900"));
+        assert_eq!(header(&headers, "NT"), Some(SSDP_SERVICE_TYPE));
+        assert_eq!(header(&headers, "NTS"), Some("ssdp:alive"));
+        assert_eq!(header(&headers, "CACHE-CONTROL"), Some("max-age=1800"));
+        assert_eq!(
+            header(&headers, "BOOTID.UPNP.ORG"),
+            Some(advertisement.boot_id.to_string().as_str())
+        );
+        assert_eq!(
+            header(&headers, "CONFIGID.UPNP.ORG"),
+            Some(advertisement.config_id.to_string().as_str())
+        );
+        assert_eq!(
+            header(&headers, "RPC.DRAGON-CLAW.JANRUPF.NET"),
+            Some("tcp://192.168.1.2:37121")
+        );
+        assert!(header(&headers, "FINGERPRINT.DRAGON-CLAW.JANRUPF.NET").is_some());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn update_notification_announces_next_boot_id() {
+        let advertisement = advertisement();
+        let updated = advertisement.with_next_boot_id();
+        assert!(updated.boot_id > advertisement.boot_id);
+
+        let data = updated.build_notify(
+            "[fd00::2]:37121".parse().unwrap(),
+            NotifyKind::Update {
+                previous_boot_id: advertisement.boot_id,
+            },
+            service_notification(&updated),
+        );
+        let (_, headers) = parse_message(&data);
+
+        assert_eq!(header(&headers, "HOST"), Some("[ff05::c]:1900"));
+        assert_eq!(header(&headers, "NTS"), Some("ssdp:update"));
+        assert_eq!(
+            header(&headers, "BOOTID.UPNP.ORG"),
+            Some(advertisement.boot_id.to_string().as_str())
+        );
+        assert_eq!(
+            header(&headers, "NEXTBOOTID.UPNP.ORG"),
+            Some(updated.boot_id.to_string().as_str())
+        );
+        assert!(header(&headers, "LOCATION").is_some());
+        assert!(header(&headers, "CACHE-CONTROL").is_none());
+    }
+
+    #[test]
+    fn byebye_notification_only_identifies_the_agent() {
+        let advertisement = advertisement();
+        let data = advertisement.build_notify(
+            SERVICE_ADDR.parse().unwrap(),
+            NotifyKind::ByeBye,
+            service_notification(&advertisement),
+        );
+        let (_, headers) = parse_message(&data);
+
+        assert_eq!(header(&headers, "NTS"), Some("ssdp:byebye"));
+        assert!(header(&headers, "USN").is_some());
+        assert!(header(&headers, "RPC.DRAGON-CLAW.JANRUPF.NET").is_some());
+        assert!(header(&headers, "LOCATION").is_none());
+        assert!(header(&headers, "CACHE-CONTROL").is_none());
+        assert!(header(&headers, "FINGERPRINT.DRAGON-CLAW.JANRUPF.NET").is_none());
+    }
+
+    #[test]
+    fn reannouncements_happen_well_before_expiry() {
+        let advertisement = advertisement();
+
+        for _ in 0..100 {
+            let interval = advertisement.reannounce_interval();
+            assert!(interval >= MAX_AGE / 4);
+            assert!(interval < MAX_AGE / 2);
+        }
+    }
+
+    #[test]
+    fn boot_ids_keep_increasing() {
+        let first = Advertisement::next_boot_id(None);
+        assert!(first <= SSDP_MAX_BOOT_ID);
+
+        // Even if the clock went backwards
+        assert_eq!(
+            Advertisement::next_boot_id(Some(first + 1000)),
+            first + 1001
+        );
+        assert_eq!(
+            Advertisement::next_boot_id(Some(SSDP_MAX_BOOT_ID - 1)),
+            SSDP_MAX_BOOT_ID
+        );
+    }
+
+    #[test]
+    fn config_id_follows_description() {
+        let properties = [("fingerprint", "sha256:ab".to_string())];
+        let config_id = Advertisement::config_id("Desktop", &properties);
+
+        assert!(config_id < 1 << 24);
+        assert_eq!(Advertisement::config_id("Desktop", &properties), config_id);
+        assert_ne!(Advertisement::config_id("Laptop", &properties), config_id);
+        assert_ne!(
+            Advertisement::config_id("Desktop", &[("fingerprint", "sha256:cd".to_string())]),
+            config_id
+        );
+    }
+}
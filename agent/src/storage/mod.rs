@@ -0,0 +1,58 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// A JSON document which is persisted in the agent data directory.
+#[derive(Debug)]
+pub struct JsonFile {
+    path: PathBuf,
+}
+
+impl JsonFile {
+    /// Creates a handle to the JSON document with the given name in the given directory.
+    pub fn new(directory: &Path, name: &str) -> Self {
+        Self {
+            path: directory.join(name),
+        }
+    }
+
+    /// Loads the document, or returns the default value if it does not exist yet.
+    pub async fn load<T: DeserializeOwned + Default>(&self) -> Result<T, StorageError> {
+        let data = match tokio::fs::read(&self.path).await {
+            Ok(v) => v,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        serde_json::from_slice(&data).map_err(|err| StorageError::Json(self.path.clone(), err))
+    }
+
+    /// Stores the document.
+    ///
+    /// The data is first written to a temporary file which then replaces the document, so an
+    /// interrupted write never leaves a truncated document behind.
+    pub async fn store<T: Serialize>(&self, value: &T) -> Result<(), StorageError> {
+        let data = serde_json::to_vec_pretty(value)
+            .map_err(|err| StorageError::Json(self.path.clone(), err))?;
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let temporary_path = self.path.with_extension("tmp");
+        tokio::fs::write(&temporary_path, data).await?;
+        tokio::fs::rename(&temporary_path, &self.path).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid JSON in {}: {1}", .0.display())]
+    Json(PathBuf, serde_json::Error),
+}
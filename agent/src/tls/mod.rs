@@ -0,0 +1,119 @@
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use thiserror::Error;
+use tonic::transport::{Identity, ServerTlsConfig};
+
+const CERTIFICATE_FILE_NAME: &str = "agent-cert.pem";
+const PRIVATE_KEY_FILE_NAME: &str = "agent-key.pem";
+
+/// Subject alternative name of the generated certificate.
+///
+/// Clients are not expected to validate the name, they pin the certificate fingerprint instead.
+const CERTIFICATE_SUBJECT_NAME: &str = "dragon-claw-agent";
+
+/// The TLS identity the agent uses to serve RPC requests.
+#[derive(Debug)]
+pub struct AgentIdentity {
+    certificate_pem: String,
+    private_key_pem: String,
+    fingerprint: String,
+}
+
+impl AgentIdentity {
+    /// Loads the identity from the given directory, or generates and persists a new self-signed
+    /// identity if there is none yet.
+    pub async fn load_or_generate(directory: &Path) -> Result<Self, AgentIdentityError> {
+        let certificate_path = directory.join(CERTIFICATE_FILE_NAME);
+        let private_key_path = directory.join(PRIVATE_KEY_FILE_NAME);
+
+        if tokio::fs::try_exists(&certificate_path).await?
+            && tokio::fs::try_exists(&private_key_path).await?
+        {
+            tracing::debug!("Loading TLS identity from {}", directory.display());
+
+            let certificate_pem = tokio::fs::read_to_string(&certificate_path).await?;
+            let private_key_pem = tokio::fs::read_to_string(&private_key_path).await?;
+
+            return Self::from_pem(certificate_pem, private_key_pem);
+        }
+
+        tracing::info!("Generating new self-signed TLS identity...");
+        let certificate =
+            rcgen::generate_simple_self_signed(vec![CERTIFICATE_SUBJECT_NAME.to_string()])?;
+        let identity = Self::from_pem(
+            certificate.serialize_pem()?,
+            certificate.serialize_private_key_pem(),
+        )?;
+
+        tokio::fs::create_dir_all(directory).await?;
+        Self::write_private(&private_key_path, &identity.private_key_pem).await?;
+        tokio::fs::write(&certificate_path, &identity.certificate_pem).await?;
+
+        tracing::info!("Persisted TLS identity to {}", certificate_path.display());
+
+        Ok(identity)
+    }
+
+    fn from_pem(
+        certificate_pem: String,
+        private_key_pem: String,
+    ) -> Result<Self, AgentIdentityError> {
+        let certificate_der = rustls_pemfile::certs(&mut certificate_pem.as_bytes())?
+            .into_iter()
+            .next()
+            .ok_or(AgentIdentityError::MissingCertificate)?;
+
+        let fingerprint = Sha256::digest(certificate_der)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        tracing::info!("TLS certificate fingerprint: sha256:{}", fingerprint);
+
+        Ok(Self {
+            certificate_pem,
+            private_key_pem,
+            fingerprint,
+        })
+    }
+
+    /// Writes a file which is only accessible by the owner.
+    async fn write_private(path: &Path, data: &str) -> Result<(), std::io::Error> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(path).await?;
+        file.write_all(data.as_bytes()).await?;
+        file.flush().await
+    }
+
+    /// The hex encoded SHA-256 fingerprint of the DER encoded certificate.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Creates the tonic server TLS configuration for this identity.
+    pub fn server_tls_config(&self) -> ServerTlsConfig {
+        ServerTlsConfig::new().identity(Identity::from_pem(
+            &self.certificate_pem,
+            &self.private_key_pem,
+        ))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AgentIdentityError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to generate certificate: {0}")]
+    Generate(#[from] rcgen::RcgenError),
+
+    #[error("certificate file does not contain a certificate")]
+    MissingCertificate,
+}
@@ -0,0 +1,181 @@
+use crate::metrics::METRICS;
+use crate::pal::power::{PowerAction, PowerManager};
+use crate::pal::{PlatformAbstraction, PlatformAbstractionError};
+use crate::util::{random_hex, unix_time_now};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How many actions are remembered, older finished actions are forgotten.
+const MAX_TRACKED_ACTIONS: usize = 64;
+
+/// State of an accepted power action.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ActionState {
+    /// The action has been accepted, but not started yet
+    Pending,
+
+    /// The action has been handed to the platform
+    Executing,
+
+    /// The platform refused or failed to perform the action
+    Failed,
+
+    /// The platform accepted the action, this is as far as the agent can observe it
+    Completed,
+}
+
+impl ActionState {
+    /// Tests whether the state will not change anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Failed | Self::Completed)
+    }
+}
+
+/// Status of an accepted power action.
+#[derive(Debug, Clone)]
+pub struct ActionStatus {
+    /// Identifier of the action.
+    pub id: String,
+
+    /// The power action.
+    pub action: PowerAction,
+
+    /// The current state of the action.
+    pub state: ActionState,
+
+    /// The platform error if the action failed.
+    pub error: Option<String>,
+
+    /// Time of the last state change, in seconds since the unix epoch.
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct TrackedActions {
+    statuses: HashMap<String, Arc<watch::Sender<ActionStatus>>>,
+    order: VecDeque<String>,
+}
+
+/// Executes power actions and keeps track of their outcome.
+#[derive(Debug)]
+pub struct ActionTracker {
+    pal: Arc<PlatformAbstraction>,
+    delay: Duration,
+    actions: Mutex<TrackedActions>,
+}
+
+impl ActionTracker {
+    /// Creates a new tracker.
+    ///
+    /// Accepted actions are executed after the given delay, so the RPC response has a chance to
+    /// reach the client before the system goes down.
+    pub fn new(pal: Arc<PlatformAbstraction>, delay: Duration) -> Self {
+        Self {
+            pal,
+            delay,
+            actions: Mutex::new(TrackedActions::default()),
+        }
+    }
+
+    /// Accepts a power action for execution.
+    ///
+    /// Returns the identifier of the action, which can be used to query its status.
+    pub fn submit(&self, action: PowerAction) -> String {
+        let id = random_hex(8);
+        let (sender, _) = watch::channel(ActionStatus {
+            id: id.clone(),
+            action,
+            state: ActionState::Pending,
+            error: None,
+            updated_at: unix_time_now(),
+        });
+        let sender = Arc::new(sender);
+
+        {
+            let mut actions = self.actions.lock().expect("Poisoned tracked actions lock");
+            actions.statuses.insert(id.clone(), sender.clone());
+            actions.order.push_back(id.clone());
+            Self::evict(&mut actions);
+        }
+
+        tracing::debug!("Accepted power action {:?} as {}", action, id);
+        METRICS.power_actions_requested.inc(&[action.name()]);
+        tokio::spawn(Self::execute(self.pal.clone(), self.delay, sender));
+
+        id
+    }
+
+    /// Retrieves the current status of an action.
+    pub fn status(&self, id: &str) -> Option<ActionStatus> {
+        self.watch(id).map(|receiver| receiver.borrow().clone())
+    }
+
+    /// Subscribes to status changes of an action.
+    pub fn watch(&self, id: &str) -> Option<watch::Receiver<ActionStatus>> {
+        self.actions
+            .lock()
+            .expect("Poisoned tracked actions lock")
+            .statuses
+            .get(id)
+            .map(|sender| sender.subscribe())
+    }
+
+    /// Forgets the oldest finished actions once there are too many.
+    fn evict(actions: &mut TrackedActions) {
+        while actions.order.len() > MAX_TRACKED_ACTIONS {
+            let Some(index) = actions
+                .order
+                .iter()
+                .position(|id| actions.statuses[id].borrow().state.is_final())
+            else {
+                // Everything is still in flight, nothing to forget
+                break;
+            };
+
+            let id = actions.order.remove(index).unwrap();
+            actions.statuses.remove(&id);
+        }
+    }
+
+    async fn execute(
+        pal: Arc<PlatformAbstraction>,
+        delay: Duration,
+        sender: Arc<watch::Sender<ActionStatus>>,
+    ) {
+        tokio::time::sleep(delay).await;
+
+        let action = sender.borrow().action;
+        Self::update(&sender, ActionState::Executing, None);
+
+        let res = match pal.power_manager() {
+            None => Err(PlatformAbstractionError::Unsupported),
+            Some(power) => power.perform_power_action(action).await,
+        };
+
+        match res {
+            Ok(()) => {
+                METRICS
+                    .power_actions_executed
+                    .inc(&[action.name(), "completed"]);
+                Self::update(&sender, ActionState::Completed, None);
+            }
+            Err(err) => {
+                tracing::error!("Failed to perform power action {:?}: {}", action, err);
+                METRICS
+                    .power_actions_executed
+                    .inc(&[action.name(), "failed"]);
+                Self::update(&sender, ActionState::Failed, Some(err.to_string()));
+            }
+        }
+    }
+
+    fn update(sender: &watch::Sender<ActionStatus>, state: ActionState, error: Option<String>) {
+        sender.send_modify(|status| {
+            status.state = state;
+            status.error = error;
+            status.updated_at = unix_time_now();
+        });
+    }
+}
@@ -0,0 +1,69 @@
+use rand::Rng;
+use std::time::{Duration, SystemTime};
+
+/// Generates a random hex string from the given number of random bytes.
+pub fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect()
+}
+
+/// Generates a random (version 4) UUID in its hyphenated form.
+pub fn random_uuid() -> String {
+    let mut bytes = rand::thread_rng().gen::<[u8; 16]>();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Retrieves the current time in seconds since the unix epoch.
+pub fn unix_time_now() -> u64 {
+    to_unix_time(SystemTime::now())
+}
+
+/// Converts a system time to seconds since the unix epoch.
+pub fn to_unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Converts seconds since the unix epoch to a system time.
+pub fn from_unix_time(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+/// A uniquely named directory in the temporary directory, which is deleted when dropped.
+#[cfg(test)]
+pub struct TestDirectory(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDirectory {
+    pub fn new() -> Self {
+        Self(std::env::temp_dir().join(format!("dragon-claw-agent-test-{}", random_hex(8))))
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::pal::discovery::{DiscoveryManager, MacAddress, NetworkInterface};
+use crate::pal::{PlatformAbstraction, PlatformAbstractionError};
+use crate::storage::{JsonFile, StorageError};
+use crate::util::{random_hex, unix_time_now};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::RwLock;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+
+const KNOWN_PEERS_FILE_NAME: &str = "known-peers.json";
+
+/// The port magic packets are sent to, the discard service.
+const WAKE_ON_LAN_PORT: u16 = 9;
+
+/// Where to send a magic packet to.
+#[derive(Debug, Clone)]
+pub struct WakeTarget {
+    /// Hardware address of the machine to wake.
+    pub mac_address: MacAddress,
+
+    /// Name of the local interface to send the packet on, all interfaces if not set.
+    pub interface: Option<String>,
+
+    /// IPv4 broadcast address to send the packet to, the limited broadcast address if not set.
+    pub broadcast_address: Option<Ipv4Addr>,
+}
+
+/// A machine which can be woken by the agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownPeer {
+    /// Identifier of the peer.
+    pub id: String,
+
+    /// Human readable name of the peer.
+    pub name: String,
+
+    /// Hardware address of the peer.
+    pub mac_address: MacAddress,
+
+    /// Name of the local interface the peer is reachable through.
+    pub interface: Option<String>,
+
+    /// IPv4 broadcast address of the segment the peer lives on.
+    pub broadcast_address: Option<Ipv4Addr>,
+
+    /// Time the peer has been added at, in seconds since the unix epoch.
+    pub added_at: u64,
+}
+
+impl KnownPeer {
+    /// Retrieves the target magic packets for this peer are sent to.
+    pub fn wake_target(&self) -> WakeTarget {
+        WakeTarget {
+            mac_address: self.mac_address,
+            interface: self.interface.clone(),
+            broadcast_address: self.broadcast_address,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KnownPeersDocument {
+    peers: Vec<KnownPeer>,
+}
+
+/// Keeps track of machines the agent can wake on behalf of clients.
+#[derive(Debug)]
+pub struct PeerRegistry {
+    storage: JsonFile,
+    peers: RwLock<Vec<KnownPeer>>,
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+impl PeerRegistry {
+    /// Loads the known peers from the given data directory.
+    pub async fn load(data_directory: &Path) -> Result<Self, WakeOnLanError> {
+        let storage = JsonFile::new(data_directory, KNOWN_PEERS_FILE_NAME);
+        let document = storage.load::<KnownPeersDocument>().await?;
+
+        tracing::debug!("Loaded {} known peers", document.peers.len());
+
+        Ok(Self {
+            storage,
+            peers: RwLock::new(document.peers),
+            persist_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Adds a peer, replacing a known peer with the same hardware address.
+    pub async fn add(
+        &self,
+        name: String,
+        mac_address: MacAddress,
+        interface: Option<String>,
+        broadcast_address: Option<Ipv4Addr>,
+    ) -> Result<KnownPeer, WakeOnLanError> {
+        let peer = KnownPeer {
+            id: random_hex(8),
+            name,
+            mac_address,
+            interface,
+            broadcast_address,
+            added_at: unix_time_now(),
+        };
+
+        {
+            let mut peers = self.peers.write().expect("Poisoned known peers lock");
+            peers.retain(|p| p.mac_address != mac_address);
+            peers.push(peer.clone());
+        }
+        self.persist().await?;
+
+        tracing::info!(
+            "Added known peer \"{}\" ({}) as {}",
+            peer.name,
+            peer.mac_address,
+            peer.id
+        );
+
+        Ok(peer)
+    }
+
+    /// Looks up a known peer.
+    pub fn get(&self, id: &str) -> Option<KnownPeer> {
+        self.peers
+            .read()
+            .expect("Poisoned known peers lock")
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+    }
+
+    /// Retrieves all known peers.
+    pub fn peers(&self) -> Vec<KnownPeer> {
+        self.peers
+            .read()
+            .expect("Poisoned known peers lock")
+            .clone()
+    }
+
+    /// Forgets a known peer.
+    pub async fn remove(&self, id: &str) -> Result<(), WakeOnLanError> {
+        {
+            let mut peers = self.peers.write().expect("Poisoned known peers lock");
+            let len_before = peers.len();
+            peers.retain(|p| p.id != id);
+
+            if peers.len() == len_before {
+                return Err(WakeOnLanError::UnknownPeer);
+            }
+        }
+
+        self.persist().await?;
+        tracing::info!("Removed known peer {}", id);
+
+        Ok(())
+    }
+
+    async fn persist(&self) -> Result<(), WakeOnLanError> {
+        // Hold the lock while taking the snapshot, so writes can't overtake each other
+        let _guard = self.persist_lock.lock().await;
+        let document = KnownPeersDocument {
+            peers: self.peers(),
+        };
+
+        self.storage.store(&document).await?;
+        Ok(())
+    }
+}
+
+/// Builds a magic packet: 6 times `0xFF` followed by the hardware address repeated 16 times.
+fn magic_packet(mac_address: MacAddress) -> [u8; 102] {
+    let mut packet = [0xFF; 102];
+    for chunk in packet[6..].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&mac_address.0);
+    }
+
+    packet
+}
+
+/// Sends a magic packet to wake the given target.
+///
+/// Without an interface, the packet is sent on every interface with an IPv4 address, unless a
+/// broadcast address is given, which then selects the interface by routing.
+pub async fn send_magic_packet(
+    pal: &PlatformAbstraction,
+    target: &WakeTarget,
+) -> Result<(), WakeOnLanError> {
+    let packet = magic_packet(target.mac_address);
+    let destination = SocketAddr::new(
+        IpAddr::V4(target.broadcast_address.unwrap_or(Ipv4Addr::BROADCAST)),
+        WAKE_ON_LAN_PORT,
+    );
+
+    let interfaces = match (&target.interface, target.broadcast_address) {
+        (None, Some(_)) => {
+            // Let the routing table decide
+            let socket = bind_broadcast_socket(None)?;
+            socket.send_to(&packet, destination).await?;
+
+            tracing::info!(
+                "Sent magic packet for {} to {}",
+                target.mac_address,
+                destination
+            );
+            return Ok(());
+        }
+        (Some(name), _) => {
+            let interface = pal
+                .discovery_manager()
+                .network_interfaces()?
+                .into_iter()
+                .find(|i| &i.name == name)
+                .ok_or(WakeOnLanError::UnknownInterface)?;
+
+            if ipv4_address_of(&interface).is_none() {
+                return Err(WakeOnLanError::NoIpv4Address);
+            }
+
+            vec![interface]
+        }
+        (None, None) => pal
+            .discovery_manager()
+            .network_interfaces()?
+            .into_iter()
+            .filter(|i| ipv4_address_of(i).is_some())
+            .collect(),
+    };
+
+    if interfaces.is_empty() {
+        return Err(WakeOnLanError::NoIpv4Address);
+    }
+
+    // Succeed if the packet made it out on at least one interface
+    let mut error = None;
+    let mut sent = false;
+    for interface in &interfaces {
+        let res = async {
+            let socket = bind_broadcast_socket(Some(interface))?;
+            socket.send_to(&packet, destination).await
+        }
+        .await;
+
+        match res {
+            Ok(_) => {
+                tracing::info!(
+                    "Sent magic packet for {} to {} on {}",
+                    target.mac_address,
+                    destination,
+                    interface.name
+                );
+                sent = true;
+            }
+            Err(err) => {
+                tracing::warn!("Failed to send magic packet on {}: {}", interface.name, err);
+                error = Some(err);
+            }
+        }
+    }
+
+    match (sent, error) {
+        (false, Some(err)) => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn ipv4_address_of(interface: &NetworkInterface) -> Option<Ipv4Addr> {
+    interface.addresses.iter().find_map(|a| match a {
+        IpAddr::V4(v) => Some(*v),
+        IpAddr::V6(_) => None,
+    })
+}
+
+/// Creates a UDP socket which may send broadcasts, optionally bound to the given interface.
+fn bind_broadcast_socket(
+    interface: Option<&NetworkInterface>,
+) -> Result<UdpSocket, std::io::Error> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+
+    let local_address = match interface {
+        None => Ipv4Addr::UNSPECIFIED,
+        Some(interface) => {
+            // Binding to the address is not enough for the limited broadcast address on Linux,
+            // the packet would leave through the interface of the default route
+            #[cfg(target_os = "linux")]
+            socket.bind_device(Some(interface.name.as_bytes()))?;
+
+            ipv4_address_of(interface).unwrap_or(Ipv4Addr::UNSPECIFIED)
+        }
+    };
+    socket.bind(&SocketAddr::new(IpAddr::V4(local_address), 0).into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+#[derive(Debug, Error)]
+pub enum WakeOnLanError {
+    #[error("failed to persist known peers: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Platform(#[from] PlatformAbstractionError),
+
+    #[error("failed to send magic packet: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("unknown peer")]
+    UnknownPeer,
+
+    #[error("unknown network interface")]
+    UnknownInterface,
+
+    #[error("no network interface with an IPv4 address to send the magic packet on")]
+    NoIpv4Address,
+}
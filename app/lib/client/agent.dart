@@ -6,19 +6,50 @@ class KnownAgent {
   final InternetAddress address;
   final int port;
 
+  /// The hex encoded SHA-256 fingerprint of the TLS certificate of the agent,
+  /// if known.
+  ///
+  /// Agents publish it alongside the discovery data. If it is not known, the
+  /// certificate presented on first contact is accepted.
+  final String? fingerprint;
+
   /// If [true], the agent was automatically discovered using network discovery.
   /// Otherwise it was manually added.
   final bool discovered;
 
-  KnownAgent.discovered(this.name, this.address, this.port) : discovered = true;
+  KnownAgent.discovered(this.name, this.address, this.port, {this.fingerprint})
+      : discovered = true;
+
+  KnownAgent.manual(this.name, this.address, this.port, {this.fingerprint})
+      : discovered = false;
+
+  KnownAgent toManual() =>
+      KnownAgent.manual(name, address, port, fingerprint: fingerprint);
 
-  KnownAgent.manual(this.name, this.address, this.port) : discovered = false;
+  /// Parses a certificate fingerprint as published by agents, such as
+  /// `sha256:0123...`.
+  ///
+  /// The `sha256:` prefix is optional and colons between the bytes are
+  /// ignored. Returns the lower case hex digest, or [null] if the value is not
+  /// a SHA-256 fingerprint.
+  static String? parseFingerprint(String value) {
+    var digest = value.trim().toLowerCase();
+    if (digest.startsWith("sha256:")) {
+      digest = digest.substring("sha256:".length);
+    }
+    digest = digest.replaceAll(":", "");
 
-  KnownAgent toManual() => KnownAgent.manual(name, address, port);
+    if (!RegExp(r"^[0-9a-f]{64}$").hasMatch(digest)) {
+      return null;
+    }
+
+    return digest;
+  }
 
   @override
   String toString() {
-    return 'DiscoveredAgent{name: $name, address: $address, port: $port}';
+    return 'DiscoveredAgent{name: $name, address: $address, port: $port, '
+        'fingerprint: $fingerprint}';
   }
 
   @override
@@ -1,29 +1,126 @@
 import 'dart:io';
 
+import 'package:crypto/crypto.dart';
+import 'package:dragon_claw/client/credential_store.dart';
 import 'package:dragon_claw/generated/google/protobuf/empty.pb.dart' as rpc;
 import 'package:dragon_claw/generated/service.pbgrpc.dart' as rpc;
 import 'package:flutter/material.dart';
 import 'package:grpc/grpc.dart';
+import 'package:logging/logging.dart';
+
+final _log = Logger("client");
 
 /// The version of the agent.
 typedef AgentVersion = rpc.AgentVersion;
 
+/// A pending pairing, completed with the PIN the agent logged.
+typedef PairingChallenge = rpc.PairingChallenge;
+
 /// Client to connect to the DragonClaw Agent
 class DragonClawAgentClient {
   final InternetAddress address;
   final int port;
 
-  final rpc.DragonClawAgentClient _client;
+  /// The fingerprint the certificate of the agent has to match, see
+  /// [KnownAgent.fingerprint].
+  final String? expectedFingerprint;
+
+  /// The credentials of the agents the app has been paired with.
+  final AgentCredentialStore _credentials;
+
+  late final rpc.DragonClawAgentClient _client;
+
+  /// The fingerprint of the certificate the agent presented, once connected.
+  String? get fingerprint => _fingerprint;
+  String? _fingerprint;
 
   /// Creates the client and connects the RPC
-  DragonClawAgentClient(this.address, this.port)
-      : _client = rpc.DragonClawAgentClient(
-          ClientChannel(address,
-              port: port,
-              options: const ChannelOptions(
-                credentials: ChannelCredentials.insecure(),
-              )),
-        );
+  DragonClawAgentClient(
+    this.address,
+    this.port,
+    this._credentials, {
+    this.expectedFingerprint,
+  }) {
+    _client = rpc.DragonClawAgentClient(
+      ClientChannel(address,
+          port: port,
+          options: ChannelOptions(
+            credentials: ChannelCredentials.secure(
+              onBadCertificate: _checkCertificate,
+            ),
+          )),
+      options: CallOptions(providers: [_authorize]),
+    );
+  }
+
+  /// Pins the self-signed certificate of the agent.
+  ///
+  /// Agents don't have a certificate a client could validate, so this is
+  /// called for every connection.
+  bool _checkCertificate(X509Certificate certificate, String host) {
+    final fingerprint = sha256.convert(certificate.der).toString();
+
+    if (expectedFingerprint != null && fingerprint != expectedFingerprint) {
+      _log.warning(
+        "Agent at $address:$port presented certificate $fingerprint, "
+        "expected $expectedFingerprint",
+      );
+      return false;
+    }
+
+    _fingerprint = fingerprint;
+    return true;
+  }
+
+  /// Adds the access token issued by the agent to a call.
+  ///
+  /// This runs once the connection has been established, so the agent has
+  /// been identified by its certificate already.
+  Future<void> _authorize(Map<String, String> metadata, String uri) async {
+    final fingerprint = _fingerprint;
+    if (fingerprint == null) {
+      return;
+    }
+
+    final credentials = await _credentials.get(fingerprint);
+    if (credentials != null) {
+      metadata["authorization"] = "Bearer ${credentials.accessToken}";
+    }
+  }
+
+  /// Starts pairing with the agent, which logs a PIN to pass to
+  /// [completePairing].
+  Future<PairingChallenge> requestPairing(String deviceName) async {
+    return await _client
+        .requestPairing(rpc.PairingRequest()..deviceName = deviceName);
+  }
+
+  /// Completes a pending pairing, the issued access token is sent with all
+  /// further calls.
+  Future<void> completePairing(String pairingId, String pin) async {
+    final result = await _client.completePairing(rpc.CompletePairingRequest()
+      ..pairingId = pairingId
+      ..pin = pin);
+
+    // A call has been made, so the certificate has been checked already
+    final fingerprint = _fingerprint;
+    if (fingerprint == null) {
+      throw StateError("Paired without checking the agent certificate");
+    }
+
+    await _credentials.put(
+      fingerprint,
+      AgentCredentials(result.deviceId, result.accessToken),
+    );
+  }
+
+  /// Forgets the access token, for example after the agent revoked it.
+  Future<void> forgetCredentials() async {
+    final fingerprint = _fingerprint;
+    if (fingerprint != null) {
+      await _credentials.remove(fingerprint);
+    }
+  }
 
   /// Query the agent for the supported power actions.
   Future<Set<PowerAction>> getSupportedPowerActions() async {
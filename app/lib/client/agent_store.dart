@@ -51,14 +51,24 @@ class KnownAgentStore with ChangeNotifier {
       final name = item["name"];
       final address = item["address"];
       final port = item["port"];
+      final fingerprint = item["fingerprint"];
 
       if (name is! String || address is! String || port is! int) {
         throw const FormatException(
             "Expected JSON to be a list of maps with name, address and port");
       }
 
+      if (fingerprint is! String?) {
+        throw const FormatException("Expected fingerprint to be a string");
+      }
+
       // Add the agent to the list
-      knownAgents.add(KnownAgent.manual(name, InternetAddress(address), port));
+      knownAgents.add(KnownAgent.manual(
+        name,
+        InternetAddress(address),
+        port,
+        fingerprint: fingerprint,
+      ));
     }
 
     _knownAgents = knownAgents;
@@ -76,6 +86,7 @@ class KnownAgentStore with ChangeNotifier {
               "name": e.name,
               "address": e.address.address,
               "port": e.port,
+              if (e.fingerprint != null) "fingerprint": e.fingerprint,
             })
         .toList(growable: false);
 
@@ -0,0 +1,112 @@
+import 'dart:convert' as convert;
+import 'dart:io';
+
+import 'package:path_provider/path_provider.dart' as path_provider;
+
+/// Credentials the app has been issued by an agent when pairing with it.
+class AgentCredentials {
+  /// Identifier the agent assigned to this device.
+  final String deviceId;
+
+  /// Access token sent as bearer token with every call.
+  final String accessToken;
+
+  const AgentCredentials(this.deviceId, this.accessToken);
+}
+
+/// Stores the credentials of paired agents by the fingerprint of their
+/// certificate.
+///
+/// This way credentials are only ever sent to the agent which issued them,
+/// no matter under which address it is reached.
+class AgentCredentialStore {
+  late final Future<File> _credentialsFile;
+
+  Map<String, AgentCredentials>? _credentials;
+
+  static Future<Directory> _getStorageDirectory() {
+    return path_provider.getApplicationDocumentsDirectory();
+  }
+
+  /// Asynchronously set's up the store.
+  AgentCredentialStore() {
+    _credentialsFile = _getStorageDirectory().then((value) {
+      return File("${value.path}/agent_credentials.json");
+    });
+  }
+
+  /// Retrieves the credentials for the agent with the given certificate
+  /// fingerprint.
+  Future<AgentCredentials?> get(String fingerprint) async {
+    final credentials = await _load();
+    return credentials[fingerprint];
+  }
+
+  /// Stores the credentials for the agent with the given certificate
+  /// fingerprint.
+  Future<void> put(String fingerprint, AgentCredentials credentials) async {
+    final allCredentials = await _load();
+    allCredentials[fingerprint] = credentials;
+    await _save(allCredentials);
+  }
+
+  /// Forgets the credentials for the agent with the given certificate
+  /// fingerprint.
+  Future<void> remove(String fingerprint) async {
+    final allCredentials = await _load();
+    if (allCredentials.remove(fingerprint) != null) {
+      await _save(allCredentials);
+    }
+  }
+
+  /// Loads the credentials from storage.
+  Future<Map<String, AgentCredentials>> _load() async {
+    if (_credentials != null) {
+      return _credentials!;
+    }
+
+    final file = await _credentialsFile;
+    if (!await file.exists()) {
+      return _credentials = {};
+    }
+
+    final contents = await file.readAsString();
+    final json = convert.jsonDecode(contents);
+
+    if (json is! Map) {
+      throw const FormatException("Expected JSON to be a map");
+    }
+
+    final credentials = <String, AgentCredentials>{};
+    for (final entry in json.entries) {
+      final fingerprint = entry.key;
+      final item = entry.value;
+      if (fingerprint is! String || item is! Map) {
+        throw const FormatException("Expected JSON to be a map of maps");
+      }
+
+      final deviceId = item["device_id"];
+      final accessToken = item["access_token"];
+
+      if (deviceId is! String || accessToken is! String) {
+        throw const FormatException(
+            "Expected JSON to be a map of maps with device_id and access_token");
+      }
+
+      credentials[fingerprint] = AgentCredentials(deviceId, accessToken);
+    }
+
+    return _credentials = credentials;
+  }
+
+  /// Saves the credentials to storage.
+  Future<void> _save(Map<String, AgentCredentials> credentials) async {
+    final file = await _credentialsFile;
+    final json = credentials.map((fingerprint, e) => MapEntry(fingerprint, {
+          "device_id": e.deviceId,
+          "access_token": e.accessToken,
+        }));
+
+    await file.writeAsString(convert.jsonEncode(json));
+  }
+}
@@ -24,6 +24,7 @@ class _AddDeviceDialogState extends State<AddDeviceDialog> {
   final _nameController = TextEditingController();
   final _addressController = TextEditingController();
   final _portController = TextEditingController(text: "37121");
+  final _fingerprintController = TextEditingController();
 
   @override
   void initState() {
@@ -124,6 +125,28 @@ class _AddDeviceDialogState extends State<AddDeviceDialog> {
                   return "Please enter a valid port";
                 }
 
+                return null;
+              },
+            ),
+            const SizedBox(height: 16),
+            TextFormField(
+              controller: _fingerprintController,
+              decoration: const InputDecoration(
+                border: OutlineInputBorder(),
+                labelText: "Certificate fingerprint (optional)",
+                helperText: "Logged by the agent when it starts",
+                helperMaxLines: 2,
+              ),
+              validator: (value) {
+                if (value == null || value.isEmpty) {
+                  // The certificate presented first is trusted
+                  return null;
+                }
+
+                if (KnownAgent.parseFingerprint(value) == null) {
+                  return "Please enter a valid SHA-256 fingerprint";
+                }
+
                 return null;
               },
             ),
@@ -138,8 +161,15 @@ class _AddDeviceDialogState extends State<AddDeviceDialog> {
       final name = _nameController.text;
       final address = _addressController.text;
       final port = int.parse(_portController.text);
-
-      final agent = KnownAgent.manual(name, InternetAddress(address), port);
+      final fingerprint =
+          KnownAgent.parseFingerprint(_fingerprintController.text);
+
+      final agent = KnownAgent.manual(
+        name,
+        InternetAddress(address),
+        port,
+        fingerprint: fingerprint,
+      );
 
       final newAgentList = List.of(_manualAgents!);
       newAgentList.add(agent);
@@ -0,0 +1,223 @@
+import 'package:dragon_claw/client/agent_client.dart';
+import 'package:flutter/material.dart';
+import 'package:grpc/grpc.dart';
+import 'package:logging/logging.dart';
+
+final _log = Logger("pairing");
+
+/// Dialog pairing the app with an agent, pops [true] once paired.
+class PairingDialog extends StatefulWidget {
+  final DragonClawAgentClient client;
+
+  const PairingDialog({super.key, required this.client});
+
+  @override
+  State<StatefulWidget> createState() => _PairingDialogState();
+}
+
+class _PairingDialogState extends State<PairingDialog> {
+  final _formKey = GlobalKey<FormState>();
+
+  final _nameController = TextEditingController(text: "Dragon Claw App");
+  final _pinController = TextEditingController();
+
+  /// The pending pairing, once the agent has logged a PIN.
+  PairingChallenge? _challenge;
+  bool _busy = false;
+  String? _error;
+
+  @override
+  void dispose() {
+    _nameController.dispose();
+    _pinController.dispose();
+    super.dispose();
+  }
+
+  @override
+  Widget build(BuildContext context) => AlertDialog(
+        title: const Text("Pair with agent"),
+        content: Form(
+          key: _formKey,
+          child: _challenge == null
+              ? _buildNameStep(context)
+              : _buildPinStep(context),
+        ),
+        actions: [
+          TextButton(
+            onPressed: () => Navigator.of(context).pop(false),
+            child: const Text("Cancel"),
+          ),
+          TextButton(
+            onPressed: _busy
+                ? null
+                : (_challenge == null ? _requestPairing : _completePairing),
+            child: Text(_challenge == null ? "Request PIN" : "Pair"),
+          ),
+        ],
+      );
+
+  Widget _buildNameStep(BuildContext context) => Column(
+        mainAxisSize: MainAxisSize.min,
+        children: [
+          const Text(
+            "Only paired devices can control the agent. "
+            "Choose the name the agent lists this device under.",
+          ),
+          const SizedBox(height: 16),
+          TextFormField(
+            controller: _nameController,
+            decoration: const InputDecoration(
+              border: OutlineInputBorder(),
+              labelText: "Device name",
+            ),
+            validator: (value) {
+              if (value == null || value.trim().isEmpty) {
+                return "Please enter a name";
+              }
+
+              return null;
+            },
+          ),
+          ..._buildError(context),
+        ],
+      );
+
+  Widget _buildPinStep(BuildContext context) {
+    final fingerprint = widget.client.fingerprint;
+
+    return Column(
+      mainAxisSize: MainAxisSize.min,
+      children: [
+        Text(
+          "Enter the PIN the agent has written to its log. "
+          "It is valid for ${_challenge!.expiresInSeconds} seconds.",
+        ),
+        if (fingerprint != null) ...[
+          const SizedBox(height: 8),
+          Text(
+            "Make sure the agent logged the certificate fingerprint "
+            "sha256:$fingerprint.",
+            style: Theme.of(context).textTheme.bodySmall,
+          ),
+        ],
+        const SizedBox(height: 16),
+        TextFormField(
+          controller: _pinController,
+          keyboardType: TextInputType.number,
+          maxLength: 6,
+          decoration: const InputDecoration(
+            border: OutlineInputBorder(),
+            labelText: "PIN",
+          ),
+          validator: (value) {
+            if (value == null || !RegExp(r"^[0-9]{6}$").hasMatch(value)) {
+              return "Please enter the 6 digits of the PIN";
+            }
+
+            return null;
+          },
+        ),
+        ..._buildError(context),
+      ],
+    );
+  }
+
+  List<Widget> _buildError(BuildContext context) {
+    if (_error == null) {
+      return const [];
+    }
+
+    return [
+      const SizedBox(height: 16),
+      Text(
+        _error!,
+        style: TextStyle(color: Theme.of(context).colorScheme.error),
+      ),
+    ];
+  }
+
+  void _requestPairing() async {
+    if (!_formKey.currentState!.validate()) {
+      return;
+    }
+
+    setState(() {
+      _busy = true;
+      _error = null;
+    });
+
+    try {
+      final challenge =
+          await widget.client.requestPairing(_nameController.text.trim());
+
+      if (mounted) {
+        setState(() {
+          _challenge = challenge;
+        });
+      }
+    } catch (error, trace) {
+      _log.warning("Failed to request pairing", error, trace);
+      _setError(error);
+    } finally {
+      if (mounted) {
+        setState(() {
+          _busy = false;
+        });
+      }
+    }
+  }
+
+  void _completePairing() async {
+    if (!_formKey.currentState!.validate()) {
+      return;
+    }
+
+    setState(() {
+      _busy = true;
+      _error = null;
+    });
+
+    try {
+      await widget.client
+          .completePairing(_challenge!.pairingId, _pinController.text);
+
+      if (mounted) {
+        Navigator.of(context).pop(true);
+      }
+    } catch (error, trace) {
+      _log.warning("Failed to complete pairing", error, trace);
+
+      if (error is GrpcError && error.code == StatusCode.notFound) {
+        // The pairing expired or has been discarded after too many wrong PINs
+        if (mounted) {
+          setState(() {
+            _challenge = null;
+            _pinController.clear();
+          });
+        }
+      }
+
+      _setError(error);
+    } finally {
+      if (mounted) {
+        setState(() {
+          _busy = false;
+        });
+      }
+    }
+  }
+
+  void _setError(Object error) {
+    if (!mounted) {
+      return;
+    }
+
+    setState(() {
+      if (error is GrpcError) {
+        _error = error.message ?? error.toString();
+      } else {
+        _error = error.toString();
+      }
+    });
+  }
+}
@@ -1,4 +1,6 @@
+import 'dart:async';
 import 'dart:collection';
+import 'dart:convert';
 import 'dart:io';
 
 import 'package:dragon_claw/client/agent.dart';
@@ -16,6 +18,9 @@ class DragonClawAgentDiscovery with ChangeNotifier {
   final Set<KnownAgent> _discoveredAgents;
   late final SSDPDiscovery _ssdp;
 
+  /// Timers removing agents found via SSDP once their announcement expired.
+  final Map<KnownAgent, Timer> _ssdpExpiryTimers = {};
+
   /// Retrieves the current list of discovered agents.
   UnmodifiableListView<KnownAgent> get discoveredAgents =>
       UnmodifiableListView(_discoveredAgents);
@@ -69,7 +74,7 @@ class DragonClawAgentDiscovery with ChangeNotifier {
     });
   }
 
-  void _onSSDPMessage(SSDPStatus status, KnownAgent agent) {
+  void _onSSDPMessage(SSDPStatus status, KnownAgent agent, Duration? maxAge) {
     final bool alive;
     switch (status) {
       case SSDPStatus.alive:
@@ -81,6 +86,20 @@ class DragonClawAgentDiscovery with ChangeNotifier {
         break;
     }
 
+    // Agents which vanish without saying goodbye are forgotten once their
+    // last announcement or search response expired. Updates don't tell how
+    // long they are valid, so the previous announcement still counts.
+    if (!alive) {
+      _ssdpExpiryTimers.remove(agent)?.cancel();
+    } else if (maxAge != null) {
+      _ssdpExpiryTimers.remove(agent)?.cancel();
+      _ssdpExpiryTimers[agent] = Timer(maxAge, () {
+        _log.fine("SSDP announcement of $agent expired");
+        _ssdpExpiryTimers.remove(agent);
+        _onAgentChanged(false, agent);
+      });
+    }
+
     _onAgentChanged(alive, agent);
   }
 
@@ -98,6 +117,11 @@ class DragonClawAgentDiscovery with ChangeNotifier {
   void stop() async {
     _ssdp.stop();
 
+    for (final timer in _ssdpExpiryTimers.values) {
+      timer.cancel();
+    }
+    _ssdpExpiryTimers.clear();
+
     if (_mDNS == null) {
       _log.warning("Discovery not running, ignoring stop() call.");
       return;
@@ -130,10 +154,18 @@ class DragonClawAgentDiscovery with ChangeNotifier {
       return null;
     }
 
+    // The certificate fingerprint is published as "fingerprint=sha256:..."
+    final fingerprintEntry = service.txt?["fingerprint"];
+    final fingerprint = fingerprintEntry == null
+        ? null
+        : KnownAgent.parseFingerprint(
+            utf8.decode(fingerprintEntry, allowMalformed: true));
+
     return KnownAgent.discovered(
       service.name ?? "<unknown>",
       serviceAddress,
       service.port!,
+      fingerprint: fingerprint,
     );
   }
 }
@@ -10,8 +10,11 @@ import 'package:logging/logging.dart';
 final _logger = Logger("ssdp:discovery");
 
 /// Callback for when a new agent is discovered.
+///
+/// For alive agents, [maxAge] is how long the announcement stays valid if
+/// the agent told so.
 typedef SSDPDiscoveryCallback = void Function(
-    SSDPStatus status, KnownAgent agent);
+    SSDPStatus status, KnownAgent agent, Duration? maxAge);
 
 /// Status associated with an SSDP message.
 enum SSDPStatus {
@@ -40,6 +43,27 @@ class SSDPStandardHeaders {
 
   /// The unique service name
   static final SSDPHeaderName usn = SSDPHeaderName.fromString("USN");
+
+  /// How long the announcement stays valid
+  static final SSDPHeaderName cacheControl =
+      SSDPHeaderName.fromString("CACHE-CONTROL");
+}
+
+/// Vendor specific headers sent by Dragon Claw agents.
+class SSDPAgentHeaders {
+  const SSDPAgentHeaders._();
+
+  /// The location of the RPC server, LOCATION points to the device description
+  static final SSDPHeaderName rpc =
+      SSDPHeaderName.fromString("RPC.DRAGON-CLAW.JANRUPF.NET");
+
+  /// The friendly name of the agent
+  static final SSDPHeaderName name =
+      SSDPHeaderName.fromString("NAME.DRAGON-CLAW.JANRUPF.NET");
+
+  /// The fingerprint of the TLS certificate of the agent
+  static final SSDPHeaderName fingerprint =
+      SSDPHeaderName.fromString("FINGERPRINT.DRAGON-CLAW.JANRUPF.NET");
 }
 
 /// Header names used in SSDP messages.
@@ -179,13 +203,17 @@ class SSDPDiscovery {
       _socketSubscriptions.add(subscription);
     }
 
-    // Start all senders
+    // Start all senders, agents answer their searches on the same socket
     for (final socket in _sendSockets) {
       final sender = _SSDPSender(socket, serviceName);
+      final receiver = _SSDPReceiver(socket, _onSSDPMessage);
       _senders.add(sender);
 
       final subscription = socket.listen(
-        sender.onEvent,
+        (event) {
+          receiver.onEvent(event);
+          sender.onEvent(event);
+        },
         onError: sender.onError,
         cancelOnError: false,
       );
@@ -321,12 +349,11 @@ class SSDPDiscovery {
 
         final RawDatagramSocket senderSocket;
         try {
-          // Create a new socket for each interface
+          // Create a new socket for each interface, on a port of its own so
+          // that only the unicast responses to our searches arrive there
           senderSocket = await RawDatagramSocket.bind(
             isIPv4 ? InternetAddress.anyIPv4 : InternetAddress.anyIPv6,
-            ssdpMulticastPort,
-            reuseAddress: true,
-            reusePort: true,
+            0,
           );
         } catch (e, trace) {
           _logger.warning(
@@ -337,9 +364,8 @@ class SSDPDiscovery {
           continue;
         }
 
-        // Disable loopback, read events and set the multicast interface
+        // Disable loopback and set the multicast interface
         senderSocket.multicastLoopback = false;
-        senderSocket.readEventsEnabled = false;
 
         try {
           if (isIPv4) {
@@ -375,70 +401,138 @@ class SSDPDiscovery {
   }
 
   void _onSSDPMessage(
-    String httpMethod,
-    String uri,
-    String httpVersion,
+    List<String> startLine,
     Map<SSDPHeaderName, SSDPHeaderValue> headers,
   ) {
-    if (httpMethod == "NOTIFY" &&
-        uri == "*" &&
-        httpVersion == "HTTP/1.1" &&
-        headers[SSDPStandardHeaders.nt]?.value == serviceName) {
-      // SSDP NOTIFY message for our service
-      final location = headers[SSDPStandardHeaders.location]?.value;
-      final subtype = headers[SSDPStandardHeaders.nts]?.value;
-
-      if (location == null || subtype == null) {
+    if (startLine[0] == "NOTIFY" &&
+        startLine[1] == "*" &&
+        startLine[2] == "HTTP/1.1") {
+      _onNotify(headers);
+    } else if (startLine[0] == "HTTP/1.1" && startLine[1] == "200") {
+      _onSearchResponse(headers);
+    }
+  }
+
+  void _onNotify(Map<SSDPHeaderName, SSDPHeaderValue> headers) {
+    if (headers[SSDPStandardHeaders.nt]?.value != serviceName) {
+      // Another service or device of the agent, or not an agent at all
+      return;
+    }
+
+    final subtype = headers[SSDPStandardHeaders.nts]?.value;
+
+    final SSDPStatus status;
+    switch (subtype) {
+      case "ssdp:alive":
+      // The agent stays available, but under a new boot id
+      case "ssdp:update":
+        status = SSDPStatus.alive;
+        break;
+
+      case "ssdp:byebye":
+        status = SSDPStatus.byebye;
+        break;
+
+      case null:
         _logger.warning(
           "Received NOTIFY message for service $serviceName, but it is missing "
-          "the location or subtype header",
+          "the subtype header",
         );
         return;
-      }
 
-      final locationUri = Uri.tryParse(location);
-      if (locationUri == null) {
+      default:
         _logger.warning(
-          "Received NOTIFY message for service $serviceName, but the location "
-          "header is not a valid URI: $location",
+          "Received NOTIFY message for service $serviceName, but it has an "
+          "unknown subtype: $subtype",
         );
         return;
-      }
+    }
 
-      final SSDPStatus status;
-      switch (subtype) {
-        case "ssdp:alive":
-          status = SSDPStatus.alive;
-          break;
+    final agent = _agentFromHeaders("NOTIFY message", headers);
+    if (agent != null) {
+      callback(status, agent, _maxAgeFromHeaders(headers));
+    }
+  }
 
-        case "ssdp:byebye":
-          status = SSDPStatus.byebye;
-          break;
+  void _onSearchResponse(Map<SSDPHeaderName, SSDPHeaderValue> headers) {
+    if (headers[SSDPStandardHeaders.st]?.value != serviceName) {
+      // Agents only answer with the search target we asked for, so this is
+      // a response to someone else
+      return;
+    }
 
-        default:
-          _logger.warning(
-            "Received NOTIFY message for service $serviceName, but it has an "
-            "unknown subtype: $subtype",
-          );
-          return;
-      }
+    final agent = _agentFromHeaders("search response", headers);
+    if (agent != null) {
+      callback(SSDPStatus.alive, agent, _maxAgeFromHeaders(headers));
+    }
+  }
+
+  /// Constructs the agent announced by a NOTIFY message or search response.
+  KnownAgent? _agentFromHeaders(
+    String messageKind,
+    Map<SSDPHeaderName, SSDPHeaderValue> headers,
+  ) {
+    // Older agents only announce the RPC server as location
+    final location = headers[SSDPAgentHeaders.rpc]?.value ??
+        headers[SSDPStandardHeaders.location]?.value;
+
+    if (location == null) {
+      _logger.warning(
+        "Received $messageKind for service $serviceName, but it is missing "
+        "the location header",
+      );
+      return null;
+    }
+
+    final locationUri = Uri.tryParse(location);
+    if (locationUri == null) {
+      _logger.warning(
+        "Received $messageKind for service $serviceName, but the location "
+        "header is not a valid URI: $location",
+      );
+      return null;
+    }
 
-      // Construct the agent
-      final name =
-          headers[SSDPStandardHeaders.usn]?.value ?? "Dragon Claw Computer";
-      final agent = KnownAgent.discovered(
-          name, InternetAddress(locationUri.host), locationUri.port);
+    final name = headers[SSDPAgentHeaders.name]?.value ??
+        headers[SSDPStandardHeaders.usn]?.value ??
+        "Dragon Claw Computer";
+    final fingerprint = headers[SSDPAgentHeaders.fingerprint]?.value;
+
+    return KnownAgent.discovered(
+      name,
+      InternetAddress(locationUri.host),
+      locationUri.port,
+      fingerprint:
+          fingerprint == null ? null : KnownAgent.parseFingerprint(fingerprint),
+    );
+  }
 
-      // Notify the callback
-      callback(status, agent);
+  /// Extracts the max-age directive of the CACHE-CONTROL header.
+  Duration? _maxAgeFromHeaders(Map<SSDPHeaderName, SSDPHeaderValue> headers) {
+    final cacheControl = headers[SSDPStandardHeaders.cacheControl]?.value;
+    if (cacheControl == null) {
+      return null;
+    }
+
+    for (final directive in cacheControl.split(",")) {
+      final parts = directive.split("=");
+      if (parts.length == 2 && parts[0].trim().toLowerCase() == "max-age") {
+        final seconds = int.tryParse(parts[1].trim());
+        if (seconds != null && seconds > 0) {
+          return Duration(seconds: seconds);
+        }
+      }
     }
+
+    return null;
   }
 }
 
+/// Callback for a received message, the start line is split into its three
+/// parts: method, URI and version for requests, version, status code and
+/// reason phrase for responses.
 typedef _SSDPMessageReceivedCallback = void Function(
-  String httpMethod,
-  String uri,
-  String httpVersion,
+  List<String> startLine,
   Map<SSDPHeaderName, SSDPHeaderValue> headers,
 );
 
@@ -504,9 +598,7 @@ class _SSDPReceiver {
       // Remove the message from the buffer
       _buffer = _buffer!.sublist(messageEnd + _messageEnd.length);
 
-      String? httpMethod;
-      String? uri;
-      String? httpVersion;
+      List<String>? startLine;
       final headers = HashMap<SSDPHeaderName, SSDPHeaderValue>();
 
       // Split the message into lines
@@ -519,25 +611,30 @@ class _SSDPReceiver {
           continue;
         }
 
-        if (httpMethod == null) {
+        if (startLine == null) {
           String lineString;
           try {
             lineString = utf8.decode(line, allowMalformed: false);
           } on FormatException {
-            // Not a valid first line for a HTTP request, maybe we caught
-            // the middle of a request.
+            // Not a valid first line for a HTTP message, maybe we caught
+            // the middle of a message.
             continue;
           }
 
-          final parts = lineString.split(" ");
-          if (parts.length != 3) {
+          // The reason phrase of a response may contain spaces itself
+          final firstSpace = lineString.indexOf(" ");
+          final secondSpace =
+              firstSpace == -1 ? -1 : lineString.indexOf(" ", firstSpace + 1);
+          if (secondSpace == -1) {
             // Not valid
             continue;
           }
 
-          httpMethod = parts[0];
-          uri = parts[1];
-          httpVersion = parts[2];
+          startLine = [
+            lineString.substring(0, firstSpace),
+            lineString.substring(firstSpace + 1, secondSpace),
+            lineString.substring(secondSpace + 1),
+          ];
         } else {
           // Decode a header
           int indexOfColon = line.indexOf(':'.codeUnitAt(0));
@@ -553,8 +650,8 @@ class _SSDPReceiver {
         }
       }
 
-      if (httpMethod != null && uri != null && httpVersion != null) {
-        receivedCallback(httpMethod, uri, httpVersion, headers);
+      if (startLine != null) {
+        receivedCallback(startLine, headers);
       }
     }
   }
@@ -611,7 +708,8 @@ class _SSDPSender {
   final Uint8List _searchMessage;
   late final Timer _timer;
 
-  bool _timerExpired = false;
+  // Search right away, not only once the timer expired for the first time
+  bool _timerExpired = true;
   bool _writeReady = false;
 
   _SSDPSender(this.socket, this.serviceName)
@@ -1,3 +1,4 @@
+import 'package:dragon_claw/client/credential_store.dart';
 import 'package:dragon_claw/routing.dart';
 import 'package:dragon_claw/updater/updater.dart';
 import 'package:dynamic_color/dynamic_color.dart';
@@ -23,8 +24,11 @@ void main() {
     }
   });
 
-  runApp(Provider(
-    create: (context) => AppUpdater(),
+  runApp(MultiProvider(
+    providers: [
+      Provider(create: (context) => AppUpdater()),
+      Provider(create: (context) => AgentCredentialStore()),
+    ],
     child: const MyApp(),
   ));
 }
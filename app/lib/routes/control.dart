@@ -1,10 +1,13 @@
 import 'package:dragon_claw/client/agent.dart';
 import 'package:dragon_claw/client/agent_client.dart';
+import 'package:dragon_claw/client/credential_store.dart';
 import 'package:dragon_claw/components/action.dart';
 import 'package:dragon_claw/components/control/power_action_sheet.dart';
+import 'package:dragon_claw/components/pairing_dialog.dart';
 import 'package:flutter/material.dart';
 import 'package:grpc/grpc.dart';
 import 'package:logging/logging.dart';
+import 'package:provider/provider.dart';
 
 final _log = Logger("screen.control");
 
@@ -23,11 +26,7 @@ class _AvailableOptions {
 class ControlScreen extends StatefulWidget {
   final KnownAgent agent;
 
-  /// The client constructed from the agent
-  final DragonClawAgentClient _client;
-
-  ControlScreen({required this.agent, super.key})
-      : _client = DragonClawAgentClient(agent.address, agent.port);
+  const ControlScreen({required this.agent, super.key});
 
   @override
   State<ControlScreen> createState() => _ControlScreenState();
@@ -39,22 +38,29 @@ class _ControlScreenState extends State<ControlScreen> {
     ..minor = 0
     ..patch = 0;
 
+  /// The client constructed from the agent
+  late final DragonClawAgentClient _client;
+
   _AvailableOptions? _availableOptions;
   AgentVersion? _agentVersion;
 
+  /// Whether the agent rejected the app because it is not paired.
+  bool _needsPairing = false;
+
   @override
   void initState() {
     super.initState();
 
-    _AvailableOptions.load(widget._client).then((value) {
-      if (mounted) {
-        setState(() {
-          _availableOptions = value;
-        });
-      }
-    });
+    _client = DragonClawAgentClient(
+      widget.agent.address,
+      widget.agent.port,
+      Provider.of<AgentCredentialStore>(context, listen: false),
+      expectedFingerprint: widget.agent.fingerprint,
+    );
+
+    _loadOptions();
 
-    widget._client.getVersion().then((value) {
+    _client.getVersion().then((value) {
       if (mounted) {
         setState(() {
           _agentVersion = value;
@@ -82,9 +88,11 @@ class _ControlScreenState extends State<ControlScreen> {
         ),
         body: Theme(
           data: originalTheme,
-          child: _availableOptions == null
-              ? _buildLoading(context)
-              : _buildBody(context, _availableOptions!),
+          child: _needsPairing
+              ? _buildNotPaired(context)
+              : _availableOptions == null
+                  ? _buildLoading(context)
+                  : _buildBody(context, _availableOptions!),
         ),
         bottomSheet: _buildAgentVersionUpdateSheet(context),
       ),
@@ -96,10 +104,25 @@ class _ControlScreenState extends State<ControlScreen> {
         child: CircularProgressIndicator(),
       );
 
+  /// Build the widget content while the app is not paired with the agent.
+  Widget _buildNotPaired(BuildContext context) => Center(
+        child: Column(
+          mainAxisSize: MainAxisSize.min,
+          children: [
+            const Text("This device is not paired with the agent."),
+            const SizedBox(height: 16),
+            FilledButton(
+              onPressed: _pair,
+              child: const Text("Pair"),
+            ),
+          ],
+        ),
+      );
+
   /// Build the body of the widget with the available options.
   Widget _buildBody(BuildContext context, _AvailableOptions options) =>
       _ControlScreenContent(
-        client: widget._client,
+        client: _client,
         options: options,
       );
 
@@ -132,6 +155,49 @@ class _ControlScreenState extends State<ControlScreen> {
 
     return null;
   }
+
+  void _loadOptions() {
+    _AvailableOptions.load(_client).then((value) {
+      if (mounted) {
+        setState(() {
+          _availableOptions = value;
+        });
+      }
+    }, onError: _onLoadError);
+  }
+
+  void _onLoadError(Object error, StackTrace trace) async {
+    if (error is! GrpcError || error.code != StatusCode.unauthenticated) {
+      _log.severe("Failed to load the available options", error, trace);
+      return;
+    }
+
+    // Either never paired or the agent revoked the pairing
+    _log.info("Agent requires pairing: ${error.message}");
+    await _client.forgetCredentials();
+
+    if (mounted) {
+      setState(() {
+        _needsPairing = true;
+      });
+      _pair();
+    }
+  }
+
+  void _pair() async {
+    final paired = await showDialog<bool>(
+      context: context,
+      barrierDismissible: false,
+      builder: (context) => PairingDialog(client: _client),
+    );
+
+    if (paired == true && mounted) {
+      setState(() {
+        _needsPairing = false;
+      });
+      _loadOptions();
+    }
+  }
 }
 
 class _ControlScreenContent extends StatelessWidget {